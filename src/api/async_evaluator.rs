@@ -0,0 +1,1086 @@
+use std::{
+    collections::HashMap,
+    future::Future,
+    process::Stdio,
+    sync::{
+        Arc, Mutex,
+        atomic::{AtomicU64, Ordering},
+    },
+};
+
+use tokio::{
+    io::{AsyncRead, AsyncReadExt, AsyncWrite, AsyncWriteExt},
+    process::{Child, Command},
+    sync::{OnceCell, mpsc, oneshot},
+    task::JoinHandle,
+};
+
+use crate::{
+    api::{
+        command::{PklCommand, PklVersion, check_version, parse_version_output, required_versions},
+        evaluator::{EvaluatorOptions, module_uri},
+        logger::{LogMessage, PklLogger, default_log},
+        manager::{
+            decode_create_evaluator_response, decode_evaluate_response, decode_pkl_message,
+            message_field,
+        },
+        project::PklProject,
+        reader::{AsyncPklModuleReader, AsyncPklResourceReader, extract_field, parse_scheme},
+    },
+    context::Context,
+    decoder::{decode_module, decode_value},
+    error::{Error, Result},
+    internal::msgapi::{
+        PklMessage,
+        codes::{
+            CREATE_EVALUATOR_RESPONSE, EVALUATE_RESPONSE, LIST_MODULES_REQUEST,
+            LIST_RESOURCES_REQUEST, LOG_MESSAGE, READ_MODULE_REQUEST, READ_RESOURCE_REQUEST,
+        },
+        incoming::PklServerMessage,
+        outgoing::{
+            CloseEvaluator, CreateEvaluator, EvaluateRequest, ListModulesResponse,
+            ListResourcesResponse, ReadModuleResponse, ReadResourceResponse,
+        },
+    },
+    pkl::PklMod,
+    utils::macros::{_debug, _warn},
+    value::PklValue,
+};
+
+/// Owns a `pkl server` process running on tokio and creates [`AsyncEvaluator`]s on it.
+///
+/// The async counterpart of [`EvaluatorManager`](crate::api::EvaluatorManager).
+/// Messages from the server are read by a background task and routed to the request they answer,
+/// so any number of evaluations can be in flight on the same process at once.
+///
+/// The server process is stopped once the manager and all of its evaluators are dropped.
+///
+/// # Example
+/// ```no_run
+/// use rpkl::{EvaluatorOptions, api::AsyncEvaluatorManager};
+///
+/// # async fn run() -> Result<(), rpkl::Error> {
+/// let manager = AsyncEvaluatorManager::new()?;
+/// let evaluator = manager.new_evaluator(EvaluatorOptions::new()).await?;
+///
+/// let (a, b) = tokio::join!(
+///     evaluator.evaluate_module("a.pkl"),
+///     evaluator.evaluate_module("b.pkl"),
+/// );
+/// #    Ok(())
+/// # }
+/// ```
+pub struct AsyncEvaluatorManager {
+    server: Arc<AsyncPklServer>,
+}
+
+impl AsyncEvaluatorManager {
+    /// Start a new pkl server process, using `PKL_EXEC` or `pkl` from `PATH`.
+    ///
+    /// Must be called from within a tokio runtime.
+    /// # Errors
+    /// - Returns an error if the pkl process fails to start
+    pub fn new() -> Result<Self> {
+        Self::with_command(PklCommand::from_env())
+    }
+
+    /// Start a new pkl server process with the given executable.
+    ///
+    /// Must be called from within a tokio runtime.
+    /// # Errors
+    /// - Returns an error if the pkl process fails to start
+    pub fn with_command(command: PklCommand) -> Result<Self> {
+        Ok(AsyncEvaluatorManager {
+            server: Arc::new(AsyncPklServer::start(command)?),
+        })
+    }
+
+    /// The version of the pkl server, or `None` if it couldn't be determined
+    /// # Errors
+    /// - Returns an error if `pkl --version` fails to run
+    pub async fn pkl_version(&self) -> Result<Option<PklVersion>> {
+        self.server.version().await
+    }
+
+    /// Create a new evaluator on this manager's pkl server
+    ///
+    /// Readers and loggers have to be registered with the `async_` options,
+    /// e.g. [`EvaluatorOptions::add_async_resource_reader`].
+    /// # Errors
+    /// - Returns an error if the evaluator fails to be created, or if `options` contains blocking readers or loggers
+    pub fn new_evaluator(
+        &self,
+        options: EvaluatorOptions,
+    ) -> impl Future<Output = Result<AsyncEvaluator>> + Send + 'static {
+        AsyncEvaluator::create(self.server.clone(), options)
+    }
+}
+
+/// Readers and logger of an evaluator, used to answer the requests the server sends for it
+struct AsyncEvaluatorHandlers {
+    module_readers: Vec<Arc<dyn AsyncPklModuleReader>>,
+    resource_readers: Vec<Arc<dyn AsyncPklResourceReader>>,
+    logger: Option<Arc<dyn PklLogger + Send + Sync>>,
+}
+
+/// State shared between the evaluators and the tasks reading from and writing to the server
+struct Connection {
+    next_request_id: AtomicU64,
+    outgoing: mpsc::UnboundedSender<Vec<u8>>,
+    /// Requests waiting for a response, by request id
+    pending: Mutex<HashMap<i64, oneshot::Sender<PklServerMessage>>>,
+    /// Open evaluators, by evaluator id
+    evaluators: Mutex<HashMap<i64, Arc<AsyncEvaluatorHandlers>>>,
+}
+
+impl Connection {
+    fn next_request_id(&self) -> u64 {
+        self.next_request_id.fetch_add(1, Ordering::Relaxed)
+    }
+
+    fn send(&self, msg: Vec<u8>) -> Result<()> {
+        self.outgoing.send(msg).map_err(|_| Error::PklSend)
+    }
+
+    /// Sends a request, returning a guard that resolves to its response.
+    fn request(&self, request_id: u64, msg: Vec<u8>) -> Result<PendingRequest<'_>> {
+        // request ids are sent as unsigned and read back as signed integers
+        let request_id = request_id as i64;
+        let (sender, receiver) = oneshot::channel();
+        self.pending.lock().unwrap().insert(request_id, sender);
+
+        let pending = PendingRequest {
+            connection: self,
+            request_id,
+            receiver,
+            evaluator_id: None,
+            done: false,
+        };
+        self.send(msg)?;
+        Ok(pending)
+    }
+
+    fn is_open(&self, evaluator_id: i64) -> bool {
+        self.evaluators.lock().unwrap().contains_key(&evaluator_id)
+    }
+
+    /// Closes the evaluator if it's still open
+    fn close_evaluator(&self, evaluator_id: i64) {
+        if self
+            .evaluators
+            .lock()
+            .unwrap()
+            .remove(&evaluator_id)
+            .is_none()
+        {
+            return;
+        }
+
+        let sent = CloseEvaluator { evaluator_id }
+            .encode_msg()
+            .map_err(Error::from)
+            .and_then(|msg| self.send(msg));
+        if let Err(_e) = sent {
+            _warn!("failed to close evaluator {evaluator_id}: {_e}");
+        }
+    }
+
+    /// Routes a message from the server to the request or evaluator it belongs to
+    fn dispatch(self: &Arc<Self>, msg: PklServerMessage) -> Result<()> {
+        match msg.header {
+            EVALUATE_RESPONSE | CREATE_EVALUATOR_RESPONSE => {
+                let request_id = message_field(&msg, "requestId")?;
+                let sender = self.pending.lock().unwrap().remove(&request_id);
+                match sender {
+                    Some(sender) => {
+                        // the receiver is gone if the request was cancelled in the meantime
+                        let _ = sender.send(msg);
+                    }
+                    None if msg.header == CREATE_EVALUATOR_RESPONSE => {
+                        // nobody is waiting for this evaluator anymore
+                        if let Ok(evaluator_id) = decode_create_evaluator_response(&msg) {
+                            self.send(CloseEvaluator { evaluator_id }.encode_msg()?)?;
+                        }
+                    }
+                    None => {}
+                }
+            }
+            LOG_MESSAGE => {
+                let message =
+                    LogMessage::try_from(&msg).map_err(|e| Error::PklMalformedResponse {
+                        message: e.to_string(),
+                    })?;
+                match self.handlers(&msg)?.and_then(|h| h.logger.clone()) {
+                    Some(logger) => logger.log(&message),
+                    None => default_log(&message),
+                }
+            }
+            READ_RESOURCE_REQUEST
+            | READ_MODULE_REQUEST
+            | LIST_MODULES_REQUEST
+            | LIST_RESOURCES_REQUEST => {
+                let handlers = self.handlers(&msg)?;
+                let connection = self.clone();
+                // readers may take a while, so they're awaited without holding up other messages
+                tokio::spawn(async move {
+                    let response = handle_reader_request(handlers.as_deref(), &msg)
+                        .await
+                        .and_then(|response| connection.send(response));
+                    if let Err(_e) = response {
+                        _warn!("failed to answer reader request: {_e}");
+                    }
+                });
+            }
+            _ => {
+                return Err(Error::Message(format!(
+                    "unknown request from pkl server: 0x{:x}",
+                    msg.header
+                )));
+            }
+        }
+        Ok(())
+    }
+
+    fn handlers(&self, msg: &PklServerMessage) -> Result<Option<Arc<AsyncEvaluatorHandlers>>> {
+        let evaluator_id = message_field(msg, "evaluatorId")?;
+        let handlers = self.evaluators.lock().unwrap().get(&evaluator_id).cloned();
+        if handlers.is_none() {
+            _warn!("request for unknown evaluator: {evaluator_id}");
+        }
+        Ok(handlers)
+    }
+}
+
+/// A request waiting for its response.
+///
+/// Dropping it before the response arrives, e.g. when the evaluation future is cancelled,
+/// stops waiting for the response and closes the evaluator it was sent for,
+/// since pkl has no way to cancel a single evaluation.
+struct PendingRequest<'a> {
+    connection: &'a Connection,
+    request_id: i64,
+    receiver: oneshot::Receiver<PklServerMessage>,
+    evaluator_id: Option<i64>,
+    done: bool,
+}
+
+impl PendingRequest<'_> {
+    fn closes_evaluator(mut self, evaluator_id: i64) -> Self {
+        self.evaluator_id = Some(evaluator_id);
+        self
+    }
+
+    async fn response(mut self) -> Result<PklServerMessage> {
+        let response = (&mut self.receiver).await;
+        self.done = true;
+        response.map_err(|_| Error::PklRecv)
+    }
+}
+
+impl Drop for PendingRequest<'_> {
+    fn drop(&mut self) {
+        if self.done {
+            return;
+        }
+        self.connection
+            .pending
+            .lock()
+            .unwrap()
+            .remove(&self.request_id);
+        if let Some(evaluator_id) = self.evaluator_id {
+            self.connection.close_evaluator(evaluator_id);
+        }
+    }
+}
+
+/// Connection to a `pkl server` process running on tokio, shared by the evaluators created on it.
+struct AsyncPklServer {
+    connection: Arc<Connection>,
+    /// Used to run `pkl --version`, `None` when not talking to a real process
+    command: Option<PklCommand>,
+    version: OnceCell<Option<PklVersion>>,
+    tasks: [JoinHandle<()>; 2],
+    /// Killed when dropped
+    _child: Option<Child>,
+}
+
+impl AsyncPklServer {
+    fn start(command: PklCommand) -> Result<Self> {
+        let mut child = Command::from(command.command())
+            .arg("server")
+            .stdin(Stdio::piped())
+            .stdout(Stdio::piped())
+            .kill_on_drop(true)
+            .spawn()
+            .map_err(Error::PklProcessStart)?;
+        let stdin = child.stdin.take().context("failed to get stdin")?;
+        let stdout = child.stdout.take().context("failed to get stdout")?;
+
+        let mut server = AsyncPklServer::new(stdin, stdout);
+        server._child = Some(child);
+        server.command = Some(command);
+        Ok(server)
+    }
+
+    fn new(
+        stdin: impl AsyncWrite + Unpin + Send + 'static,
+        stdout: impl AsyncRead + Unpin + Send + 'static,
+    ) -> Self {
+        let (outgoing, receiver) = mpsc::unbounded_channel();
+        let connection = Arc::new(Connection {
+            next_request_id: AtomicU64::new(1),
+            outgoing,
+            pending: Mutex::new(HashMap::new()),
+            evaluators: Mutex::new(HashMap::new()),
+        });
+
+        AsyncPklServer {
+            tasks: [
+                tokio::spawn(write_messages(receiver, stdin)),
+                tokio::spawn(read_messages(connection.clone(), stdout)),
+            ],
+            connection,
+            command: None,
+            version: OnceCell::new(),
+            _child: None,
+        }
+    }
+
+    /// Runs `pkl --version` the first time it's needed
+    async fn version(&self) -> Result<Option<PklVersion>> {
+        let Some(command) = &self.command else {
+            return Ok(None);
+        };
+        let version = self
+            .version
+            .get_or_try_init(|| async {
+                let output = Command::from(command.command())
+                    .arg("--version")
+                    .output()
+                    .await
+                    .map_err(Error::PklProcessStart)?;
+                Ok::<_, Error>(parse_version_output(&output.stdout))
+            })
+            .await?;
+        Ok(*version)
+    }
+}
+
+impl Drop for AsyncPklServer {
+    fn drop(&mut self) {
+        for task in &self.tasks {
+            task.abort();
+        }
+    }
+}
+
+async fn write_messages(
+    mut receiver: mpsc::UnboundedReceiver<Vec<u8>>,
+    mut stdin: impl AsyncWrite + Unpin,
+) {
+    while let Some(msg) = receiver.recv().await {
+        if let Err(_e) = async {
+            stdin.write_all(&msg).await?;
+            stdin.flush().await
+        }
+        .await
+        {
+            _warn!("failed to write to pkl server: {_e}");
+            break;
+        }
+    }
+}
+
+async fn read_messages(connection: Arc<Connection>, stdout: impl AsyncRead + Unpin) {
+    let mut messages = MessageReader::new(stdout);
+    loop {
+        match messages.next().await {
+            Ok(Some(msg)) => {
+                if let Err(_e) = connection.dispatch(msg) {
+                    _warn!("failed to handle message from pkl server: {_e}");
+                }
+            }
+            Ok(None) => break,
+            Err(_e) => {
+                _warn!("failed to read from pkl server: {_e}");
+                break;
+            }
+        }
+    }
+
+    // the server is gone, fail everything still waiting on it
+    connection.pending.lock().unwrap().clear();
+}
+
+/// Reads the messages the server writes, keeping a partially received message between reads
+struct MessageReader<R> {
+    reader: R,
+    buf: Vec<u8>,
+    scan: MessageScan,
+}
+
+impl<R: AsyncRead + Unpin> MessageReader<R> {
+    fn new(reader: R) -> Self {
+        MessageReader {
+            reader,
+            buf: Vec::new(),
+            scan: MessageScan::default(),
+        }
+    }
+
+    /// Reads the next message, returns `None` once the server closes its output
+    async fn next(&mut self) -> Result<Option<PklServerMessage>> {
+        loop {
+            if let Some(len) = self.scan.complete(&self.buf) {
+                let value = rmpv::decode::read_value(&mut &self.buf[..len])?;
+                self.buf.drain(..len);
+                let msg = decode_pkl_message(&value).ok_or(Error::PklMalformedResponse {
+                    message: "Failed to decode pkl message format".to_string(),
+                })?;
+                return Ok(Some(msg));
+            }
+
+            self.buf.reserve(8 * 1024);
+            if self.reader.read_buf(&mut self.buf).await? == 0 {
+                return Ok(None);
+            }
+        }
+    }
+}
+
+/// How far the msgpack value at the start of a buffer has been received.
+///
+/// Only the headers are read, and the scan resumes where it stopped when more bytes arrive,
+/// so a message is decoded once it's complete instead of every time a part of it arrives.
+#[derive(Debug, Default)]
+struct MessageScan {
+    /// The end of the values scanned so far
+    pos: usize,
+    /// The values still to be scanned, the message itself if nothing was scanned yet
+    remaining: usize,
+}
+
+/// The size of a msgpack value after its header
+enum ValueSize {
+    /// A scalar, string, binary or extension with this many bytes of data
+    Bytes(usize),
+    /// An array or map with this many nested values, counting keys and values of maps separately
+    Values(usize),
+}
+
+impl MessageScan {
+    /// Returns the length of the message at the start of `buf` once it has been received in full
+    fn complete(&mut self, buf: &[u8]) -> Option<usize> {
+        if self.pos == 0 && self.remaining == 0 {
+            self.remaining = 1;
+        }
+        while self.remaining > 0 {
+            let (header, size) = value_header(&buf[self.pos..])?;
+            match size {
+                ValueSize::Bytes(len) => {
+                    if buf.len() < self.pos + header + len {
+                        return None;
+                    }
+                    self.pos += header + len;
+                    self.remaining -= 1;
+                }
+                ValueSize::Values(len) => {
+                    self.pos += header;
+                    self.remaining = self.remaining - 1 + len;
+                }
+            }
+        }
+        Some(std::mem::take(self).pos)
+    }
+}
+
+/// The length of the header of the msgpack value at the start of `buf` and the size after it,
+/// `None` if the header hasn't been received in full.
+fn value_header(buf: &[u8]) -> Option<(usize, ValueSize)> {
+    use ValueSize::{Bytes, Values};
+
+    let marker = *buf.first()?;
+    // big endian length following the marker
+    let len = |width: usize| {
+        buf.get(1..=width)
+            .map(|bytes| bytes.iter().fold(0, |n, &b| (n << 8) | usize::from(b)))
+    };
+    Some(match marker {
+        0x80..=0x8f => (1, Values(2 * usize::from(marker & 0x0f))),
+        0x90..=0x9f => (1, Values(usize::from(marker & 0x0f))),
+        0xa0..=0xbf => (1, Bytes(usize::from(marker & 0x1f))),
+        0xc4 | 0xd9 => (2, Bytes(len(1)?)),
+        0xc5 | 0xda => (3, Bytes(len(2)?)),
+        0xc6 | 0xdb => (5, Bytes(len(4)?)),
+        // ext 8/16/32 have their type after the length
+        0xc7 => (3, Bytes(len(1)?)),
+        0xc8 => (4, Bytes(len(2)?)),
+        0xc9 => (6, Bytes(len(4)?)),
+        0xcc | 0xd0 => (2, Bytes(0)),
+        0xcd | 0xd1 => (3, Bytes(0)),
+        0xca | 0xce | 0xd2 => (5, Bytes(0)),
+        0xcb | 0xcf | 0xd3 => (9, Bytes(0)),
+        0xd4 => (3, Bytes(0)),
+        0xd5 => (4, Bytes(0)),
+        0xd6 => (6, Bytes(0)),
+        0xd7 => (10, Bytes(0)),
+        0xd8 => (18, Bytes(0)),
+        0xdc => (3, Values(len(2)?)),
+        0xdd => (5, Values(len(4)?)),
+        0xde => (3, Values(2 * len(2)?)),
+        0xdf => (5, Values(2 * len(4)?)),
+        // fixints, nil, booleans and the invalid `0xc1`, which fails to decode
+        _ => (1, Bytes(0)),
+    })
+}
+
+/// Answers a read or list request with the evaluator's async readers, returning the encoded response
+async fn handle_reader_request(
+    handlers: Option<&AsyncEvaluatorHandlers>,
+    msg: &PklServerMessage,
+) -> Result<Vec<u8>> {
+    let evaluator_id = message_field(msg, "evaluatorId")?;
+    let request_id = message_field(msg, "requestId")?;
+    let uri: String = msg
+        .response
+        .as_map()
+        .context("expected map for reader request")
+        .and_then(|map| {
+            extract_field(map, "uri").map_err(|e| Error::PklMalformedResponse {
+                message: e.to_string(),
+            })
+        })?;
+    let scheme = parse_scheme(&uri);
+    let no_reader = || {
+        _warn!("No reader found for scheme: {:?}", uri);
+        format!("No reader found for scheme: {uri:?}")
+    };
+
+    let resource_reader = handlers
+        .and_then(|h| {
+            h.resource_readers
+                .iter()
+                .find(|r| Some(r.scheme()) == scheme)
+        })
+        .ok_or_else(no_reader);
+    let module_reader = handlers
+        .and_then(|h| h.module_readers.iter().find(|r| Some(r.scheme()) == scheme))
+        .ok_or_else(no_reader);
+
+    match msg.header {
+        READ_RESOURCE_REQUEST => {
+            let (contents, error) = split(match resource_reader {
+                Ok(reader) => reader.read(&uri).await.map_err(|e| e.to_string()),
+                Err(e) => Err(e),
+            });
+            Ok(ReadResourceResponse {
+                request_id,
+                evaluator_id,
+                contents,
+                error,
+            }
+            .encode_msg()?)
+        }
+        READ_MODULE_REQUEST => {
+            let (contents, error) = split(match module_reader {
+                Ok(reader) => reader.read(&uri).await.map_err(|e| e.to_string()),
+                Err(e) => Err(e),
+            });
+            Ok(ReadModuleResponse {
+                request_id,
+                evaluator_id,
+                contents,
+                error,
+            }
+            .encode_msg()?)
+        }
+        LIST_RESOURCES_REQUEST => {
+            let (path_elements, error) = split(match resource_reader {
+                Ok(reader) => reader.list(&uri).await.map_err(|e| e.to_string()),
+                Err(e) => Err(e),
+            });
+            Ok(ListResourcesResponse {
+                request_id,
+                evaluator_id,
+                path_elements,
+                error,
+            }
+            .encode_msg()?)
+        }
+        LIST_MODULES_REQUEST => {
+            let (path_elements, error) = split(match module_reader {
+                Ok(reader) => reader.list(&uri).await.map_err(|e| e.to_string()),
+                Err(e) => Err(e),
+            });
+            Ok(ListModulesResponse {
+                request_id,
+                evaluator_id,
+                path_elements,
+                error,
+            }
+            .encode_msg()?)
+        }
+        header => Err(Error::Message(format!(
+            "not a reader request: 0x{header:x}"
+        ))),
+    }
+}
+
+/// Splits a reader result into the `contents`/`error` pair of a response
+fn split<T>(result: std::result::Result<T, String>) -> (Option<T>, Option<String>) {
+    match result {
+        Ok(value) => (Some(value), None),
+        Err(e) => (None, Some(e)),
+    }
+}
+
+/// Evaluates pkl modules on a `pkl server` process without blocking the tokio runtime.
+///
+/// Created with [`AsyncEvaluator::new`], which starts a process for this evaluator alone,
+/// or with [`AsyncEvaluatorManager::new_evaluator`] to share a process between several evaluators.
+///
+/// Evaluations take `&self`, so several of them can run concurrently on one evaluator.
+/// Dropping an evaluation before it completes closes the evaluator, since pkl can't cancel
+/// a single evaluation; later evaluations on it return an error.
+/// The evaluator is also closed when it is dropped.
+pub struct AsyncEvaluator {
+    evaluator_id: i64,
+    server: Arc<AsyncPklServer>,
+}
+
+impl AsyncEvaluator {
+    pub fn id(&self) -> i64 {
+        self.evaluator_id
+    }
+
+    /// Create a new evaluator with default options
+    /// This will start a new pkl process and create an evaluator
+    /// # Errors
+    /// - Returns an error if the pkl process fails to start or if the evaluator fails to be created
+    pub async fn new() -> Result<Self> {
+        Self::new_from_options(EvaluatorOptions::default()).await
+    }
+
+    /// Create a new evaluator with the given options
+    /// This will start a new pkl process and create an evaluator
+    ///
+    /// Use an [`AsyncEvaluatorManager`] to create several evaluators on the same process.
+    /// # Errors
+    /// - Returns an error if the pkl process fails to start or if the evaluator fails to be created
+    pub fn new_from_options(
+        options: EvaluatorOptions,
+    ) -> impl Future<Output = Result<Self>> + Send + 'static {
+        let command = options.pkl_command.clone().unwrap_or_default();
+        let create = AsyncPklServer::start(command)
+            .map(|server| AsyncEvaluator::create(Arc::new(server), options));
+        async move { create?.await }
+    }
+
+    fn create(
+        server: Arc<AsyncPklServer>,
+        options: EvaluatorOptions,
+    ) -> impl Future<Output = Result<Self>> + Send + 'static {
+        // the options aren't `Send`, so everything needed from them is taken out before awaiting
+        let request_id = server.connection.next_request_id();
+        let required = required_versions(&options);
+        let project_dir = options.project_dir.clone();
+        let prepared = prepare_create_evaluator(options, request_id);
+
+        async move {
+            let (mut message, handlers) = prepared?;
+            if !required.is_empty() {
+                check_version(server.version().await?, &required)?;
+            }
+            if let Some(dir) = project_dir {
+                Self::load_project(&server, &dir).await?.apply(&mut message);
+            }
+
+            let response = server
+                .connection
+                .request(request_id, message.encode_msg()?)?
+                .response()
+                .await?;
+            let evaluator_id = decode_create_evaluator_response(&response)?;
+
+            server
+                .connection
+                .evaluators
+                .lock()
+                .unwrap()
+                .insert(evaluator_id, Arc::new(handlers));
+
+            Ok(AsyncEvaluator {
+                evaluator_id,
+                server,
+            })
+        }
+    }
+
+    /// Evaluates the `PklProject` file in `dir` with a temporary evaluator on the same server
+    async fn load_project(
+        server: &Arc<AsyncPklServer>,
+        dir: &std::path::Path,
+    ) -> Result<PklProject> {
+        // boxed, as creating the evaluator that loads the project is part of creating an evaluator
+        let evaluator = Box::pin(AsyncEvaluator::create(
+            server.clone(),
+            EvaluatorOptions::default(),
+        ))
+        .await?;
+        let pkl_mod = evaluator.evaluate_module(PklProject::file(dir)).await?;
+        PklProject::from_module(pkl_mod, dir)
+    }
+
+    /// Evaluate a pkl module
+    /// # Errors
+    /// - Returns an error if the pkl process fails to evaluate the module or if the module is malformed
+    /// - If the provided path is not does not exist
+    pub async fn evaluate_module(&self, path: impl AsRef<std::path::Path>) -> Result<PklMod> {
+        let uri = module_uri(path)?;
+        self.evaluate_uri(uri).await
+    }
+
+    /// Evaluate the module at the given URI, see [`Evaluator::evaluate_uri`](crate::api::Evaluator::evaluate_uri)
+    /// # Errors
+    /// - Returns an error if the pkl process fails to evaluate the module or if the module is malformed
+    pub async fn evaluate_uri(&self, uri: impl Into<String>) -> Result<PklMod> {
+        let ast = self.evaluate(uri.into(), None, None).await?;
+        decode_module(&ast)
+    }
+
+    /// Evaluate pkl source text, see [`Evaluator::evaluate_text`](crate::api::Evaluator::evaluate_text)
+    /// # Errors
+    /// - Returns an error if the pkl process fails to evaluate the module or if the module is malformed
+    pub async fn evaluate_text(
+        &self,
+        source: impl Into<String>,
+        uri: impl Into<String>,
+    ) -> Result<PklMod> {
+        let ast = self.evaluate(uri.into(), Some(source.into()), None).await?;
+        decode_module(&ast)
+    }
+
+    /// Evaluate a single expression within the pkl module at `path`,
+    /// see [`Evaluator::evaluate_expression`](crate::api::Evaluator::evaluate_expression)
+    /// # Errors
+    /// - Returns an error if the pkl process fails to evaluate the expression or if the result is malformed
+    /// - If the provided path does not exist
+    pub async fn evaluate_expression(
+        &self,
+        path: impl AsRef<std::path::Path>,
+        expr: impl Into<String>,
+    ) -> Result<PklValue> {
+        let uri = module_uri(path)?;
+        self.evaluate_expression_uri(uri, expr).await
+    }
+
+    /// Evaluate a single expression within the module at the given URI.
+    /// # Errors
+    /// - Returns an error if the pkl process fails to evaluate the expression or if the result is malformed
+    pub async fn evaluate_expression_uri(
+        &self,
+        uri: impl Into<String>,
+        expr: impl Into<String>,
+    ) -> Result<PklValue> {
+        let ast = self.evaluate(uri.into(), None, Some(expr.into())).await?;
+        decode_value(&ast)
+    }
+
+    /// The version of the pkl server, or `None` if it couldn't be determined
+    /// # Errors
+    /// - Returns an error if `pkl --version` fails to run
+    pub async fn pkl_version(&self) -> Result<Option<PklVersion>> {
+        self.server.version().await
+    }
+
+    async fn evaluate(
+        &self,
+        module_uri: String,
+        module_text: Option<String>,
+        expr: Option<String>,
+    ) -> Result<rmpv::Value> {
+        let connection = &self.server.connection;
+        if !connection.is_open(self.evaluator_id) {
+            return Err(Error::Message(format!(
+                "evaluator {} was closed",
+                self.evaluator_id
+            )));
+        }
+
+        let request_id = connection.next_request_id();
+        let request = EvaluateRequest {
+            request_id,
+            evaluator_id: self.evaluator_id,
+            module_uri,
+            module_text,
+            expr,
+        };
+        let response = connection
+            .request(request_id, request.encode_msg()?)?
+            .closes_evaluator(self.evaluator_id)
+            .response()
+            .await?;
+
+        _debug!("evaluate response for request {request_id}");
+        decode_evaluate_response(&response)
+    }
+}
+
+impl Drop for AsyncEvaluator {
+    fn drop(&mut self) {
+        self.server.connection.close_evaluator(self.evaluator_id);
+    }
+}
+
+fn prepare_create_evaluator(
+    options: EvaluatorOptions,
+    request_id: u64,
+) -> Result<(CreateEvaluator, AsyncEvaluatorHandlers)> {
+    if options.client_module_readers.is_some()
+        || options.client_resource_readers.is_some()
+        || options.logger.is_some()
+    {
+        return Err(Error::Message(
+            "an `AsyncEvaluator` can't use blocking readers or loggers, register them with \
+             `add_async_module_reader`, `add_async_resource_reader` and `async_logger`"
+                .into(),
+        ));
+    }
+
+    let mut message = CreateEvaluator::from(&options);
+    message.request_id = request_id;
+
+    Ok((
+        message,
+        AsyncEvaluatorHandlers {
+            module_readers: options.async_module_readers.unwrap_or_default(),
+            resource_readers: options.async_resource_readers.unwrap_or_default(),
+            logger: options.async_logger,
+        },
+    ))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::api::reader::{AsyncReaderError, BoxFuture, PathElements};
+    use crate::internal::msgapi::codes::{CLOSE, EVALUATE_REQUEST};
+    use tokio::io::DuplexStream;
+
+    struct GreetingReader;
+
+    impl AsyncPklResourceReader for GreetingReader {
+        fn scheme(&self) -> &str {
+            "custom"
+        }
+
+        fn read<'a>(
+            &'a self,
+            _uri: &'a str,
+        ) -> BoxFuture<'a, std::result::Result<Vec<u8>, AsyncReaderError>> {
+            Box::pin(async { Ok(b"hello".to_vec()) })
+        }
+
+        fn list<'a>(
+            &'a self,
+            _uri: &'a str,
+        ) -> BoxFuture<'a, std::result::Result<Vec<PathElements>, AsyncReaderError>> {
+            Box::pin(async { Ok(vec![]) })
+        }
+    }
+
+    fn message(header: u64, fields: Vec<(&str, rmpv::Value)>) -> Vec<u8> {
+        let value = rmpv::Value::Array(vec![
+            header.into(),
+            rmpv::Value::Map(fields.into_iter().map(|(k, v)| (k.into(), v)).collect()),
+        ]);
+        let mut out = Vec::new();
+        rmpv::encode::write_value(&mut out, &value).unwrap();
+        out
+    }
+
+    #[test]
+    fn scans_messages_as_they_arrive() {
+        let values = vec![
+            ("nil", rmpv::Value::Nil),
+            ("negative", (-40_000).into()),
+            ("float", 1.5.into()),
+            ("text", "x".repeat(300).into()),
+            ("bytes", rmpv::Value::Binary(vec![7; 70_000])),
+            ("ext", rmpv::Value::Ext(1, vec![1, 2, 3, 4])),
+            (
+                "nested",
+                rmpv::Value::Array(vec![rmpv::Value::Map(vec![]); 20]),
+            ),
+        ];
+        let mut buf = message(EVALUATE_RESPONSE, values);
+        let len = buf.len();
+        buf.extend(message(LOG_MESSAGE, vec![]));
+
+        let mut scan = MessageScan::default();
+        for end in 0..len {
+            assert_eq!(scan.complete(&buf[..end]), None);
+        }
+        assert_eq!(scan.complete(&buf), Some(len));
+        assert_eq!(scan.complete(&buf[len..]), Some(buf.len() - len));
+    }
+
+    #[tokio::test]
+    async fn reads_messages_split_across_reads() {
+        let (mut writer, reader) = tokio::io::duplex(5);
+        let payload = message(
+            EVALUATE_RESPONSE,
+            vec![("result", rmpv::Value::Binary(vec![1; 1000]))],
+        );
+
+        let write = async {
+            writer.write_all(&payload).await.unwrap();
+            writer
+                .write_all(&message(LOG_MESSAGE, vec![]))
+                .await
+                .unwrap();
+            drop(writer);
+        };
+        let read = async {
+            let mut messages = MessageReader::new(reader);
+            let first = messages.next().await.unwrap().unwrap();
+            let second = messages.next().await.unwrap().unwrap();
+            assert!(messages.next().await.unwrap().is_none());
+            (first, second)
+        };
+
+        let ((), (first, second)) = tokio::join!(write, read);
+        assert_eq!(first.header, EVALUATE_RESPONSE);
+        assert_eq!(field(&first, "result").as_slice(), Some(&[1; 1000][..]));
+        assert_eq!(second.header, LOG_MESSAGE);
+    }
+
+    fn field<'a>(msg: &'a PklServerMessage, name: &str) -> &'a rmpv::Value {
+        let map = msg.response.as_map().unwrap();
+        &map.iter()
+            .find(|(k, _)| k.as_str() == Some(name))
+            .unwrap()
+            .1
+    }
+
+    /// Answers an evaluate request with its module uri as the result
+    fn evaluate_response(request: &PklServerMessage) -> Vec<u8> {
+        let mut result = Vec::new();
+        rmpv::encode::write_value(&mut result, field(request, "moduleUri")).unwrap();
+        message(
+            EVALUATE_RESPONSE,
+            vec![
+                ("requestId", field(request, "requestId").clone()),
+                ("evaluatorId", field(request, "evaluatorId").clone()),
+                ("result", rmpv::Value::Binary(result)),
+            ],
+        )
+    }
+
+    /// An evaluator with id 1, and the streams the fake server reads requests from and writes responses to
+    fn fake_server(
+        resource_readers: Vec<Arc<dyn AsyncPklResourceReader>>,
+    ) -> (AsyncEvaluator, DuplexStream, DuplexStream) {
+        let (stdin, requests) = tokio::io::duplex(64 * 1024);
+        let (responses, stdout) = tokio::io::duplex(64 * 1024);
+
+        let server = AsyncPklServer::new(stdin, stdout);
+        server.connection.evaluators.lock().unwrap().insert(
+            1,
+            Arc::new(AsyncEvaluatorHandlers {
+                module_readers: vec![],
+                resource_readers,
+                logger: None,
+            }),
+        );
+
+        let evaluator = AsyncEvaluator {
+            evaluator_id: 1,
+            server: Arc::new(server),
+        };
+        (evaluator, requests, responses)
+    }
+
+    #[tokio::test]
+    async fn concurrent_evaluations() {
+        let (evaluator, requests, mut responses) = fake_server(vec![]);
+
+        let server = async {
+            let mut requests = MessageReader::new(requests);
+            let first = requests.next().await.unwrap().unwrap();
+            let second = requests.next().await.unwrap().unwrap();
+            // the responses arrive in the opposite order of the requests
+            for request in [second, first] {
+                assert_eq!(request.header, EVALUATE_REQUEST);
+                responses
+                    .write_all(&evaluate_response(&request))
+                    .await
+                    .unwrap();
+            }
+        };
+
+        let (a, b, ()) = tokio::join!(
+            evaluator.evaluate_expression_uri("repl:a", "output"),
+            evaluator.evaluate_expression_uri("repl:b", "output"),
+            server
+        );
+        assert_eq!(a.unwrap(), PklValue::String("repl:a".into()));
+        assert_eq!(b.unwrap(), PklValue::String("repl:b".into()));
+    }
+
+    #[tokio::test]
+    async fn answers_reads_with_async_readers() {
+        let (_evaluator, requests, mut responses) = fake_server(vec![Arc::new(GreetingReader)]);
+
+        responses
+            .write_all(&message(
+                READ_RESOURCE_REQUEST,
+                vec![
+                    ("requestId", 7.into()),
+                    ("evaluatorId", 1.into()),
+                    ("uri", "custom:greeting".into()),
+                ],
+            ))
+            .await
+            .unwrap();
+
+        let reply = MessageReader::new(requests).next().await.unwrap().unwrap();
+        assert_eq!(field(&reply, "requestId").as_i64(), Some(7));
+        assert_eq!(field(&reply, "contents").as_slice(), Some(&b"hello"[..]));
+    }
+
+    #[tokio::test]
+    async fn cancelling_an_evaluation_closes_the_evaluator() {
+        let (evaluator, requests, _responses) = fake_server(vec![]);
+        let evaluator = Arc::new(evaluator);
+
+        let task = tokio::spawn({
+            let evaluator = evaluator.clone();
+            async move { evaluator.evaluate_uri("repl:a").await }
+        });
+
+        let mut requests = MessageReader::new(requests);
+        let request = requests.next().await.unwrap().unwrap();
+        assert_eq!(request.header, EVALUATE_REQUEST);
+
+        task.abort();
+        assert!(task.await.unwrap_err().is_cancelled());
+
+        let close = requests.next().await.unwrap().unwrap();
+        assert_eq!(close.header, CLOSE);
+        assert_eq!(field(&close, "evaluatorId").as_i64(), Some(1));
+
+        let connection = &evaluator.server.connection;
+        assert!(connection.pending.lock().unwrap().is_empty());
+        assert!(evaluator.evaluate_uri("repl:a").await.is_err());
+    }
+
+    #[tokio::test]
+    async fn rejects_blocking_readers() {
+        let (evaluator, _requests, _responses) = fake_server(vec![]);
+        let options = EvaluatorOptions::new().logger(|_: &LogMessage| {});
+
+        let result = AsyncEvaluator::create(evaluator.server.clone(), options).await;
+        assert!(result.is_err());
+    }
+}
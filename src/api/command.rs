@@ -0,0 +1,219 @@
+use std::{fmt, path::PathBuf, process::Command};
+
+use crate::{
+    api::evaluator::EvaluatorOptions,
+    error::{Error, Result},
+    utils::macros::_warn,
+};
+
+/// The `pkl` executable used to start the pkl server, and any arguments passed before `server`.
+///
+/// Defaults to the command in the `PKL_EXEC` environment variable if it's set, otherwise `pkl` from `PATH`.
+/// `PKL_EXEC` is split on whitespace, so it can hold arguments too, e.g. `java -jar pkl.jar`.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct PklCommand {
+    /// Path to the executable
+    pub path: PathBuf,
+
+    /// Arguments passed to the executable before `server` or `--version`
+    pub args: Vec<String>,
+}
+
+impl PklCommand {
+    pub fn new(path: impl Into<PathBuf>, args: &[&str]) -> Self {
+        PklCommand {
+            path: path.into(),
+            args: args.iter().map(ToString::to_string).collect(),
+        }
+    }
+
+    /// The command in `PKL_EXEC`, or `pkl` if it isn't set
+    pub fn from_env() -> Self {
+        std::env::var("PKL_EXEC")
+            .ok()
+            .and_then(|exec| Self::parse(&exec))
+            .unwrap_or_else(|| PklCommand::new("pkl", &[]))
+    }
+
+    fn parse(exec: &str) -> Option<Self> {
+        let mut parts = exec.split_whitespace();
+        let path = parts.next()?;
+        Some(PklCommand {
+            path: path.into(),
+            args: parts.map(ToString::to_string).collect(),
+        })
+    }
+
+    /// The command with its arguments, ready for `server` or `--version` to be added
+    pub(crate) fn command(&self) -> Command {
+        let mut command = Command::new(&self.path);
+        command.args(&self.args);
+        command
+    }
+
+    /// Runs `pkl --version` and returns the version it reports.
+    ///
+    /// Returns `None` if the output isn't in the expected format.
+    /// # Errors
+    /// - Returns an error if the executable fails to run
+    pub fn version(&self) -> Result<Option<PklVersion>> {
+        let output = self
+            .command()
+            .arg("--version")
+            .output()
+            .map_err(Error::PklProcessStart)?;
+        Ok(parse_version_output(&output.stdout))
+    }
+}
+
+impl Default for PklCommand {
+    fn default() -> Self {
+        Self::from_env()
+    }
+}
+
+pub(crate) fn parse_version_output(stdout: &[u8]) -> Option<PklVersion> {
+    let output = String::from_utf8_lossy(stdout);
+    let version = PklVersion::parse(&output);
+    if version.is_none() {
+        _warn!("unrecognized `pkl --version` output: {output:?}");
+    }
+    version
+}
+
+/// A pkl release, as reported by `pkl --version`
+#[derive(Clone, Copy, Debug, PartialEq, Eq, PartialOrd, Ord, Hash)]
+pub struct PklVersion {
+    pub major: u32,
+    pub minor: u32,
+    pub patch: u32,
+}
+
+impl PklVersion {
+    /// The first release supporting [`EvaluatorOptions::http`]
+    pub const HTTP: PklVersion = PklVersion::new(0, 26, 0);
+    /// The first release supporting external readers
+    pub const EXTERNAL_READERS: PklVersion = PklVersion::new(0, 27, 0);
+    /// The first release with `pkl:analyze`, used to find the imports of watched modules
+    pub const IMPORT_GRAPH: PklVersion = PklVersion::new(0, 27, 0);
+    /// The first release with the `Bytes` type
+    pub const BYTES: PklVersion = PklVersion::new(0, 29, 0);
+
+    pub const fn new(major: u32, minor: u32, patch: u32) -> Self {
+        PklVersion {
+            major,
+            minor,
+            patch,
+        }
+    }
+
+    /// Parses `pkl --version` output such as `Pkl 0.28.2 (macOS 14.5, native)`
+    fn parse(output: &str) -> Option<Self> {
+        let version = output
+            .split_whitespace()
+            .find(|word| word.starts_with(|c: char| c.is_ascii_digit()))?;
+        // drop pre-release and build metadata, e.g. `0.30.0-dev+abc`
+        let version = version.split(['-', '+']).next()?;
+
+        let mut parts = version.split('.').map(str::parse::<u32>);
+        let major = parts.next()?.ok()?;
+        let minor = parts.next()?.ok()?;
+        let patch = parts.next().unwrap_or(Ok(0)).ok()?;
+        Some(PklVersion::new(major, minor, patch))
+    }
+}
+
+impl fmt::Display for PklVersion {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}.{}.{}", self.major, self.minor, self.patch)
+    }
+}
+
+/// The pkl versions needed by the features `options` uses, empty if none of them is version-gated
+pub(crate) fn required_versions(options: &EvaluatorOptions) -> Vec<(&'static str, PklVersion)> {
+    let mut required = vec![];
+    if options.http.is_some() {
+        required.push(("http options", PklVersion::HTTP));
+    }
+    if options.external_module_readers.is_some() || options.external_resource_readers.is_some() {
+        required.push(("external readers", PklVersion::EXTERNAL_READERS));
+    }
+    required
+}
+
+/// Fails if `version` is older than any of the `required` versions.
+/// An unknown version is assumed to support everything.
+pub(crate) fn check_version(
+    version: Option<PklVersion>,
+    required: &[(&'static str, PklVersion)],
+) -> Result<()> {
+    let Some(version) = version else {
+        return Ok(());
+    };
+    match required.iter().find(|(_, minimum)| version < *minimum) {
+        Some(&(feature, minimum)) => Err(Error::UnsupportedPklVersion {
+            version,
+            required: minimum,
+            feature,
+        }),
+        None => Ok(()),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::api::evaluator::HttpOptions;
+
+    #[test]
+    fn parse_version() {
+        assert_eq!(
+            PklVersion::parse("Pkl 0.28.2 (macOS 14.5, native)"),
+            Some(PklVersion::new(0, 28, 2))
+        );
+        assert_eq!(
+            PklVersion::parse("Pkl 0.30.0-dev+abc123 (Linux 6.1, Java 17.0.9)\n"),
+            Some(PklVersion::new(0, 30, 0))
+        );
+        assert_eq!(PklVersion::parse("command not found"), None);
+    }
+
+    #[test]
+    fn parse_pkl_exec() {
+        assert_eq!(
+            PklCommand::parse("java -jar /opt/pkl.jar"),
+            Some(PklCommand::new("java", &["-jar", "/opt/pkl.jar"]))
+        );
+        assert_eq!(PklCommand::parse("  "), None);
+    }
+
+    #[test]
+    fn rejects_features_newer_than_server() {
+        let options = EvaluatorOptions::new().http(HttpOptions::new());
+        let required = required_versions(&options);
+
+        let err = check_version(Some(PklVersion::new(0, 25, 3)), &required).unwrap_err();
+        assert!(matches!(
+            err,
+            Error::UnsupportedPklVersion {
+                required: PklVersion::HTTP,
+                ..
+            }
+        ));
+        assert!(check_version(Some(PklVersion::new(0, 26, 0)), &required).is_ok());
+        assert!(check_version(None, &required).is_ok());
+        assert!(required_versions(&EvaluatorOptions::new()).is_empty());
+
+        let required = [("Bytes overrides", PklVersion::BYTES)];
+        let err = check_version(Some(PklVersion::new(0, 28, 2)), &required).unwrap_err();
+        assert!(matches!(
+            err,
+            Error::UnsupportedPklVersion {
+                required: PklVersion::BYTES,
+                feature: "Bytes overrides",
+                ..
+            }
+        ));
+        assert!(check_version(Some(PklVersion::BYTES), &required).is_ok());
+    }
+}
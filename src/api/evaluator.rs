@@ -1,40 +1,34 @@
-use std::{
-    collections::HashMap,
-    io::{Read, Write},
-    process::{Child, Command, Stdio},
-    sync::Arc,
-};
+use std::{cell::RefCell, collections::HashMap, rc::Rc, sync::Arc};
 
 #[cfg(feature = "indexmap")]
 use indexmap::IndexMap;
 
 use crate::{
-    api::reader::{
-        handle_list_modules, handle_list_resources, handle_read_module, handle_read_resource,
-    },
     context::Context,
     error::{Error, Result},
-    internal::msgapi::{
-        PklMessage,
-        incoming::PklServerMessage,
-        outgoing::{CloseEvaluator, CreateEvaluator, EvaluateRequest, ExternalReader},
-    },
-    utils::{self, macros::_debug},
-    value::value::MapImpl,
+    internal::msgapi::outgoing::{EvaluateRequest, ExternalReader},
+    utils,
+    value::{PklValue, value::MapImpl},
 };
 
-use crate::internal::msgapi::codes::{
-    LIST_MODULES_REQUEST, LIST_RESOURCES_REQUEST, READ_MODULE_REQUEST, READ_RESOURCE_REQUEST,
+use crate::{
+    REPL_TEXT_URI,
+    config::split_path,
+    decoder::{decode_module, decode_value},
+    pkl::{
+        PklMod,
+        ser::{Node, render_amends, render_type_query},
+    },
 };
 
-use crate::{decoder::decode_module, pkl::PklMod};
-
+use super::command::{PklCommand, PklVersion, check_version};
+use super::logger::PklLogger;
+use super::manager::{EvaluatorManager, PklServer};
+use super::project::PklProject;
+#[cfg(feature = "async")]
+use super::reader::{AsyncPklModuleReader, AsyncPklResourceReader};
 use super::reader::{IntoModuleReaders, IntoResourceReaders, PklModuleReader, PklResourceReader};
 
-pub(crate) const EVALUATE_RESPONSE: u64 = 0x24;
-pub(crate) const CREATE_EVALUATOR_REQUEST_ID: u64 = 135;
-pub(crate) const OUTGOING_MESSAGE_REQUEST_ID: u64 = 9805131;
-
 /// HTTP proxy configuration for outgoing requests.
 ///
 /// Only HTTP proxies are supported (not HTTPS or SOCKS).
@@ -101,6 +95,51 @@ impl HttpOptions {
     }
 }
 
+/// Modules an evaluator may import unless [`EvaluatorOptions::allowed_modules`] is set
+pub const DEFAULT_ALLOWED_MODULES: &[&str] = &["pkl:", "repl:", "file:", "https:", "package:"];
+
+/// Resources an evaluator may read unless [`EvaluatorOptions::allowed_resources`] is set
+pub const DEFAULT_ALLOWED_RESOURCES: &[&str] =
+    &["env:", "prop:", "package:", "https:", "projectpackage:"];
+
+/// Which variables of the process environment an evaluator can read with `env:`,
+/// see [`EvaluatorOptions::inherit_env`]
+#[derive(Default, Clone, Debug, PartialEq, Eq)]
+pub enum InheritEnv {
+    /// Every variable, the default
+    #[default]
+    All,
+    /// No variables, only those set with [`EvaluatorOptions::env_var`]
+    Nothing,
+    /// Only the listed variables. A name ending in `*` matches every variable starting with the rest of it, e.g. `APP_*`.
+    Only(Vec<String>),
+}
+
+impl InheritEnv {
+    fn allows(&self, name: &str) -> bool {
+        match self {
+            InheritEnv::All => true,
+            InheritEnv::Nothing => false,
+            InheritEnv::Only(names) => {
+                names.iter().any(|allowed| match allowed.strip_suffix('*') {
+                    Some(prefix) => name.starts_with(prefix),
+                    None => name == allowed,
+                })
+            }
+        }
+    }
+
+    /// The variables of `vars` this allows
+    pub(crate) fn filter(
+        &self,
+        vars: impl IntoIterator<Item = (String, String)>,
+    ) -> HashMap<String, String> {
+        vars.into_iter()
+            .filter(|(name, _)| self.allows(name))
+            .collect()
+    }
+}
+
 // options that can be provided to the evaluator, such as properties (-p flag from CLI)
 #[derive(Default, Clone)]
 pub struct EvaluatorOptions {
@@ -125,6 +164,51 @@ pub struct EvaluatorOptions {
 
     /// Timeout in seconds for evaluating a source module.
     pub timeout_seconds: Option<u64>,
+
+    /// Receives messages logged by the pkl server, such as `trace()` output and warnings.
+    pub logger: Option<Arc<dyn PklLogger>>,
+
+    /// The pkl executable to start the server with, see [`PklCommand`]
+    pub pkl_command: Option<PklCommand>,
+
+    /// Directory containing the `PklProject` file whose dependencies and evaluator settings are used
+    pub project_dir: Option<std::path::PathBuf>,
+
+    /// Patterns of the module URIs that may be imported, replacing [`DEFAULT_ALLOWED_MODULES`]
+    pub allowed_modules: Option<Vec<String>>,
+
+    /// Patterns of the resource URIs that may be read, replacing [`DEFAULT_ALLOWED_RESOURCES`]
+    pub allowed_resources: Option<Vec<String>>,
+
+    /// Directories and zip files searched by `modulepath:` URIs
+    pub module_paths: Option<Vec<std::path::PathBuf>>,
+
+    /// Restricts file-based modules and resources to this directory
+    pub root_dir: Option<std::path::PathBuf>,
+
+    /// Directory packages are cached in. Packages aren't cached if this isn't set.
+    pub cache_dir: Option<std::path::PathBuf>,
+
+    /// Format of `output.text`, e.g. `json`, `yaml` or `pcf`
+    pub output_format: Option<String>,
+
+    /// Environment variables readable with `env:`, in addition to the inherited ones
+    pub env: Option<HashMap<String, String>>,
+
+    /// Which variables of the process environment are passed to the evaluator, [`InheritEnv::All`] if not set
+    pub inherit_env: Option<InheritEnv>,
+
+    /// Client-side module readers used by an [`AsyncEvaluator`](crate::api::AsyncEvaluator)
+    #[cfg(feature = "async")]
+    pub async_module_readers: Option<Vec<Arc<dyn AsyncPklModuleReader>>>,
+
+    /// Client-side resource readers used by an [`AsyncEvaluator`](crate::api::AsyncEvaluator)
+    #[cfg(feature = "async")]
+    pub async_resource_readers: Option<Vec<Arc<dyn AsyncPklResourceReader>>>,
+
+    /// Logger used by an [`AsyncEvaluator`](crate::api::AsyncEvaluator), which may be called from another thread
+    #[cfg(feature = "async")]
+    pub async_logger: Option<Arc<dyn PklLogger + Send + Sync>>,
 }
 
 impl EvaluatorOptions {
@@ -246,19 +330,205 @@ impl EvaluatorOptions {
         self
     }
 
+    /// Set the pkl executable used to start the server, and any arguments placed before `server`.
+    ///
+    /// Defaults to `PKL_EXEC` or `pkl` from `PATH`, see [`PklCommand`].
+    /// Only used when the evaluator starts its own server, evaluators created from an
+    /// [`EvaluatorManager`] run on the manager's server.
+    pub fn pkl_command(mut self, path: impl Into<std::path::PathBuf>, args: &[&str]) -> Self {
+        self.pkl_command = Some(PklCommand::new(path, args));
+        self
+    }
+
+    /// Evaluate modules within the project in `dir`, like `pkl eval --project-dir`.
+    ///
+    /// The `PklProject` file in `dir` is evaluated when the evaluator is created, so modules can import
+    /// its dependencies with `@name/...`. Dependencies have to be resolved with `pkl project resolve` first.
+    /// The project's `evaluatorSettings` are applied to options that aren't set on the evaluator itself,
+    /// e.g. properties set with [`EvaluatorOptions::property`] override its `externalProperties`.
+    pub fn project_dir(mut self, dir: impl Into<std::path::PathBuf>) -> Self {
+        self.project_dir = Some(dir.into());
+        self
+    }
+
+    /// Set the modules the evaluator may import, replacing [`DEFAULT_ALLOWED_MODULES`].
+    ///
+    /// Each pattern is a regular expression matched against the start of a module URI, e.g. `file:` or `https://example\.com/`.
+    /// The schemes of client and external module readers are allowed regardless.
+    ///
+    /// # Example
+    /// Evaluating untrusted source text without network, file or environment access:
+    /// ```no_run
+    /// use rpkl::EvaluatorOptions;
+    ///
+    /// let options = EvaluatorOptions::new()
+    ///     .allowed_modules(["pkl:", "repl:"])
+    ///     .allowed_resources(["prop:"]);
+    /// ```
+    pub fn allowed_modules(
+        mut self,
+        patterns: impl IntoIterator<Item = impl Into<String>>,
+    ) -> Self {
+        self.allowed_modules = Some(patterns.into_iter().map(Into::into).collect());
+        self
+    }
+
+    /// Set the resources the evaluator may read, replacing [`DEFAULT_ALLOWED_RESOURCES`].
+    ///
+    /// See [`EvaluatorOptions::allowed_modules`].
+    pub fn allowed_resources(
+        mut self,
+        patterns: impl IntoIterator<Item = impl Into<String>>,
+    ) -> Self {
+        self.allowed_resources = Some(patterns.into_iter().map(Into::into).collect());
+        self
+    }
+
+    /// Set the directories and zip files searched by `modulepath:` URIs, like `pkl eval --module-path`.
+    pub fn module_paths(
+        mut self,
+        paths: impl IntoIterator<Item = impl Into<std::path::PathBuf>>,
+    ) -> Self {
+        self.module_paths = Some(paths.into_iter().map(Into::into).collect());
+        self
+    }
+
+    /// Restrict file-based modules and resources to those within `dir`, like `pkl eval --root-dir`.
+    pub fn root_dir(mut self, dir: impl Into<std::path::PathBuf>) -> Self {
+        self.root_dir = Some(dir.into());
+        self
+    }
+
+    /// Cache downloaded packages in `dir`, e.g. `~/.pkl/cache` to share the cache with the pkl CLI.
+    ///
+    /// Packages aren't cached if this isn't set.
+    pub fn cache_dir(mut self, dir: impl Into<std::path::PathBuf>) -> Self {
+        self.cache_dir = Some(dir.into());
+        self
+    }
+
+    /// Set the format `output.text` is rendered in, e.g. `json`, `yaml` or `pcf`, like `pkl eval --format`.
+    pub fn output_format(mut self, format: impl Into<String>) -> Self {
+        self.output_format = Some(format.into());
+        self
+    }
+
+    /// Set the environment variables readable with `env:`, replacing any set before.
+    ///
+    /// They're added to the variables inherited from the process, and take precedence over them.
+    pub fn env<I, K, V>(mut self, vars: I) -> Self
+    where
+        I: IntoIterator<Item = (K, V)>,
+        K: Into<String>,
+        V: Into<String>,
+    {
+        self.env = Some(
+            vars.into_iter()
+                .map(|(k, v)| (k.into(), v.into()))
+                .collect(),
+        );
+        self
+    }
+
+    /// Add an environment variable readable with `env:`
+    pub fn env_var(mut self, key: impl Into<String>, value: impl Into<String>) -> Self {
+        self.env
+            .get_or_insert_default()
+            .insert(key.into(), value.into());
+        self
+    }
+
+    /// Set whether the evaluator inherits the environment of this process.
+    ///
+    /// Every variable is inherited by default, including secrets.
+    /// Turning it off makes evaluations reproducible, as `env:` only sees variables set with [`EvaluatorOptions::env_var`].
+    ///
+    /// # Example
+    /// ```no_run
+    /// use rpkl::EvaluatorOptions;
+    ///
+    /// let options = EvaluatorOptions::new()
+    ///     .inherit_env(false)
+    ///     .env_var("APP_ENV", "test");
+    /// ```
+    pub fn inherit_env(mut self, inherit: bool) -> Self {
+        self.inherit_env = Some(if inherit {
+            InheritEnv::All
+        } else {
+            InheritEnv::Nothing
+        });
+        self
+    }
+
+    /// Only inherit the listed variables of this process' environment, see [`InheritEnv::Only`]
+    pub fn inherit_env_vars(mut self, names: impl IntoIterator<Item = impl Into<String>>) -> Self {
+        self.inherit_env = Some(InheritEnv::Only(
+            names.into_iter().map(Into::into).collect(),
+        ));
+        self
+    }
+
     /// Set the timeout in seconds for evaluating a source module.
     pub fn timeout_seconds(mut self, seconds: u64) -> Self {
         self.timeout_seconds = Some(seconds);
         self
     }
+
+    /// Set the logger that receives messages logged by the pkl server during evaluation,
+    /// e.g. from calls to `trace()` or deprecation warnings.
+    ///
+    /// If no logger is set, messages are forwarded to `tracing` when the `trace` feature is enabled.
+    ///
+    /// # Example
+    /// ```no_run
+    /// use rpkl::EvaluatorOptions;
+    /// use rpkl::api::logger::LogMessage;
+    ///
+    /// let options = EvaluatorOptions::new()
+    ///     .logger(|msg: &LogMessage| eprintln!("[{:?}] {} ({})", msg.level, msg.message, msg.frame_uri));
+    /// ```
+    pub fn logger(mut self, logger: impl PklLogger + 'static) -> Self {
+        self.logger = Some(Arc::new(logger));
+        self
+    }
+
+    /// Add a resource reader to an [`AsyncEvaluator`](crate::api::AsyncEvaluator)
+    #[cfg(feature = "async")]
+    pub fn add_async_resource_reader(
+        mut self,
+        reader: impl AsyncPklResourceReader + 'static,
+    ) -> Self {
+        self.async_resource_readers
+            .get_or_insert_default()
+            .push(Arc::new(reader));
+        self
+    }
+
+    /// Add a module reader to an [`AsyncEvaluator`](crate::api::AsyncEvaluator)
+    #[cfg(feature = "async")]
+    pub fn add_async_module_reader(mut self, reader: impl AsyncPklModuleReader + 'static) -> Self {
+        self.async_module_readers
+            .get_or_insert_default()
+            .push(Arc::new(reader));
+        self
+    }
+
+    /// Set the logger of an [`AsyncEvaluator`](crate::api::AsyncEvaluator),
+    /// see [`EvaluatorOptions::logger`]
+    #[cfg(feature = "async")]
+    pub fn async_logger(mut self, logger: impl PklLogger + Send + Sync + 'static) -> Self {
+        self.async_logger = Some(Arc::new(logger));
+        self
+    }
 }
 
+/// Evaluates pkl modules on a `pkl server` process.
+///
+/// Created with [`Evaluator::new`], which starts a process for this evaluator alone,
+/// or with [`EvaluatorManager::new_evaluator`] to share a process between several evaluators.
 pub struct Evaluator {
     evaluator_id: i64,
-    stdin: std::process::ChildStdin,
-    stdout: std::process::ChildStdout,
-    client_module_readers: Vec<Arc<dyn PklModuleReader>>,
-    client_resource_readers: Vec<Arc<dyn PklResourceReader>>,
+    server: Rc<RefCell<PklServer>>,
 }
 
 impl Evaluator {
@@ -278,231 +548,301 @@ impl Evaluator {
     /// Create a new evaluator with the given options
     /// This will start a new pkl process and create an evaluator
     /// The evaluator will be closed when it is dropped
+    ///
+    /// Use an [`EvaluatorManager`] to create several evaluators on the same process.
     /// # Errors
     /// - Returns an error if the pkl process fails to start or if the evaluator fails to be created
     pub fn new_from_options(options: EvaluatorOptions) -> Result<Self> {
-        let mut child = start_pkl(false).map_err(|_e| Error::PklProcessStart)?;
-        let child_stdin = child.stdin.as_mut().context("failed to get stdin")?;
-        let mut child_stdout = child.stdout.take().context("failed to get stdout")?;
-
-        let evaluator_message = CreateEvaluator::from(&options);
-
-        let serialized_request = evaluator_message.encode_msg()?;
-
-        let create_eval_response =
-            pkl_send_msg_child(child_stdin, &mut child_stdout, &serialized_request)
-                .map_err(|_e| Error::PklSend)?;
+        let command = options.pkl_command.clone().unwrap_or_default();
+        EvaluatorManager::with_command(command)?.new_evaluator(options)
+    }
 
-        let Some(map) = create_eval_response.response.as_map() else {
-            return Err(Error::PklMalformedResponse {
-                message: "expected map in response".to_string(),
-            });
+    pub(crate) fn create(
+        server: Rc<RefCell<PklServer>>,
+        options: EvaluatorOptions,
+    ) -> Result<Self> {
+        let project = match &options.project_dir {
+            Some(dir) => Some(Self::load_project(&server, dir)?),
+            None => None,
         };
-
-        let Some(evaluator_id) = map
-            .iter()
-            .find(|(k, _v)| k.as_str() == Some("evaluatorId"))
-            .and_then(|(_, v)| v.as_i64())
-        else {
-            return Err(Error::PklMalformedResponse {
-                message: "expected evaluatorId in CreateEvaluator response".into(),
-            });
-        };
-
+        let evaluator_id = server
+            .borrow_mut()
+            .create_evaluator(options, project.as_ref())?;
         Ok(Evaluator {
             evaluator_id,
-            stdin: child.stdin.take().context("failed to get stdin")?,
-            stdout: child_stdout,
-            client_module_readers: options.client_module_readers.unwrap_or_default(),
-            client_resource_readers: options.client_resource_readers.unwrap_or_default(),
+            server,
         })
     }
 
+    /// Evaluates the `PklProject` file in `dir` with a temporary evaluator on the same server
+    fn load_project(server: &Rc<RefCell<PklServer>>, dir: &std::path::Path) -> Result<PklProject> {
+        let mut evaluator = Evaluator::create(server.clone(), EvaluatorOptions::default())?;
+        let pkl_mod = evaluator.evaluate_module(PklProject::file(dir))?;
+        PklProject::from_module(pkl_mod, dir)
+    }
+
+    /// The version of the pkl server, or `None` if it couldn't be determined
+    /// # Errors
+    /// - Returns an error if `pkl --version` fails to run
+    pub fn pkl_version(&self) -> Result<Option<PklVersion>> {
+        self.server.borrow_mut().version()
+    }
+
     /// Evaluate a pkl module
     /// This will send the module to the pkl process and return the result
     /// # Errors
     /// - Returns an error if the pkl process fails to evaluate the module or if the module is malformed
     /// - If the provided path is not does not exist
     pub fn evaluate_module(&mut self, path: impl AsRef<std::path::Path>) -> Result<PklMod> {
-        let evaluator_id = self.id();
-        let mut child_stdin = &mut self.stdin;
-        let mut child_stdout = &mut self.stdout;
-
-        let path = utils::canonicalize(path)
-            .map_err(|_e| Error::Message("failed to canonicalize pkl module path".into()))?;
-
-        let msg = EvaluateRequest {
-            request_id: OUTGOING_MESSAGE_REQUEST_ID,
-            evaluator_id,
-            module_uri: format!(
-                "file://{}",
-                path.to_str().context("Path is not valid utf8")?
-            ),
-        }
-        .encode_msg()?;
-
-        // send the evaluate request
-        child_stdin.write_all(&msg)?;
-        child_stdin.flush()?;
-
-        // handle any requests until we get the evaluate response
-        let mut eval_res = None;
-        while let Ok(msg) = recv_msg(&mut child_stdout) {
-            if msg.header == EVALUATE_RESPONSE {
-                eval_res = Some(msg);
-                break;
-            }
-
-            match msg.header {
-                READ_RESOURCE_REQUEST => {
-                    handle_read_resource(&self.client_resource_readers, &msg, &mut child_stdin)?;
-                }
-                READ_MODULE_REQUEST => {
-                    handle_read_module(&self.client_module_readers, &msg, &mut child_stdin)?;
-                }
-                LIST_MODULES_REQUEST => {
-                    handle_list_modules(&self.client_module_readers, &msg, &mut child_stdin)?;
-                }
-                LIST_RESOURCES_REQUEST => {
-                    handle_list_resources(&self.client_resource_readers, &msg, &mut child_stdin)?;
-                }
-                _ => {
-                    return Err(Error::Message(format!(
-                        "unknown request from pkl server: 0x{:x}",
-                        msg.header
-                    )));
-                }
-            }
-        }
-
-        let Some(eval_res) = eval_res else {
-            return Err(Error::Message("failed to evaluate pkl module".into()));
-        };
-
-        let Some(res) = eval_res.response.as_map() else {
-            return Err(Error::PklMalformedResponse {
-                message: "expected map in evaluate response".into(),
-            });
-        };
-        let Some((_, result)) = res.iter().find(|(k, _v)| k.as_str() == Some("result")) else {
-            // pkl module evaluation failed, return the error message from pkl
-            if let Some((_, error)) = res.iter().find(|(k, _v)| k.as_str() == Some("error")) {
-                return Err(Error::PklServerError {
-                    pkl_error: error
-                        .as_str()
-                        .context("error message from pkl should be valid utf8")?
-                        .to_owned(),
-                });
-            }
-
-            return Err(Error::PklMalformedResponse {
-                message: "expected result or error in evaluate response".into(),
-            });
-        };
+        self.evaluate_uri(module_uri(path)?)
+    }
 
-        let slice = result
-            .as_slice()
-            .context(format!("expected result to be a slice, got: {result:?}"))?;
-        let rmpv_ast: rmpv::Value = rmpv::decode::value::read_value(&mut &slice[..])?;
+    /// Evaluate the module at the given URI, e.g. `https://example.com/config.pkl`,
+    /// `package://example.com/mypackage@1.0.0#/config.pkl` or a URI handled by one of the client module readers.
+    ///
+    /// The scheme of the URI must be allowed by the evaluator.
+    /// # Errors
+    /// - Returns an error if the pkl process fails to evaluate the module or if the module is malformed
+    pub fn evaluate_uri(&mut self, uri: impl Into<String>) -> Result<PklMod> {
+        self.evaluate(uri.into(), None, None)
+            .and_then(|ast| decode_module(&ast))
+    }
 
-        _debug!("rmpv pkl module: {:#?}", rmpv_ast);
-        let pkl_mod = decode_module(&rmpv_ast)?;
+    /// Evaluate pkl source text without reading it from disk.
+    ///
+    /// `uri` is the URI the module is evaluated as; relative imports and reads are resolved against it.
+    /// Use something like `repl:text` if the module doesn't need to resolve relative URIs.
+    /// # Errors
+    /// - Returns an error if the pkl process fails to evaluate the module or if the module is malformed
+    pub fn evaluate_text(
+        &mut self,
+        source: impl Into<String>,
+        uri: impl Into<String>,
+    ) -> Result<PklMod> {
+        self.evaluate(uri.into(), Some(source.into()), None)
+            .and_then(|ast| decode_module(&ast))
+    }
 
-        Ok(pkl_mod)
+    /// Evaluate the pkl module at `path` with some of its properties overridden, see [`Evaluator::evaluate_uri_with_overrides`]
+    /// # Errors
+    /// - Returns an error if an override path is malformed, or if the pkl process fails to evaluate the module
+    /// - If the provided path does not exist
+    pub fn evaluate_module_with_overrides<K>(
+        &mut self,
+        path: impl AsRef<std::path::Path>,
+        overrides: impl IntoIterator<Item = (K, PklValue)>,
+    ) -> Result<PklMod>
+    where
+        K: AsRef<str>,
+    {
+        self.evaluate_uri_with_overrides(module_uri(path)?, overrides)
     }
-}
 
-impl Drop for Evaluator {
-    fn drop(&mut self) {
-        let child_stdin = &mut self.stdin;
+    /// Evaluate the module at `uri` with the properties at the dotted paths in `overrides` set to new values.
+    ///
+    /// Instead of merging values after evaluation, this evaluates a module amending `uri`:
+    ///
+    /// ```ignore
+    /// amends "file:///app/config.pkl"
+    ///
+    /// database {
+    ///   port = 5433
+    /// }
+    /// ```
+    ///
+    /// so type constraints and computed properties of the base module apply to the new values.
+    /// Values are written as pkl literals of the type declared for each property in the base module,
+    /// so `1` overriding a `Float` is written as `1.0`, `"5.s"` overriding a `Duration` as `5.s`
+    /// and a list overriding a `List<T>` as `List(...)` rather than `new Listing { ... }`.
+    /// Members of object and map values are written the same way, using the types declared for them,
+    /// while elements of listings and mappings are written as-is.
+    /// The declared types are resolved through `pkl:reflect` in a separate evaluation,
+    /// which is skipped when no value could be written differently, e.g. for booleans and plain strings.
+    /// String overrides in the form `key.path=value` can be parsed with [`parse_override`](crate::config::parse_override).
+    ///
+    /// # Example
+    /// ```no_run
+    /// # fn main() -> Result<(), rpkl::Error> {
+    /// let mut evaluator = rpkl::api::Evaluator::new()?;
+    /// let port = rpkl::config::parse_override("database.port=5433")?;
+    /// let config = evaluator.evaluate_module_with_overrides("config.pkl", [port])?;
+    /// #    Ok(())
+    /// # }
+    /// ```
+    /// # Errors
+    /// - Returns an error if an override path is malformed or indexes into a listing or mapping (`servers[0].port`),
+    ///   or if the pkl process fails to evaluate the module or to resolve the declared types
+    /// - Returns [`Error::UnsupportedPklVersion`] if an override contains bytes and the pkl version predates the `Bytes` type
+    pub fn evaluate_uri_with_overrides<K>(
+        &mut self,
+        uri: impl Into<String>,
+        overrides: impl IntoIterator<Item = (K, PklValue)>,
+    ) -> Result<PklMod>
+    where
+        K: AsRef<str>,
+    {
+        let uri = uri.into();
+        let overrides: Vec<(K, PklValue)> = overrides.into_iter().collect();
+        let paths = overrides
+            .iter()
+            .map(|(path, _)| split_path(path.as_ref()))
+            .collect::<Result<Vec<_>>>()?;
+
+        let mut overrides = paths
+            .into_iter()
+            .zip(&overrides)
+            .map(|(path, (_, value))| (path, Node::from(value)))
+            .collect::<Vec<_>>();
+
+        let mut typed = vec![];
+        for (path, node) in &overrides {
+            node.typed_paths(&mut owned_path(path), &mut typed);
+        }
+        if !typed.is_empty() {
+            let types = self.declared_types(&uri, &typed)?;
+            let types: HashMap<Vec<String>, String> = typed.into_iter().zip(types).collect();
+            overrides = overrides
+                .into_iter()
+                .map(|(path, node)| {
+                    let node = node.into_declared(&mut owned_path(&path), &types);
+                    (path, node)
+                })
+                .collect();
+        }
 
-        let Ok(msg) = CloseEvaluator {
-            evaluator_id: self.evaluator_id,
+        if overrides.iter().any(|(_, node)| node.contains_bytes()) {
+            check_version(
+                self.pkl_version()?,
+                &[("Bytes overrides", PklVersion::BYTES)],
+            )?;
         }
-        .encode_msg() else {
-            eprintln!("failed to encode close evaluator message");
-            return;
-        };
 
-        if let Err(e) = pkl_send_msg_one_way(child_stdin, &msg) {
-            eprintln!("failed to close evaluator: {e}");
-        };
+        let source = render_amends(&uri, overrides);
+        utils::macros::_trace!("evaluating amends module {source}");
+        self.evaluate_text(source, REPL_TEXT_URI)
     }
-}
 
-fn start_pkl(pkl_debug: bool) -> Result<Child> {
-    let mut command = Command::new("pkl");
+    /// The simple names of the types declared for `paths` in the module at `uri`, `""` if a path has no declared type.
+    ///
+    /// Override values are written as pkl literals of these types, see [`Node::into_declared`].
+    fn declared_types(&mut self, uri: &str, paths: &[Vec<String>]) -> Result<Vec<String>> {
+        let source = render_type_query(uri, paths);
+        let ast = self.evaluate(REPL_TEXT_URI.into(), Some(source), Some("types".into()))?;
+        match decode_value(&ast)? {
+            PklValue::List(types) if types.len() == paths.len() => Ok(types
+                .into_iter()
+                .map(|t| t.as_str().unwrap_or_default().to_string())
+                .collect()),
+            value => Err(Error::Message(format!(
+                "failed to resolve the declared types of overrides, got {value:?}"
+            ))),
+        }
+    }
 
-    command
-        .arg("server")
-        .stdin(Stdio::piped())
-        .stdout(Stdio::piped());
+    /// Evaluate a single expression within the pkl module at `path`, e.g. `output.text` or `database.port`.
+    ///
+    /// Only the result of the expression is sent back from the pkl process, which avoids decoding the whole module
+    /// when only a subtree of it is needed.
+    /// # Errors
+    /// - Returns an error if the pkl process fails to evaluate the expression or if the result is malformed
+    /// - If the provided path does not exist
+    pub fn evaluate_expression(
+        &mut self,
+        path: impl AsRef<std::path::Path>,
+        expr: impl Into<String>,
+    ) -> Result<PklValue> {
+        self.evaluate_expression_uri(module_uri(path)?, expr)
+    }
 
-    if pkl_debug {
-        command.env("PKL_DEBUG", "1");
+    /// Evaluate a single expression within the module at the given URI.
+    ///
+    /// See [`Evaluator::evaluate_expression`] and [`Evaluator::evaluate_uri`].
+    /// # Errors
+    /// - Returns an error if the pkl process fails to evaluate the expression or if the result is malformed
+    pub fn evaluate_expression_uri(
+        &mut self,
+        uri: impl Into<String>,
+        expr: impl Into<String>,
+    ) -> Result<PklValue> {
+        self.evaluate(uri.into(), None, Some(expr.into()))
+            .and_then(|ast| decode_value(&ast))
     }
 
-    let child = command.spawn()?;
+    fn evaluate(
+        &mut self,
+        module_uri: String,
+        module_text: Option<String>,
+        expr: Option<String>,
+    ) -> Result<rmpv::Value> {
+        let mut server = self.server.borrow_mut();
+        let request_id = server.next_request_id();
+        server.evaluate(EvaluateRequest {
+            request_id,
+            evaluator_id: self.id(),
+            module_uri,
+            module_text,
+            expr,
+        })
+    }
+}
 
-    Ok(child)
+fn owned_path(path: &[&str]) -> Vec<String> {
+    path.iter().map(|segment| segment.to_string()).collect()
 }
 
-fn pkl_send_msg_one_way(
-    child_stdin: &mut std::process::ChildStdin,
+/// The `file:` URI of the module at `path`
+pub(crate) fn module_uri(path: impl AsRef<std::path::Path>) -> Result<String> {
+    let path = utils::canonicalize(path)
+        .map_err(|_e| Error::Message("failed to canonicalize pkl module path".into()))?;
 
-    serialized_request: &[u8],
-) -> Result<()> {
-    child_stdin.write_all(serialized_request)?;
-    child_stdin.flush()?;
-    Ok(())
+    Ok(format!(
+        "file://{}",
+        path.to_str().context("Path is not valid utf8")?
+    ))
 }
 
-fn decode_pkl_message(value: &rmpv::Value) -> Option<PklServerMessage> {
-    let decoded_array = value.as_array()?;
-    let header = decoded_array.first()?.as_u64()?;
-    let response = decoded_array.get(1)?.to_owned();
-    Some(PklServerMessage { header, response })
+impl Drop for Evaluator {
+    fn drop(&mut self) {
+        if let Err(e) = self.server.borrow_mut().close_evaluator(self.evaluator_id) {
+            eprintln!("failed to close evaluator: {e}");
+        }
+    }
 }
 
-fn pkl_send_msg_child(
-    child_stdin: &mut std::process::ChildStdin,
-    child_stdout: &mut std::process::ChildStdout,
-    serialized_request: &[u8],
-) -> Result<PklServerMessage> {
-    child_stdin.write_all(serialized_request)?;
-    child_stdin.flush()?;
-
-    // TODO: refactor this to use decode_pkl_message
-    match rmpv::decode::read_value(child_stdout) {
-        Ok(response) => {
-            let decoded_array = response
-                .as_array()
-                .context("expected server response to be formatted as an array")?;
-            let first_element = decoded_array.first().context(
-                "malformed server response, received empty array, expected array of length 2",
-            )?;
-            let message_header_hex = first_element.as_u64().context(
-                "malformed server response, expected first element to be a u64 representing the message header",
-            )?;
-            let second = decoded_array.get(1).context(
-                "malformed server response, expected second element to be a u64 representing the message header",
-            )?;
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::internal::msgapi::outgoing::CreateEvaluator;
+
+    #[test]
+    fn filter_inherited_env() {
+        let vars = || {
+            [
+                ("HOME", "/root"),
+                ("APP_ENV", "dev"),
+                ("AWS_SECRET", "hunter2"),
+            ]
+            .map(|(k, v)| (k.to_string(), v.to_string()))
+        };
 
-            Ok(PklServerMessage {
-                header: message_header_hex,
-                response: second.to_owned(),
-            })
-        }
-        Err(e) => Err(Error::Message(format!(
-            "\nfailed to decode value from pkl process: {e:?}",
-        ))),
+        let only = InheritEnv::Only(vec!["HOME".into(), "APP_*".into()]).filter(vars());
+        assert_eq!(only.len(), 2);
+        assert!(only.contains_key("HOME") && only.contains_key("APP_ENV"));
+
+        assert!(InheritEnv::Nothing.filter(vars()).is_empty());
+        assert_eq!(InheritEnv::All.filter(vars()).len(), 3);
     }
-}
 
-pub(crate) fn recv_msg<R: Read>(reader: &mut R) -> std::result::Result<PklServerMessage, Error> {
-    let data = rmpv::decode::read_value(reader)?;
-    let pkl_msg = decode_pkl_message(&data).ok_or(Error::PklMalformedResponse {
-        message: "Failed to decode pkl message format".to_string(),
-    })?;
-    Ok(pkl_msg)
+    #[test]
+    fn explicit_env_only() {
+        let options = EvaluatorOptions::new()
+            .inherit_env(false)
+            .env_var("APP_ENV", "test");
+        let message = CreateEvaluator::from(&options);
+
+        assert_eq!(
+            message.env,
+            Some(HashMap::from([("APP_ENV".into(), "test".into())]))
+        );
+    }
 }
@@ -19,7 +19,7 @@ use crate::{
 
 use crate::{
     api::{
-        evaluator::recv_msg,
+        manager::recv_msg,
         reader::{
             handle_list_modules, handle_list_resources, handle_read_module, handle_read_resource,
         },
@@ -196,6 +196,8 @@ impl ExternalReaderRuntime {
             _info!("Registered module reader: {:?}", _reader.scheme());
         }
 
+        // `_warn!` is compiled out without the `trace` feature
+        #[allow(clippy::while_let_loop)]
         loop {
             let Ok(pkl_msg) = recv_msg(&mut stdin) else {
                 _warn!("Failed to decode message");
@@ -0,0 +1,153 @@
+use std::sync::Arc;
+
+use crate::{
+    api::reader::extract_field,
+    context::Context as _,
+    internal::msgapi::incoming::PklServerMessage,
+    utils::macros::{_trace, _warn},
+};
+
+/// The level of a message logged by the pkl server.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum LogLevel {
+    /// Messages produced by calls to `trace()`
+    Trace,
+    /// Warnings, such as the use of deprecated members
+    Warn,
+}
+
+impl From<i64> for LogLevel {
+    fn from(level: i64) -> Self {
+        match level {
+            0 => LogLevel::Trace,
+            // the spec only defines 0 (trace) and 1 (warn),
+            // anything else is treated as a warning so it isn't silently dropped
+            _ => LogLevel::Warn,
+        }
+    }
+}
+
+/// A message logged by the pkl server during evaluation.
+///
+/// Code: 0x100
+///
+/// Type: Server One Way
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct LogMessage {
+    /// The level of the message.
+    pub level: LogLevel,
+
+    /// The message to be logged.
+    pub message: String,
+
+    /// A string representing the source location within Pkl code that produced this log output.
+    pub frame_uri: String,
+}
+
+impl TryFrom<&PklServerMessage> for LogMessage {
+    type Error = Box<dyn std::error::Error>;
+
+    fn try_from(msg: &PklServerMessage) -> Result<Self, Self::Error> {
+        let response = msg
+            .response
+            .as_map()
+            .context("expected map for `LogMessage` message")?;
+
+        let level: i64 = extract_field(response, "level")?;
+        let message: String = extract_field(response, "message")?;
+        let frame_uri: String = extract_field(response, "frameUri")?;
+
+        Ok(LogMessage {
+            level: level.into(),
+            message,
+            frame_uri,
+        })
+    }
+}
+
+/// Receives messages logged by the pkl server, e.g. from `trace()` or deprecation warnings.
+///
+/// Implemented for any `Fn(&LogMessage)`, so a closure can be passed to [`EvaluatorOptions::logger`].
+///
+/// [`EvaluatorOptions::logger`]: crate::EvaluatorOptions::logger
+pub trait PklLogger {
+    fn log(&self, message: &LogMessage);
+}
+
+impl<F: Fn(&LogMessage)> PklLogger for F {
+    fn log(&self, message: &LogMessage) {
+        self(message);
+    }
+}
+
+/// Used when no logger is registered.
+/// Forwards messages to `tracing` when the `trace` feature is enabled, otherwise they are discarded.
+pub(crate) fn default_log(_message: &LogMessage) {
+    match _message.level {
+        LogLevel::Trace => {
+            _trace!("pkl: {} ({})", _message.message, _message.frame_uri);
+        }
+        LogLevel::Warn => {
+            _warn!("pkl: {} ({})", _message.message, _message.frame_uri);
+        }
+    }
+}
+
+pub(crate) fn handle_log_message(
+    logger: Option<&Arc<dyn PklLogger>>,
+    msg: &PklServerMessage,
+) -> Result<(), Box<dyn std::error::Error>> {
+    let message = LogMessage::try_from(msg)?;
+
+    match logger {
+        Some(logger) => logger.log(&message),
+        None => default_log(&message),
+    }
+
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::sync::Mutex;
+
+    fn log_message(level: i64) -> PklServerMessage {
+        PklServerMessage {
+            header: crate::internal::msgapi::codes::LOG_MESSAGE,
+            response: rmpv::Value::Map(vec![
+                ("evaluatorId".into(), 1.into()),
+                ("level".into(), level.into()),
+                ("message".into(), "hello".into()),
+                ("frameUri".into(), "file:///config.pkl".into()),
+            ]),
+        }
+    }
+
+    #[test]
+    fn decode_log_message() {
+        let message = LogMessage::try_from(&log_message(1)).unwrap();
+        assert_eq!(
+            message,
+            LogMessage {
+                level: LogLevel::Warn,
+                message: "hello".into(),
+                frame_uri: "file:///config.pkl".into(),
+            }
+        );
+    }
+
+    #[test]
+    fn routes_to_logger() {
+        let logged = Arc::new(Mutex::new(vec![]));
+        let sink = logged.clone();
+        let logger: Arc<dyn PklLogger> =
+            Arc::new(move |m: &LogMessage| sink.lock().unwrap().push(m.clone()));
+
+        handle_log_message(Some(&logger), &log_message(0)).unwrap();
+
+        let logged = logged.lock().unwrap();
+        assert_eq!(logged.len(), 1);
+        assert_eq!(logged[0].level, LogLevel::Trace);
+    }
+}
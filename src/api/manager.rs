@@ -0,0 +1,497 @@
+use std::{
+    cell::RefCell,
+    collections::HashMap,
+    io::{Read, Write},
+    process::{Child, Stdio},
+    rc::Rc,
+    sync::Arc,
+};
+
+use crate::{
+    api::{
+        evaluator::{Evaluator, EvaluatorOptions},
+        reader::{
+            extract_field, handle_list_modules, handle_list_resources, handle_read_module,
+            handle_read_resource,
+        },
+    },
+    context::Context,
+    error::{Error, EvalError, Result},
+    internal::msgapi::{
+        PklMessage,
+        codes::{
+            CREATE_EVALUATOR_RESPONSE, EVALUATE_RESPONSE, LIST_MODULES_REQUEST,
+            LIST_RESOURCES_REQUEST, LOG_MESSAGE, READ_MODULE_REQUEST, READ_RESOURCE_REQUEST,
+        },
+        incoming::PklServerMessage,
+        outgoing::{CloseEvaluator, CreateEvaluator, EvaluateRequest},
+    },
+    utils::macros::{_debug, _warn},
+};
+
+use super::command::{PklCommand, PklVersion, check_version, required_versions};
+use super::logger::{PklLogger, handle_log_message};
+use super::project::PklProject;
+use super::reader::{PklModuleReader, PklResourceReader};
+
+/// Owns a single `pkl server` process and creates evaluators on it.
+///
+/// Starting the pkl server is the slow part of creating an [`Evaluator`],
+/// so applications evaluating several modules with different options can share one process
+/// instead of starting one per evaluator with [`Evaluator::new_from_options`].
+///
+/// Each evaluator gets its own id from the server, and every request is sent with a unique request id,
+/// so responses and reader callbacks are routed to the evaluator they belong to.
+/// The server process is stopped once the manager and all of its evaluators are dropped.
+///
+/// # Example
+/// ```no_run
+/// use rpkl::{EvaluatorOptions, api::EvaluatorManager};
+///
+/// # fn main() -> Result<(), rpkl::Error> {
+/// let manager = EvaluatorManager::new()?;
+/// let mut staging = manager.new_evaluator(EvaluatorOptions::new().property("env", "staging"))?;
+/// let mut prod = manager.new_evaluator(EvaluatorOptions::new().property("env", "prod"))?;
+///
+/// let staging_config = staging.evaluate_module("config.pkl")?;
+/// let prod_config = prod.evaluate_module("config.pkl")?;
+/// #    Ok(())
+/// # }
+/// ```
+pub struct EvaluatorManager {
+    server: Rc<RefCell<PklServer>>,
+}
+
+impl EvaluatorManager {
+    /// Start a new pkl server process, using `PKL_EXEC` or `pkl` from `PATH`
+    /// # Errors
+    /// - Returns an error if the pkl process fails to start
+    pub fn new() -> Result<Self> {
+        Self::with_command(PklCommand::from_env())
+    }
+
+    /// Start a new pkl server process with the given executable
+    /// # Errors
+    /// - Returns an error if the pkl process fails to start
+    pub fn with_command(command: PklCommand) -> Result<Self> {
+        Ok(EvaluatorManager {
+            server: Rc::new(RefCell::new(PklServer::start(&command)?)),
+        })
+    }
+
+    /// The version of the pkl server, or `None` if it couldn't be determined
+    /// # Errors
+    /// - Returns an error if `pkl --version` fails to run
+    pub fn pkl_version(&self) -> Result<Option<PklVersion>> {
+        self.server.borrow_mut().version()
+    }
+
+    /// Create a new evaluator on this manager's pkl server
+    ///
+    /// The evaluator is closed when it is dropped, the server keeps running for the other evaluators.
+    /// # Errors
+    /// - Returns an error if the evaluator fails to be created
+    pub fn new_evaluator(&self, options: EvaluatorOptions) -> Result<Evaluator> {
+        Evaluator::create(self.server.clone(), options)
+    }
+}
+
+/// Readers and logger of an evaluator, used to answer the requests the server sends for it
+struct EvaluatorHandlers {
+    client_module_readers: Vec<Arc<dyn PklModuleReader>>,
+    client_resource_readers: Vec<Arc<dyn PklResourceReader>>,
+    logger: Option<Arc<dyn PklLogger>>,
+}
+
+/// Connection to a `pkl server` process, shared by the evaluators created on it.
+pub(crate) struct PklServer {
+    child: Option<Child>,
+    stdin: Box<dyn Write>,
+    stdout: Box<dyn Read>,
+    next_request_id: u64,
+    /// Used to run `pkl --version`, `None` when not talking to a real process
+    command: Option<PklCommand>,
+    /// Reported by `pkl --version` the first time it's needed
+    version: Option<Option<PklVersion>>,
+    evaluators: HashMap<i64, EvaluatorHandlers>,
+    /// Responses that arrived while waiting for a different request
+    pending: HashMap<i64, PklServerMessage>,
+}
+
+impl PklServer {
+    fn start(command: &PklCommand) -> Result<Self> {
+        let mut child = start_pkl(command, false)?;
+        let stdin = child.stdin.take().context("failed to get stdin")?;
+        let stdout = child.stdout.take().context("failed to get stdout")?;
+
+        let mut server = PklServer::new(stdin, stdout);
+        server.child = Some(child);
+        server.command = Some(command.clone());
+        Ok(server)
+    }
+
+    fn new(stdin: impl Write + 'static, stdout: impl Read + 'static) -> Self {
+        PklServer {
+            child: None,
+            stdin: Box::new(stdin),
+            stdout: Box::new(stdout),
+            next_request_id: 1,
+            command: None,
+            version: None,
+            evaluators: HashMap::new(),
+            pending: HashMap::new(),
+        }
+    }
+
+    /// Runs `pkl --version` the first time it's needed
+    pub(crate) fn version(&mut self) -> Result<Option<PklVersion>> {
+        if let Some(version) = self.version {
+            return Ok(version);
+        }
+        let version = match &self.command {
+            Some(command) => command.version()?,
+            None => None,
+        };
+        self.version = Some(version);
+        Ok(version)
+    }
+
+    pub(crate) fn next_request_id(&mut self) -> u64 {
+        let id = self.next_request_id;
+        self.next_request_id += 1;
+        id
+    }
+
+    fn send(&mut self, msg: &[u8]) -> Result<()> {
+        self.stdin.write_all(msg)?;
+        self.stdin.flush()?;
+        Ok(())
+    }
+
+    /// Creates an evaluator and registers its readers, returning the evaluator id
+    pub(crate) fn create_evaluator(
+        &mut self,
+        options: EvaluatorOptions,
+        project: Option<&PklProject>,
+    ) -> Result<i64> {
+        #[cfg(feature = "async")]
+        if options.async_module_readers.is_some() || options.async_resource_readers.is_some() {
+            return Err(Error::Message(
+                "async readers can only be used with an `AsyncEvaluator`".into(),
+            ));
+        }
+
+        let required = required_versions(&options);
+        if !required.is_empty() {
+            check_version(self.version()?, &required)?;
+        }
+
+        let request_id = self.next_request_id();
+        let mut message = CreateEvaluator::from(&options);
+        message.request_id = request_id;
+        if let Some(project) = project {
+            project.apply(&mut message);
+        }
+        self.send(&message.encode_msg()?)?;
+
+        let response = self.wait_for_response(request_id)?;
+        let evaluator_id = decode_create_evaluator_response(&response)?;
+
+        self.evaluators.insert(
+            evaluator_id,
+            EvaluatorHandlers {
+                client_module_readers: options.client_module_readers.unwrap_or_default(),
+                client_resource_readers: options.client_resource_readers.unwrap_or_default(),
+                logger: options.logger,
+            },
+        );
+
+        Ok(evaluator_id)
+    }
+
+    pub(crate) fn close_evaluator(&mut self, evaluator_id: i64) -> Result<()> {
+        self.evaluators.remove(&evaluator_id);
+        self.send(&CloseEvaluator { evaluator_id }.encode_msg()?)
+    }
+
+    /// Sends the evaluate request and handles any reader requests from the pkl process
+    /// until the evaluation result is received.
+    pub(crate) fn evaluate(&mut self, request: EvaluateRequest) -> Result<rmpv::Value> {
+        let request_id = request.request_id;
+        self.send(&request.encode_msg()?)?;
+
+        let response = self.wait_for_response(request_id)?;
+        decode_evaluate_response(&response)
+    }
+
+    /// Reads messages from the server until the response to `request_id` arrives,
+    /// answering reader requests and logging messages for any evaluator along the way.
+    fn wait_for_response(&mut self, request_id: u64) -> Result<PklServerMessage> {
+        // request ids are sent as unsigned and read back as signed integers
+        let request_id = request_id as i64;
+        if let Some(msg) = self.pending.remove(&request_id) {
+            return Ok(msg);
+        }
+
+        loop {
+            let msg = recv_msg(&mut self.stdout)?;
+
+            match msg.header {
+                EVALUATE_RESPONSE | CREATE_EVALUATOR_RESPONSE => {
+                    let id = message_field(&msg, "requestId")?;
+                    if id == request_id {
+                        return Ok(msg);
+                    }
+                    self.pending.insert(id, msg);
+                }
+                READ_RESOURCE_REQUEST
+                | READ_MODULE_REQUEST
+                | LIST_MODULES_REQUEST
+                | LIST_RESOURCES_REQUEST
+                | LOG_MESSAGE => self.handle_request(&msg)?,
+                _ => {
+                    return Err(Error::Message(format!(
+                        "unknown request from pkl server: 0x{:x}",
+                        msg.header
+                    )));
+                }
+            }
+        }
+    }
+
+    /// Routes a request from the server to the readers of the evaluator it was sent for
+    fn handle_request(&mut self, msg: &PklServerMessage) -> Result<()> {
+        let evaluator_id = message_field(msg, "evaluatorId")?;
+        let handlers = self.evaluators.get(&evaluator_id);
+        if handlers.is_none() {
+            _warn!("request for unknown evaluator: {evaluator_id}");
+        }
+
+        let module_readers = handlers.map_or(&[][..], |h| &h.client_module_readers);
+        let resource_readers = handlers.map_or(&[][..], |h| &h.client_resource_readers);
+        let stdin = &mut self.stdin;
+
+        match msg.header {
+            READ_RESOURCE_REQUEST => handle_read_resource(resource_readers, msg, stdin)?,
+            READ_MODULE_REQUEST => handle_read_module(module_readers, msg, stdin)?,
+            LIST_MODULES_REQUEST => handle_list_modules(module_readers, msg, stdin)?,
+            LIST_RESOURCES_REQUEST => handle_list_resources(resource_readers, msg, stdin)?,
+            LOG_MESSAGE => handle_log_message(handlers.and_then(|h| h.logger.as_ref()), msg)?,
+            _ => {}
+        }
+        Ok(())
+    }
+}
+
+impl Drop for PklServer {
+    fn drop(&mut self) {
+        if let Some(child) = self.child.as_mut() {
+            let _ = child.kill();
+            let _ = child.wait();
+        }
+    }
+}
+
+/// Returns the evaluator id from a `CreateEvaluatorResponse`
+pub(crate) fn decode_create_evaluator_response(response: &PklServerMessage) -> Result<i64> {
+    let Some(map) = response.response.as_map() else {
+        return Err(Error::PklMalformedResponse {
+            message: "expected map in response".to_string(),
+        });
+    };
+
+    if let Some((_, error)) = map.iter().find(|(k, _v)| k.as_str() == Some("error")) {
+        return Err(Error::PklServerError(EvalError::parse(
+            error
+                .as_str()
+                .context("error message from pkl should be valid utf8")?,
+        )));
+    }
+
+    map.iter()
+        .find(|(k, _v)| k.as_str() == Some("evaluatorId"))
+        .and_then(|(_, v)| v.as_i64())
+        .ok_or_else(|| Error::PklMalformedResponse {
+            message: "expected evaluatorId in CreateEvaluator response".into(),
+        })
+}
+
+/// Returns the decoded result of an `EvaluateResponse`, or the error pkl failed with
+pub(crate) fn decode_evaluate_response(response: &PklServerMessage) -> Result<rmpv::Value> {
+    let Some(res) = response.response.as_map() else {
+        return Err(Error::PklMalformedResponse {
+            message: "expected map in evaluate response".into(),
+        });
+    };
+    let Some((_, result)) = res.iter().find(|(k, _v)| k.as_str() == Some("result")) else {
+        // pkl module evaluation failed, return the error message from pkl
+        if let Some((_, error)) = res.iter().find(|(k, _v)| k.as_str() == Some("error")) {
+            return Err(Error::PklServerError(EvalError::parse(
+                error
+                    .as_str()
+                    .context("error message from pkl should be valid utf8")?,
+            )));
+        }
+
+        return Err(Error::PklMalformedResponse {
+            message: "expected result or error in evaluate response".into(),
+        });
+    };
+
+    let slice = result
+        .as_slice()
+        .context(format!("expected result to be a slice, got: {result:?}"))?;
+    let rmpv_ast: rmpv::Value = rmpv::decode::value::read_value(&mut &slice[..])?;
+
+    _debug!("rmpv evaluate result: {:#?}", rmpv_ast);
+
+    Ok(rmpv_ast)
+}
+
+pub(crate) fn message_field(msg: &PklServerMessage, field: &str) -> Result<i64> {
+    let map = msg.response.as_map().ok_or(Error::PklMalformedResponse {
+        message: format!("expected map in message 0x{:x}", msg.header),
+    })?;
+    extract_field(map, field).map_err(|e| Error::PklMalformedResponse {
+        message: e.to_string(),
+    })
+}
+
+fn start_pkl(command: &PklCommand, pkl_debug: bool) -> Result<Child> {
+    let mut command = command.command();
+
+    command
+        .arg("server")
+        .stdin(Stdio::piped())
+        .stdout(Stdio::piped());
+
+    if pkl_debug {
+        command.env("PKL_DEBUG", "1");
+    }
+
+    let child = command.spawn().map_err(Error::PklProcessStart)?;
+
+    Ok(child)
+}
+
+pub(crate) fn decode_pkl_message(value: &rmpv::Value) -> Option<PklServerMessage> {
+    let decoded_array = value.as_array()?;
+    let header = decoded_array.first()?.as_u64()?;
+    let response = decoded_array.get(1)?.to_owned();
+    Some(PklServerMessage { header, response })
+}
+
+pub(crate) fn recv_msg<R: Read>(reader: &mut R) -> std::result::Result<PklServerMessage, Error> {
+    let data = rmpv::decode::read_value(reader)?;
+    let pkl_msg = decode_pkl_message(&data).ok_or(Error::PklMalformedResponse {
+        message: "Failed to decode pkl message format".to_string(),
+    })?;
+    Ok(pkl_msg)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::api::reader::PathElements;
+
+    struct GreetingReader;
+
+    impl PklResourceReader for GreetingReader {
+        fn scheme(&self) -> &str {
+            "custom"
+        }
+
+        fn read(&self, _uri: &str) -> std::result::Result<Vec<u8>, Box<dyn std::error::Error>> {
+            Ok(b"hello".to_vec())
+        }
+
+        fn list(
+            &self,
+            _uri: &str,
+        ) -> std::result::Result<Vec<PathElements>, Box<dyn std::error::Error>> {
+            Ok(vec![])
+        }
+    }
+
+    fn message(header: u64, fields: Vec<(&str, rmpv::Value)>) -> Vec<u8> {
+        let value = rmpv::Value::Array(vec![
+            header.into(),
+            rmpv::Value::Map(fields.into_iter().map(|(k, v)| (k.into(), v)).collect()),
+        ]);
+        let mut out = Vec::new();
+        rmpv::encode::write_value(&mut out, &value).unwrap();
+        out
+    }
+
+    /// A writer whose contents can be inspected after it has been moved into the server
+    #[derive(Clone, Default)]
+    struct SharedWriter(Rc<RefCell<Vec<u8>>>);
+
+    impl Write for SharedWriter {
+        fn write(&mut self, buf: &[u8]) -> std::io::Result<usize> {
+            self.0.borrow_mut().extend_from_slice(buf);
+            Ok(buf.len())
+        }
+
+        fn flush(&mut self) -> std::io::Result<()> {
+            Ok(())
+        }
+    }
+
+    #[test]
+    fn routes_messages_by_id() {
+        let mut incoming = Vec::new();
+        // a read for evaluator 2, then the response to another request before ours
+        incoming.extend(message(
+            READ_RESOURCE_REQUEST,
+            vec![
+                ("requestId", 7.into()),
+                ("evaluatorId", 2.into()),
+                ("uri", "custom:greeting".into()),
+            ],
+        ));
+        incoming.extend(message(
+            EVALUATE_RESPONSE,
+            vec![("requestId", 4.into()), ("evaluatorId", 1.into())],
+        ));
+        incoming.extend(message(
+            EVALUATE_RESPONSE,
+            vec![("requestId", 3.into()), ("evaluatorId", 2.into())],
+        ));
+
+        let writer = SharedWriter::default();
+        let mut server = PklServer::new(writer.clone(), std::io::Cursor::new(incoming));
+        server.evaluators.insert(
+            2,
+            EvaluatorHandlers {
+                client_module_readers: vec![],
+                client_resource_readers: vec![Arc::new(GreetingReader)],
+                logger: None,
+            },
+        );
+
+        let response = server.wait_for_response(3).unwrap();
+        assert_eq!(message_field(&response, "evaluatorId").unwrap(), 2);
+
+        // the read was answered by evaluator 2's reader
+        let written = writer.0.borrow().clone();
+        let reply = recv_msg(&mut written.as_slice()).unwrap();
+        let reply = reply.response.as_map().unwrap();
+        let contents = reply
+            .iter()
+            .find(|(k, _)| k.as_str() == Some("contents"))
+            .map(|(_, v)| v.as_slice().unwrap().to_vec());
+        assert_eq!(contents, Some(b"hello".to_vec()));
+
+        // the other response is kept until it's asked for
+        let other = server.wait_for_response(4).unwrap();
+        assert_eq!(message_field(&other, "evaluatorId").unwrap(), 1);
+    }
+
+    #[test]
+    fn unique_request_ids() {
+        let mut server = PklServer::new(std::io::sink(), std::io::empty());
+        let ids = [server.next_request_id(), server.next_request_id()];
+        assert_ne!(ids[0], ids[1]);
+    }
+}
@@ -1,4 +1,14 @@
+#[cfg(feature = "async")]
+pub mod async_evaluator;
+#[cfg(feature = "async")]
+pub use async_evaluator::{AsyncEvaluator, AsyncEvaluatorManager};
+pub mod command;
+pub use command::{PklCommand, PklVersion};
 pub mod evaluator;
 pub use evaluator::Evaluator;
 pub mod external_reader;
+pub mod logger;
+pub mod manager;
+pub use manager::EvaluatorManager;
+pub(crate) mod project;
 pub mod reader;
@@ -0,0 +1,430 @@
+use std::{
+    collections::HashMap,
+    path::{Path, PathBuf},
+};
+
+use serde::Deserialize;
+
+use crate::{
+    api::evaluator::{DEFAULT_ALLOWED_MODULES, DEFAULT_ALLOWED_RESOURCES},
+    error::{Error, Result},
+    internal::msgapi::outgoing::{
+        Checksums, CreateEvaluator, Http, HttpProxy, ProjectOrDependency, path_string,
+    },
+    pkl::PklMod,
+    value::Duration,
+};
+
+/// Name of the project file in a project directory
+const PROJECT_FILE: &str = "PklProject";
+/// Written by `pkl project resolve` next to the project file
+const DEPS_FILE: &str = "PklProject.deps.json";
+
+/// An evaluated `PklProject` file, see [`EvaluatorOptions::project_dir`](crate::EvaluatorOptions::project_dir)
+#[derive(Debug, Clone, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub(crate) struct PklProject {
+    project_file_uri: String,
+    #[serde(default)]
+    package: Option<Package>,
+    #[serde(default)]
+    dependencies: HashMap<String, Dependency>,
+    #[serde(default)]
+    evaluator_settings: Option<EvaluatorSettings>,
+
+    /// Resolved remote dependencies from `PklProject.deps.json`, by canonical package URI
+    #[serde(skip)]
+    resolved: HashMap<String, ResolvedDependency>,
+
+    /// The project directory, which relative paths in the settings are resolved against
+    #[serde(skip)]
+    dir: PathBuf,
+}
+
+#[derive(Debug, Clone, Deserialize)]
+struct Package {
+    uri: String,
+}
+
+/// A `RemoteDependency`, or a local `Project` imported with `import("../other/PklProject")`
+#[derive(Debug, Clone, Deserialize)]
+#[serde(rename_all = "camelCase")]
+struct Dependency {
+    /// Set for remote dependencies
+    #[serde(default)]
+    uri: Option<String>,
+    #[serde(default)]
+    checksums: Option<Checksums>,
+
+    /// Set for local dependencies
+    #[serde(default)]
+    project_file_uri: Option<String>,
+    #[serde(default)]
+    package: Option<Package>,
+    #[serde(default)]
+    dependencies: HashMap<String, Dependency>,
+}
+
+/// The `evaluatorSettings` of a project that rpkl applies to the evaluator
+#[derive(Debug, Clone, Default, Deserialize)]
+#[serde(rename_all = "camelCase")]
+struct EvaluatorSettings {
+    #[serde(default)]
+    external_properties: Option<HashMap<String, String>>,
+    #[serde(default)]
+    env: Option<HashMap<String, String>>,
+    #[serde(default)]
+    timeout: Option<Duration>,
+    #[serde(default)]
+    http: Option<HttpSettings>,
+    #[serde(default)]
+    allowed_modules: Option<Vec<String>>,
+    #[serde(default)]
+    allowed_resources: Option<Vec<String>>,
+    #[serde(default)]
+    module_path: Option<Vec<String>>,
+    #[serde(default)]
+    root_dir: Option<String>,
+    #[serde(default)]
+    module_cache_dir: Option<String>,
+    #[serde(default)]
+    no_cache: Option<bool>,
+}
+
+#[derive(Debug, Clone, Deserialize)]
+struct HttpSettings {
+    #[serde(default)]
+    proxy: Option<ProxySettings>,
+}
+
+#[derive(Debug, Clone, Deserialize)]
+#[serde(rename_all = "camelCase")]
+struct ProxySettings {
+    #[serde(default)]
+    address: Option<String>,
+    #[serde(default)]
+    no_proxy: Option<Vec<String>>,
+}
+
+#[derive(Debug, Deserialize)]
+#[serde(rename_all = "camelCase")]
+struct DepsFile {
+    resolved_dependencies: HashMap<String, ResolvedDependency>,
+}
+
+#[derive(Debug, Clone, Deserialize)]
+struct ResolvedDependency {
+    #[serde(default)]
+    checksums: Option<Checksums>,
+}
+
+impl PklProject {
+    /// The project file in `dir`, to be evaluated with [`PklProject::from_module`]
+    pub(crate) fn file(dir: &Path) -> PathBuf {
+        dir.join(PROJECT_FILE)
+    }
+
+    /// Reads the project from its evaluated `PklProject` file in `dir`,
+    /// along with the resolved dependencies in `PklProject.deps.json`
+    pub(crate) fn from_module(pkl_mod: PklMod, dir: &Path) -> Result<Self> {
+        let mut project: PklProject = crate::deserialize_module(pkl_mod)?;
+        project.dir = dir.to_path_buf();
+
+        if !project.dependencies.is_empty() {
+            let deps_file = dir.join(DEPS_FILE);
+            let contents = std::fs::read_to_string(&deps_file).map_err(|e| {
+                Error::Message(format!(
+                    "failed to read {}, run `pkl project resolve` to create it: {e}",
+                    deps_file.display()
+                ))
+            })?;
+            let deps: DepsFile = serde_json::from_str(&contents)
+                .map_err(|e| Error::Message(format!("invalid {}: {e}", deps_file.display())))?;
+            project.resolved = deps.resolved_dependencies;
+        }
+
+        Ok(project)
+    }
+
+    /// Sets the project in the CreateEvaluator message, and applies the project's `evaluatorSettings`
+    /// that weren't already set by the evaluator options
+    pub(crate) fn apply(&self, message: &mut CreateEvaluator) {
+        message.project = Some(ProjectOrDependency {
+            kind: "local",
+            package_uri: self.package.as_ref().map(|p| p.uri.clone()),
+            project_file_uri: Some(self.project_file_uri.clone()),
+            checksums: None,
+            dependencies: Some(self.dependencies(&self.dependencies)),
+        });
+
+        let Some(settings) = &self.evaluator_settings else {
+            return;
+        };
+
+        if let Some(properties) = &settings.external_properties {
+            let merged = message.properties.get_or_insert_default();
+            for (key, value) in properties {
+                merged.entry(key.clone()).or_insert_with(|| value.clone());
+            }
+        }
+
+        // like `pkl eval`, the project's env replaces the inherited one
+        if let Some(env) = &settings.env
+            && !message.custom_env
+        {
+            message.env = Some(env.clone());
+        }
+
+        if message.timeout_seconds.is_none() {
+            message.timeout_seconds = settings
+                .timeout
+                .as_ref()
+                .map(|timeout| timeout.as_secs_f64().ceil() as u64);
+        }
+
+        if message.http.is_none() {
+            message.http = settings.http.as_ref().map(|http| Http {
+                proxy: http.proxy.as_ref().map(|proxy| HttpProxy {
+                    address: proxy.address.clone(),
+                    no_proxy: proxy.no_proxy.clone(),
+                }),
+                ca_certificates: None,
+            });
+        }
+
+        // the schemes of client and external readers come after the defaults, and stay allowed
+        if let Some(allowed) = &settings.allowed_modules
+            && !message.custom_allowed_modules
+        {
+            let readers = message
+                .allowed_modules
+                .split_off(DEFAULT_ALLOWED_MODULES.len());
+            message.allowed_modules = allowed.iter().cloned().chain(readers).collect();
+        }
+
+        if let Some(allowed) = &settings.allowed_resources
+            && !message.custom_allowed_resources
+        {
+            let readers = message
+                .allowed_resources
+                .split_off(DEFAULT_ALLOWED_RESOURCES.len());
+            message.allowed_resources = allowed.iter().cloned().chain(readers).collect();
+        }
+
+        if message.module_paths.is_none() {
+            message.module_paths = settings
+                .module_path
+                .as_ref()
+                .map(|paths| paths.iter().map(|p| self.path(p)).collect());
+        }
+
+        if message.root_dir.is_none() {
+            message.root_dir = settings.root_dir.as_deref().map(|dir| self.path(dir));
+        }
+
+        if message.cache_dir.is_none() && settings.no_cache != Some(true) {
+            message.cache_dir = settings
+                .module_cache_dir
+                .as_deref()
+                .map(|dir| self.path(dir));
+        }
+    }
+
+    /// Resolves a path from the settings against the project directory
+    fn path(&self, path: &str) -> String {
+        path_string(&self.dir.join(path))
+    }
+
+    fn dependencies(
+        &self,
+        dependencies: &HashMap<String, Dependency>,
+    ) -> HashMap<String, ProjectOrDependency> {
+        dependencies
+            .iter()
+            .map(|(name, dependency)| {
+                let message = match &dependency.project_file_uri {
+                    Some(project_file_uri) => ProjectOrDependency {
+                        kind: "local",
+                        package_uri: dependency.package.as_ref().map(|p| p.uri.clone()),
+                        project_file_uri: Some(project_file_uri.clone()),
+                        checksums: None,
+                        dependencies: Some(self.dependencies(&dependency.dependencies)),
+                    },
+                    None => ProjectOrDependency {
+                        kind: "remote",
+                        package_uri: dependency.uri.clone(),
+                        // prefer the checksums written by `pkl project resolve`
+                        checksums: dependency
+                            .uri
+                            .as_deref()
+                            .and_then(canonical_package_uri)
+                            .and_then(|uri| self.resolved.get(&uri))
+                            .and_then(|resolved| resolved.checksums.clone())
+                            .or_else(|| dependency.checksums.clone()),
+                        project_file_uri: None,
+                        dependencies: None,
+                    },
+                };
+                (name.clone(), message)
+            })
+            .collect()
+    }
+}
+
+/// The key of a package in `PklProject.deps.json`,
+/// e.g. `package://example.com/birds@0` for `package://example.com/birds@0.5.0`
+fn canonical_package_uri(uri: &str) -> Option<String> {
+    let (base, version) = uri.rsplit_once('@')?;
+    let major = version.split('.').next()?;
+    Some(format!("{base}@{major}"))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn remote(uri: &str) -> Dependency {
+        Dependency {
+            uri: Some(uri.into()),
+            checksums: None,
+            project_file_uri: None,
+            package: None,
+            dependencies: HashMap::new(),
+        }
+    }
+
+    #[test]
+    fn canonical_uris() {
+        assert_eq!(
+            canonical_package_uri("package://example.com/birds@0.5.0").as_deref(),
+            Some("package://example.com/birds@0")
+        );
+        assert_eq!(canonical_package_uri("package://example.com/birds"), None);
+    }
+
+    #[test]
+    fn project_message() {
+        let project = PklProject {
+            project_file_uri: "file:///app/PklProject".into(),
+            package: None,
+            dependencies: HashMap::from([
+                ("birds".into(), remote("package://example.com/birds@0.5.0")),
+                (
+                    "shared".into(),
+                    Dependency {
+                        uri: None,
+                        checksums: None,
+                        project_file_uri: Some("file:///shared/PklProject".into()),
+                        package: Some(Package {
+                            uri: "package://example.com/shared@1.0.0".into(),
+                        }),
+                        dependencies: HashMap::new(),
+                    },
+                ),
+            ]),
+            evaluator_settings: None,
+            resolved: HashMap::from([(
+                "package://example.com/birds@0".into(),
+                ResolvedDependency {
+                    checksums: Some(Checksums {
+                        sha256: "abc".into(),
+                    }),
+                },
+            )]),
+            dir: PathBuf::from("/app"),
+        };
+
+        let mut message = CreateEvaluator::default();
+        project.apply(&mut message);
+
+        let sent = message.project.unwrap();
+        assert_eq!(sent.kind, "local");
+        assert_eq!(
+            sent.project_file_uri.as_deref(),
+            Some("file:///app/PklProject")
+        );
+
+        let dependencies = sent.dependencies.unwrap();
+        let birds = &dependencies["birds"];
+        assert_eq!(birds.kind, "remote");
+        assert_eq!(
+            birds.checksums.as_ref().map(|c| c.sha256.as_str()),
+            Some("abc")
+        );
+
+        let shared = &dependencies["shared"];
+        assert_eq!(shared.kind, "local");
+        assert_eq!(
+            shared.package_uri.as_deref(),
+            Some("package://example.com/shared@1.0.0")
+        );
+    }
+
+    #[test]
+    fn options_take_precedence_over_settings() {
+        let project = PklProject {
+            project_file_uri: "file:///app/PklProject".into(),
+            package: None,
+            dependencies: HashMap::new(),
+            evaluator_settings: Some(EvaluatorSettings {
+                external_properties: Some(HashMap::from([
+                    ("env".into(), "dev".into()),
+                    ("region".into(), "eu".into()),
+                ])),
+                timeout: Some(Duration::new(1.5, crate::value::DurationUnit::Minutes)),
+                env: Some(HashMap::from([("APP_ENV".into(), "dev".into())])),
+                ..Default::default()
+            }),
+            resolved: HashMap::new(),
+            dir: PathBuf::from("/app"),
+        };
+
+        let options = crate::EvaluatorOptions::new()
+            .property("env", "prod")
+            .inherit_env(false);
+        let mut message = CreateEvaluator::from(&options);
+        project.apply(&mut message);
+
+        let properties = message.properties.unwrap();
+        assert_eq!(properties["env"], "prod");
+        assert_eq!(properties["region"], "eu");
+        assert_eq!(message.timeout_seconds, Some(90));
+        // the options configured the environment, so the project's env isn't used
+        assert_eq!(message.env, Some(HashMap::new()));
+    }
+
+    #[test]
+    fn project_allowlists_and_paths() {
+        let project = PklProject {
+            project_file_uri: "file:///app/PklProject".into(),
+            package: None,
+            dependencies: HashMap::new(),
+            evaluator_settings: Some(EvaluatorSettings {
+                allowed_modules: Some(vec!["pkl:".into(), "file:".into()]),
+                allowed_resources: Some(vec!["env:".into()]),
+                module_path: Some(vec!["lib".into()]),
+                module_cache_dir: Some("cache".into()),
+                no_cache: Some(true),
+                ..Default::default()
+            }),
+            resolved: HashMap::new(),
+            dir: PathBuf::from("/app"),
+        };
+
+        let options = crate::EvaluatorOptions::new()
+            .allowed_resources(["prop:"])
+            .external_module_reader("custom", "reader", &[]);
+        let mut message = CreateEvaluator::from(&options);
+        project.apply(&mut message);
+
+        // the external reader's scheme stays allowed alongside the project's modules
+        assert_eq!(message.allowed_modules, ["pkl:", "file:", "custom"]);
+        // set by the options, so the project's `allowedResources` don't apply
+        assert_eq!(message.allowed_resources, ["prop:"]);
+        assert_eq!(
+            message.module_paths,
+            Some(vec![path_string(Path::new("/app/lib"))])
+        );
+        assert_eq!(message.cache_dir, None);
+    }
+}
@@ -75,6 +75,98 @@ pub trait PklModuleReader {
     fn list(&self, uri: &str) -> Result<Vec<PathElements>, Box<dyn std::error::Error>>;
 }
 
+/// Future returned by the async reader traits
+#[cfg(feature = "async")]
+pub type BoxFuture<'a, T> = std::pin::Pin<Box<dyn std::future::Future<Output = T> + Send + 'a>>;
+
+/// Errors returned by async readers, which have to be sent back to the pkl server from another task
+#[cfg(feature = "async")]
+pub type AsyncReaderError = Box<dyn std::error::Error + Send + Sync>;
+
+/// A [`PklResourceReader`] whose reads are awaited, used by [`AsyncEvaluator`](crate::api::AsyncEvaluator).
+///
+/// Register it with [`EvaluatorOptions::add_async_resource_reader`](crate::EvaluatorOptions::add_async_resource_reader).
+///
+/// # Example
+/// ```no_run
+/// use rpkl::api::reader::{AsyncPklResourceReader, AsyncReaderError, BoxFuture, PathElements};
+///
+/// struct Secrets;
+///
+/// impl AsyncPklResourceReader for Secrets {
+///     fn scheme(&self) -> &str {
+///         "secret"
+///     }
+///
+///     fn read<'a>(&'a self, uri: &'a str) -> BoxFuture<'a, Result<Vec<u8>, AsyncReaderError>> {
+///         Box::pin(async move { Ok(uri.trim_start_matches("secret:").as_bytes().to_vec()) })
+///     }
+///
+///     fn list<'a>(&'a self, _uri: &'a str) -> BoxFuture<'a, Result<Vec<PathElements>, AsyncReaderError>> {
+///         Box::pin(async { Ok(vec![]) })
+///     }
+/// }
+/// ```
+#[cfg(feature = "async")]
+pub trait AsyncPklResourceReader: Send + Sync {
+    /// Scheme returns the scheme part of the URL that this reader can read.
+    /// The value should be the URI scheme up to (not including) `:`
+    fn scheme(&self) -> &str;
+
+    /// Tells whether the path part of ths URI has a
+    /// [hier-part](https://datatracker.ietf.org/doc/html/rfc3986#section-3).
+    fn has_hierarchical_uris(&self) -> bool {
+        false
+    }
+
+    /// Tells whether this reader supports globbing.
+    fn is_globbable(&self) -> bool {
+        false
+    }
+
+    /// Read the contents of the resource at the given URI.
+    fn read<'a>(&'a self, uri: &'a str) -> BoxFuture<'a, Result<Vec<u8>, AsyncReaderError>>;
+
+    /// List the contents of the resource at the given URI.
+    fn list<'a>(
+        &'a self,
+        uri: &'a str,
+    ) -> BoxFuture<'a, Result<Vec<PathElements>, AsyncReaderError>>;
+}
+
+/// A [`PklModuleReader`] whose reads are awaited, used by [`AsyncEvaluator`](crate::api::AsyncEvaluator).
+///
+/// Register it with [`EvaluatorOptions::add_async_module_reader`](crate::EvaluatorOptions::add_async_module_reader).
+#[cfg(feature = "async")]
+pub trait AsyncPklModuleReader: Send + Sync {
+    /// Scheme returns the scheme part of the URL that this reader can read.
+    /// The value should be the URI scheme up to (not including) `:`
+    fn scheme(&self) -> &str;
+
+    /// Tells whether the path part of ths URI has a
+    /// [hier-part](https://datatracker.ietf.org/doc/html/rfc3986#section-3).
+    fn has_hierarchical_uris(&self) -> bool {
+        false
+    }
+
+    /// Tells whether this reader supports globbing.
+    fn is_globbable(&self) -> bool {
+        false
+    }
+
+    /// Tells whether the module is local to the system.
+    fn is_local(&self) -> bool;
+
+    /// Read the contents of the module at the given URI.
+    fn read<'a>(&'a self, uri: &'a str) -> BoxFuture<'a, Result<String, AsyncReaderError>>;
+
+    /// List the contents of the module at the given URI.
+    fn list<'a>(
+        &'a self,
+        uri: &'a str,
+    ) -> BoxFuture<'a, Result<Vec<PathElements>, AsyncReaderError>>;
+}
+
 pub trait IntoResourceReaders {
     fn into_readers(self) -> Vec<Arc<dyn PklResourceReader>>;
 }
@@ -358,7 +450,7 @@ pub(crate) fn handle_read_module<W: Write>(
     Ok(())
 }
 
-struct MapValue<'a>(&'a rmpv::Value);
+pub(crate) struct MapValue<'a>(&'a rmpv::Value);
 
 impl<'a> TryFrom<MapValue<'a>> for i64 {
     type Error = Box<dyn std::error::Error>;
@@ -398,7 +490,7 @@ impl<'a> TryFrom<MapValue<'a>> for String {
     }
 }
 
-fn parse_scheme(uri: &str) -> Option<&str> {
+pub(crate) fn parse_scheme(uri: &str) -> Option<&str> {
     match uri.find(':') {
         Some(pos) => {
             let scheme = &uri[..pos];
@@ -417,7 +509,7 @@ fn parse_scheme(uri: &str) -> Option<&str> {
 }
 
 // Helper function to extract fields from response map
-fn extract_field<'a, T>(
+pub(crate) fn extract_field<'a, T>(
     map: &'a [(rmpv::Value, rmpv::Value)],
     field: &str,
 ) -> Result<T, Box<dyn std::error::Error>>
@@ -252,8 +252,12 @@ impl Context<'_> {
             }),
             PklValue::String(_) => Cow::Borrowed("String"),
             PklValue::Null => Cow::Borrowed("Option<rpkl::Value>"),
-            PklValue::Map(_) => Cow::Borrowed("rpkl::Value"),
-            PklValue::List(values) => {
+            PklValue::Map(_)
+            | PklValue::Mapping(_)
+            | PklValue::Entries(_)
+            | PklValue::MappingEntries(_)
+            | PklValue::Object { .. } => Cow::Borrowed("rpkl::Value"),
+            PklValue::List(values) | PklValue::Listing(values) | PklValue::Set(values) => {
                 if self.options.infer_vec_types {
                     Cow::Owned(format!(
                         "Vec<{}>",
@@ -325,7 +329,13 @@ impl Context<'_> {
         //     dynamic_members,
         // )) = member_value
         let is_forced_opaque = self.options.is_forced_opaque(&field_modifier);
-        if let PklValue::Map(dynamic_members) = member_value {
+        if let PklValue::Map(dynamic_members)
+        | PklValue::Mapping(dynamic_members)
+        | PklValue::Object {
+            members: dynamic_members,
+            ..
+        } = member_value
+        {
             // generate the struct if they didn't specify for it to be opaque
             if !is_forced_opaque {
                 // TODO: improve this
@@ -0,0 +1,660 @@
+//! Layered configuration, merging several pkl modules and rust-side overrides into one value.
+//!
+//! Layers are merged in the order they're added, each one on top of the previous ones:
+//! - maps and objects are merged member by member, recursively
+//! - lists and sets are replaced by default, or appended to with [`ListMerge::Append`]
+//! - maps with non-string keys are merged entry by entry
+//! - any other value, including `null`, replaces the value below it
+
+use std::{
+    collections::{BTreeMap, HashMap},
+    ops::Bound,
+    path::PathBuf,
+};
+
+use crate::{
+    EvaluatorOptions, REPL_TEXT_URI,
+    api::Evaluator,
+    error::{Error, Result},
+    internal::Integer,
+    pkl::{Deserializer, IntoPklMap, ser::parse_data_size},
+    value::{Duration, PklValue, value::MapImpl},
+};
+
+/// How a list or set is merged with the one in the layer below it
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub enum ListMerge {
+    /// The list replaces the one below it
+    #[default]
+    Replace,
+    /// The elements are appended to the list below it. Elements of sets are only appended if they aren't in it already.
+    Append,
+}
+
+enum Source {
+    Module(Module),
+    Value(MapImpl<String, PklValue>),
+    Override(String, PklValue),
+    Arg(String),
+}
+
+enum Module {
+    Path(PathBuf),
+    Uri(String),
+    Text(String),
+}
+
+/// Evaluates an ordered list of modules, text and overrides and merges them into one config.
+///
+/// See the [module docs](crate::config) for how layers are merged.
+///
+/// # Example
+///
+/// ```no_run
+/// use serde::Deserialize;
+///
+/// #[derive(Deserialize)]
+/// struct Config {
+///     host: String,
+///     port: u16,
+/// }
+///
+/// # fn main() -> Result<(), rpkl::Error> {
+/// let config = rpkl::ConfigBuilder::new()
+///     .module("base.pkl")
+///     .module("prod.pkl")
+///     .overrides(["port=9090"])
+///     .evaluate()?;
+///
+/// assert_eq!(config.origin("port"), Some("override port"));
+/// let config: Config = config.deserialize()?;
+/// #    Ok(())
+/// # }
+/// ```
+#[derive(Default)]
+pub struct ConfigBuilder {
+    options: EvaluatorOptions,
+    layers: Vec<(String, Source)>,
+    lists: ListMerge,
+    list_paths: HashMap<String, ListMerge>,
+}
+
+impl ConfigBuilder {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Sets the options of the evaluator the modules are evaluated with
+    pub fn options(mut self, options: EvaluatorOptions) -> Self {
+        self.options = options;
+        self
+    }
+
+    /// Adds the `.pkl` file at `path` as a layer
+    pub fn module(mut self, path: impl Into<PathBuf>) -> Self {
+        let path = path.into();
+        self.layers.push((
+            path.display().to_string(),
+            Source::Module(Module::Path(path)),
+        ));
+        self
+    }
+
+    /// Adds the module at `uri` as a layer, see [`Evaluator::evaluate_uri`]
+    pub fn uri(mut self, uri: impl Into<String>) -> Self {
+        let uri = uri.into();
+        self.layers
+            .push((uri.clone(), Source::Module(Module::Uri(uri))));
+        self
+    }
+
+    /// Adds pkl source text as a layer named `name`. The text is evaluated as the module `repl:text`.
+    pub fn text(mut self, name: impl Into<String>, source: impl Into<String>) -> Self {
+        self.layers
+            .push((name.into(), Source::Module(Module::Text(source.into()))));
+        self
+    }
+
+    /// Adds already evaluated values as a layer named `name`, e.g. defaults computed in rust
+    pub fn layer(mut self, name: impl Into<String>, value: MapImpl<String, PklValue>) -> Self {
+        self.layers.push((name.into(), Source::Value(value)));
+        self
+    }
+
+    /// Sets the value at a dotted path, e.g. `database.port`, creating the maps along the path if needed.
+    /// Paths can't index into listings or mappings, e.g. `servers[0].port` fails when evaluating.
+    pub fn set(mut self, path: impl Into<String>, value: PklValue) -> Self {
+        let path = path.into();
+        self.layers
+            .push((format!("override {path}"), Source::Override(path, value)));
+        self
+    }
+
+    /// Adds overrides in the form `key.path=value`, e.g. from command line arguments.
+    ///
+    /// Values are parsed as `true`, `false`, `null`, an integer, a float, a duration such as `5.s`
+    /// or a data size such as `512.mb` if they look like one,
+    /// a string in double quotes has its quotes removed, and anything else is a string.
+    pub fn overrides(mut self, overrides: impl IntoIterator<Item = impl Into<String>>) -> Self {
+        for arg in overrides {
+            let arg = arg.into();
+            let name = match arg.split_once('=') {
+                Some((path, _)) => format!("override {}", path.trim()),
+                None => format!("override {arg}"),
+            };
+            self.layers.push((name, Source::Arg(arg)));
+        }
+        self
+    }
+
+    /// Sets how lists and sets are merged, [`ListMerge::Replace`] by default
+    pub fn list_merge(mut self, merge: ListMerge) -> Self {
+        self.lists = merge;
+        self
+    }
+
+    /// Sets how the list or set at a dotted path is merged, overriding [`ConfigBuilder::list_merge`]
+    pub fn list_merge_at(mut self, path: impl Into<String>, merge: ListMerge) -> Self {
+        self.list_paths.insert(path.into(), merge);
+        self
+    }
+
+    /// Evaluates and merges the layers, then deserializes the result as `T`
+    /// # Errors
+    /// - If a layer fails to evaluate or an override is malformed
+    /// - `DeserializeError`: If the deserialization fails.
+    pub fn build<T>(self) -> Result<T>
+    where
+        T: Sized + for<'de> serde::Deserialize<'de>,
+    {
+        self.evaluate()?.deserialize()
+    }
+
+    /// Evaluates and merges the layers
+    /// # Errors
+    /// - If a layer fails to evaluate or an override is malformed
+    pub fn evaluate(self) -> Result<LayeredConfig> {
+        let ConfigBuilder {
+            options,
+            layers,
+            lists,
+            list_paths,
+        } = self;
+
+        let mut evaluator = None;
+        let mut merger = Merger {
+            lists,
+            list_paths: &list_paths,
+            origins: BTreeMap::new(),
+            layer: 0,
+        };
+        let mut value = MapImpl::new();
+        let mut names = Vec::with_capacity(layers.len());
+
+        for (layer, (name, source)) in layers.into_iter().enumerate() {
+            let members = match source {
+                Source::Value(members) => members,
+                Source::Override(path, value) => nest(&path, value)?,
+                Source::Arg(arg) => {
+                    let (path, value) = parse_override(&arg)?;
+                    nest(&path, value)?
+                }
+                Source::Module(module) => {
+                    let evaluator = match &mut evaluator {
+                        Some(evaluator) => evaluator,
+                        slot @ None => slot.insert(Evaluator::new_from_options(options.clone())?),
+                    };
+                    let pkl_mod = match module {
+                        Module::Path(path) => evaluator.evaluate_module(path)?,
+                        Module::Uri(uri) => evaluator.evaluate_uri(uri)?,
+                        Module::Text(text) => evaluator.evaluate_text(text, REPL_TEXT_URI)?,
+                    };
+                    pkl_mod.into_pkl_map()
+                }
+            };
+
+            merger.layer = layer;
+            merger.merge_members(&mut value, members, "");
+            names.push(name);
+        }
+
+        Ok(LayeredConfig {
+            value,
+            layers: names,
+            origins: merger.origins,
+        })
+    }
+}
+
+/// The result of merging the layers of a [`ConfigBuilder`]
+#[derive(Debug, Clone)]
+pub struct LayeredConfig {
+    value: MapImpl<String, PklValue>,
+    layers: Vec<String>,
+    origins: BTreeMap<String, usize>,
+}
+
+impl LayeredConfig {
+    /// The merged members
+    pub fn value(&self) -> &MapImpl<String, PklValue> {
+        &self.value
+    }
+
+    pub fn into_value(self) -> MapImpl<String, PklValue> {
+        self.value
+    }
+
+    /// Names of the layers, in the order they were merged.
+    /// Modules are named after their path or URI, overrides after the path they set, e.g. `override database.port`.
+    pub fn layers(&self) -> &[String] {
+        &self.layers
+    }
+
+    /// Name of the layer the value at `path` came from, e.g. `database.port` or `servers[2]`.
+    ///
+    /// For a merged map or object, this is the layer that first set it; its members may come from later layers.
+    pub fn origin(&self, path: &str) -> Option<&str> {
+        let mut path = path;
+        loop {
+            if let Some(&layer) = self.origins.get(path) {
+                return Some(&self.layers[layer]);
+            }
+            path = &path[..path.rfind(['.', '['])?];
+        }
+    }
+
+    /// The path of every value set by a layer, with the name of that layer, sorted by path
+    pub fn origins(&self) -> Vec<(&str, &str)> {
+        self.origins
+            .iter()
+            .map(|(path, &layer)| (path.as_str(), self.layers[layer].as_str()))
+            .collect()
+    }
+
+    /// Deserializes the merged members as `T`
+    /// # Errors
+    /// - `DeserializeError`: If the deserialization fails.
+    pub fn deserialize<T>(&self) -> Result<T>
+    where
+        T: Sized + for<'de> serde::Deserialize<'de>,
+    {
+        T::deserialize(&mut Deserializer::from_pkl_map(&self.value))
+    }
+}
+
+/// Merges values into the ones from previous layers, recording which layer set each value
+struct Merger<'a> {
+    lists: ListMerge,
+    list_paths: &'a HashMap<String, ListMerge>,
+    origins: BTreeMap<String, usize>,
+    layer: usize,
+}
+
+impl Merger<'_> {
+    fn merge_members(
+        &mut self,
+        base: &mut MapImpl<String, PklValue>,
+        upper: MapImpl<String, PklValue>,
+        path: &str,
+    ) {
+        for (key, value) in upper {
+            let child = if path.is_empty() {
+                key.clone()
+            } else {
+                format!("{path}.{key}")
+            };
+            match base.get_mut(&key) {
+                Some(existing) => {
+                    let old = std::mem::replace(existing, PklValue::Null);
+                    *existing = self.merge(old, value, &child);
+                }
+                None => {
+                    self.replaced(&child);
+                    base.insert(key, value);
+                }
+            }
+        }
+    }
+
+    fn merge(&mut self, base: PklValue, upper: PklValue, path: &str) -> PklValue {
+        let append = self.list_paths.get(path).copied().unwrap_or(self.lists) == ListMerge::Append;
+        match (base, upper) {
+            (
+                base @ (PklValue::Map(_) | PklValue::Mapping(_) | PklValue::Object { .. }),
+                upper @ (PklValue::Map(_) | PklValue::Mapping(_) | PklValue::Object { .. }),
+            ) => {
+                let mapping =
+                    matches!(base, PklValue::Mapping(_)) || matches!(upper, PklValue::Mapping(_));
+                let (base_class, mut members) = into_members(base);
+                let (upper_class, upper) = into_members(upper);
+                self.merge_members(&mut members, upper, path);
+                // the class of the upper layer wins, a map merged into an object stays an object
+                match upper_class.or(base_class) {
+                    Some((class_name, module_uri)) => PklValue::Object {
+                        class_name,
+                        module_uri,
+                        members,
+                    },
+                    None if mapping => PklValue::Mapping(members),
+                    None => PklValue::Map(members),
+                }
+            }
+            (
+                mut base @ (PklValue::List(_) | PklValue::Listing(_)),
+                PklValue::List(upper) | PklValue::Listing(upper),
+            ) if append => {
+                if let PklValue::List(items) | PklValue::Listing(items) = &mut base {
+                    for item in upper {
+                        self.replaced(&format!("{path}[{}]", items.len()));
+                        items.push(item);
+                    }
+                }
+                base
+            }
+            (PklValue::Set(mut items), PklValue::Set(upper)) if append => {
+                for item in upper {
+                    if !items.contains(&item) {
+                        self.replaced(&format!("{path}[{}]", items.len()));
+                        items.push(item);
+                    }
+                }
+                PklValue::Set(items)
+            }
+            (
+                mut base @ (PklValue::Entries(_) | PklValue::MappingEntries(_)),
+                PklValue::Entries(upper) | PklValue::MappingEntries(upper),
+            ) => {
+                if let PklValue::Entries(entries) | PklValue::MappingEntries(entries) = &mut base {
+                    for (key, value) in upper {
+                        let child = format!("{path}[{}]", key.to_pkl_string());
+                        match entries.iter_mut().find(|(k, _)| *k == key) {
+                            Some((_, existing)) => {
+                                let old = std::mem::replace(existing, PklValue::Null);
+                                *existing = self.merge(old, value, &child);
+                            }
+                            None => {
+                                self.replaced(&child);
+                                entries.push((key, value));
+                            }
+                        }
+                    }
+                }
+                base
+            }
+            (_, upper) => {
+                self.replaced(path);
+                upper
+            }
+        }
+    }
+
+    /// Records that the value at `path`, and everything below it, now comes from the current layer
+    fn replaced(&mut self, path: &str) {
+        // the paths below `path` are sorted right after `path.` and `path[`
+        for separator in ['.', '['] {
+            let prefix = format!("{path}{separator}");
+            let below: Vec<String> = self
+                .origins
+                .range::<str, _>((Bound::Included(prefix.as_str()), Bound::Unbounded))
+                .take_while(|(p, _)| p.starts_with(&prefix))
+                .map(|(p, _)| p.clone())
+                .collect();
+            for p in below {
+                self.origins.remove(&p);
+            }
+        }
+        self.origins.insert(path.to_string(), self.layer);
+    }
+}
+
+/// Splits a map or object into its class, if it's an object, and its members
+fn into_members(value: PklValue) -> (Option<(String, String)>, MapImpl<String, PklValue>) {
+    match value {
+        PklValue::Object {
+            class_name,
+            module_uri,
+            members,
+        } => (Some((class_name, module_uri)), members),
+        PklValue::Map(members) | PklValue::Mapping(members) => (None, members),
+        _ => unreachable!("only called with maps, mappings and objects"),
+    }
+}
+
+/// Builds `{ a { b = value } }` for the path `a.b`
+fn nest(path: &str, value: PklValue) -> Result<MapImpl<String, PklValue>> {
+    let segments = split_path(path)?;
+    let (last, parents) = segments
+        .split_last()
+        .expect("paths have at least one segment");
+
+    let mut members = MapImpl::new();
+    members.insert(last.to_string(), value);
+    for segment in parents.iter().rev() {
+        let mut parent = MapImpl::new();
+        parent.insert(segment.to_string(), PklValue::Map(members));
+        members = parent;
+    }
+    Ok(members)
+}
+
+/// Splits a dotted path such as `database.port` into its segments.
+///
+/// Only member names are supported, indexes such as `servers[0]` are rejected.
+pub(crate) fn split_path(path: &str) -> Result<Vec<&str>> {
+    if path.contains(['[', ']']) {
+        return Err(Error::Message(format!(
+            "invalid path `{path}`: indexing into listings and mappings isn't supported"
+        )));
+    }
+    let segments: Vec<&str> = path.split('.').map(str::trim).collect();
+    if segments.iter().any(|segment| segment.is_empty()) {
+        return Err(Error::Message(format!("invalid path `{path}`")));
+    }
+    Ok(segments)
+}
+
+/// Parses an override in the form `key.path=value` into the path and the value.
+///
+/// See [`ConfigBuilder::overrides`] for how the value is parsed.
+///
+/// # Example
+/// ```
+/// let (path, value) = rpkl::config::parse_override("database.port=5433")?;
+/// assert_eq!(path, "database.port");
+/// assert_eq!(value.to_pkl_string(), "5433");
+/// # Ok::<(), rpkl::Error>(())
+/// ```
+/// # Errors
+/// - If there's no `=`, or the path has an empty segment
+pub fn parse_override(arg: &str) -> Result<(String, PklValue)> {
+    let (path, value) = arg
+        .split_once('=')
+        .ok_or_else(|| Error::Message(format!("expected `key.path=value`, got `{arg}`")))?;
+    let path = path.trim();
+    split_path(path)?;
+    Ok((path.to_string(), parse_literal(value.trim())))
+}
+
+/// Parses an override value, see [`ConfigBuilder::overrides`]
+fn parse_literal(value: &str) -> PklValue {
+    match value {
+        "true" => return PklValue::Boolean(true),
+        "false" => return PklValue::Boolean(false),
+        "null" => return PklValue::Null,
+        _ => {}
+    }
+    if let Some(s) = value
+        .strip_prefix('"')
+        .and_then(|value| value.strip_suffix('"'))
+    {
+        return PklValue::String(s.to_string());
+    }
+    if value.starts_with(|c: char| c.is_ascii_digit() || c == '-') {
+        if let Ok(n) = value.parse::<u64>() {
+            return PklValue::Int(Integer::Pos(n));
+        }
+        if let Ok(n) = value.parse::<i64>() {
+            return PklValue::Int(Integer::Neg(n));
+        }
+        if let Ok(n) = value.parse::<f64>() {
+            return PklValue::Int(Integer::Float(n));
+        }
+        if let Ok(duration) = value.parse::<Duration>() {
+            return PklValue::Duration(duration);
+        }
+        if let Some(size) = parse_data_size(value) {
+            return PklValue::DataSize(size);
+        }
+    }
+    PklValue::String(value.to_string())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::pkl_value;
+    use serde::Deserialize;
+
+    fn members(value: PklValue) -> MapImpl<String, PklValue> {
+        match value {
+            PklValue::Map(members) => members,
+            value => panic!("expected a map, got {value:?}"),
+        }
+    }
+
+    #[test]
+    fn merges_layers() {
+        let mut base = members(pkl_value!({
+            "tags": ["base"],
+            "hosts": ["a"],
+        }));
+        base.insert(
+            "database".into(),
+            PklValue::Object {
+                class_name: "config#Database".into(),
+                module_uri: "file:///config.pkl".into(),
+                members: members(pkl_value!({ "host": "localhost", "port": 5432 })),
+            },
+        );
+        let prod = members(pkl_value!({
+            "database": { "host": "db.prod" },
+            "tags": ["prod"],
+            "hosts": ["b"],
+        }));
+
+        let config = ConfigBuilder::new()
+            .layer("base", base)
+            .layer("prod", prod)
+            .overrides(["database.port=6543", "debug=true"])
+            .list_merge_at("tags", ListMerge::Append)
+            .evaluate()
+            .unwrap();
+
+        let database = &config.value()["database"];
+        assert_eq!(database.class_name(), Some("config#Database"));
+        assert_eq!(database["host"], pkl_value!("db.prod"));
+        assert_eq!(database["port"], pkl_value!(6543));
+        assert_eq!(config.value()["tags"], pkl_value!(["base", "prod"]));
+        assert_eq!(config.value()["hosts"], pkl_value!(["b"]));
+        assert_eq!(config.value()["debug"], pkl_value!(true));
+
+        assert_eq!(config.origin("database"), Some("base"));
+        assert_eq!(config.origin("database.host"), Some("prod"));
+        assert_eq!(
+            config.origin("database.port"),
+            Some("override database.port")
+        );
+        assert_eq!(config.origin("tags[0]"), Some("base"));
+        assert_eq!(config.origin("tags[1]"), Some("prod"));
+        assert_eq!(config.origin("hosts[0]"), Some("prod"));
+        assert_eq!(config.origin("missing"), None);
+
+        #[derive(Deserialize)]
+        struct Config {
+            database: Database,
+            debug: bool,
+        }
+        #[derive(Deserialize)]
+        struct Database {
+            port: u16,
+        }
+        let value: Config = config.deserialize().unwrap();
+        assert_eq!(value.database.port, 6543);
+        assert!(value.debug);
+
+        #[derive(Debug, Deserialize)]
+        struct Hosts {
+            #[allow(dead_code)]
+            hosts: Vec<u16>,
+        }
+        let Err(Error::DeserializeError(err)) = config.deserialize::<Hosts>() else {
+            panic!("expected a deserialize error");
+        };
+        assert_eq!(err.path_string(), "hosts[0]");
+    }
+
+    #[test]
+    fn replacing_a_map_resets_origins() {
+        let config = ConfigBuilder::new()
+            .layer("base", members(pkl_value!({ "a": { "b": 1 } })))
+            .layer("top", members(pkl_value!({ "a": 2 })))
+            .evaluate()
+            .unwrap();
+
+        assert_eq!(config.origin("a.b"), Some("top"));
+        assert_eq!(config.origins(), vec![("a", "top")]);
+
+        // only the paths below the replaced one are reset, not ones sharing its prefix
+        let config = ConfigBuilder::new()
+            .layer(
+                "base",
+                members(pkl_value!({ "a": { "b": 1 }, "ab": 1, "l": [1, 2] })),
+            )
+            .layer("top", members(pkl_value!({ "a": 2, "l": [3] })))
+            .evaluate()
+            .unwrap();
+        assert_eq!(
+            config.origins(),
+            vec![("a", "top"), ("ab", "base"), ("l", "top")]
+        );
+        assert_eq!(config.origin("l[0]"), Some("top"));
+    }
+
+    #[test]
+    fn parse_overrides() {
+        assert_eq!(
+            parse_override("database.port = 5433").unwrap(),
+            ("database.port".to_string(), pkl_value!(5433))
+        );
+        assert_eq!(parse_override("offset=-1").unwrap().1, pkl_value!(-1));
+        assert_eq!(parse_override("ratio=0.5").unwrap().1, pkl_value!(0.5));
+        assert_eq!(
+            parse_override("timeout=-1.5.min").unwrap().1,
+            pkl_value!("-1.5.min".parse::<Duration>().unwrap())
+        );
+        assert_eq!(
+            parse_override("size=512.mb")
+                .unwrap()
+                .1
+                .as_data_size()
+                .map(ToString::to_string),
+            Some("512.mb".into())
+        );
+        assert_eq!(
+            parse_override("timeout=\"5.s\"").unwrap().1,
+            pkl_value!("5.s")
+        );
+        assert_eq!(parse_override("name=\"42\"").unwrap().1, pkl_value!("42"));
+        assert_eq!(parse_override("name=prod").unwrap().1, pkl_value!("prod"));
+        assert_eq!(parse_override("url=a=b").unwrap().1, pkl_value!("a=b"));
+        assert!(parse_override("database.port").is_err());
+        assert!(parse_override("database..port=1").is_err());
+        assert!(parse_override("servers[0].port=1").is_err());
+        assert!(
+            ConfigBuilder::new()
+                .set("servers[0].port", pkl_value!(1))
+                .evaluate()
+                .is_err()
+        );
+    }
+}
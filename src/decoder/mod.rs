@@ -1,7 +1,9 @@
 mod primitive;
 mod structs;
 
-use crate::{context::Context, internal::ObjectMember, pkl::PklMod, Error, Result};
+use crate::{
+    Error, Result, Value as PklValue, context::Context, internal::ObjectMember, pkl::PklMod,
+};
 
 /// Decode a pkl module from a messagepack value
 pub(crate) fn decode_module(decoded: &rmpv::Value) -> Result<crate::pkl::PklMod> {
@@ -45,3 +47,8 @@ pub(crate) fn decode_module(decoded: &rmpv::Value) -> Result<crate::pkl::PklMod>
         members,
     })
 }
+
+/// Decode an arbitrary pkl value from a messagepack value, e.g. the result of evaluating an expression
+pub(crate) fn decode_value(decoded: &rmpv::Value) -> Result<PklValue> {
+    structs::decode_member(decoded).map(Into::into)
+}
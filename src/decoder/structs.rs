@@ -9,7 +9,7 @@ use crate::{
     decoder::primitive::decode_primitive,
     internal::{IPklValue, ObjectMember, PklNonPrimitive, type_constants},
     utils::macros::_trace,
-    value::{DataSize, datasize::DataSizeUnit, value::MapImpl},
+    value::{DataSize, Duration, DurationUnit, datasize::DataSizeUnit, value::MapImpl},
 };
 
 pub fn decode_object_member(data: &[rmpv::Value]) -> Result<ObjectMember> {
@@ -60,7 +60,7 @@ fn decode_object_generic(_type_id: u64, slots: &[rmpv::Value]) -> Result<ObjectM
 
 /// helper function to decode a member into an `IPklValue`
 #[inline]
-fn decode_member(value: &rmpv::Value) -> Result<IPklValue> {
+pub fn decode_member(value: &rmpv::Value) -> Result<IPklValue> {
     // if its an array, parse the inner object, otherwise parse the primitive value
     if let Some(array) = value.as_array() {
         Ok(decode_non_primitive(array)?.into())
@@ -155,6 +155,20 @@ fn decode_mapping(type_id: u64, slots: &[rmpv::Value]) -> Result<PklNonPrimitive
         )));
     };
 
+    // keys of any other type (`Mapping<Int, String>`, `Map<Duration, ...>`) are kept as decoded values
+    if !values.iter().all(|(k, _)| k.is_str()) {
+        let entries = values
+            .iter()
+            .map(|(k, v)| Ok((decode_member(k)?.into(), decode_member(v)?.into())))
+            .collect::<Result<Vec<_>>>()?;
+        let entries = if type_id == type_constants::MAPPING {
+            PklValue::MappingEntries(entries)
+        } else {
+            PklValue::Entries(entries)
+        };
+        return Ok(PklNonPrimitive::Mapping(type_id, entries));
+    }
+
     #[cfg(feature = "indexmap")]
     let mut mapping: MapImpl<String, PklValue> = IndexMap::with_capacity(values.len());
     #[cfg(not(feature = "indexmap"))]
@@ -166,7 +180,12 @@ fn decode_mapping(type_id: u64, slots: &[rmpv::Value]) -> Result<PklNonPrimitive
         mapping.insert(key.to_string(), value.into());
     }
 
-    Ok(PklNonPrimitive::Mapping(type_id, PklValue::Map(mapping)))
+    let mapping = if type_id == type_constants::MAPPING {
+        PklValue::Mapping(mapping)
+    } else {
+        PklValue::Map(mapping)
+    };
+    Ok(PklNonPrimitive::Mapping(type_id, mapping))
 }
 
 fn decode_list(type_id: u64, slots: &[rmpv::Value]) -> Result<PklNonPrimitive> {
@@ -224,32 +243,16 @@ fn decode_duration(type_id: u64, slots: &[rmpv::Value]) -> Result<PklNonPrimitiv
     let value = slots[0].as_f64().context("expected float for duration")?;
     let unit = slots[1].as_str().context("expected time type")?;
 
-    let nanos_f64 = match unit {
-        "ns" => value,
-        "us" => value * 1_000.0,
-        "ms" => value * 1_000_000.0,
-        "s" => value * 1_000_000_000.0,
-        "min" => value * 60.0 * 1_000_000_000.0,
-        "h" => value * 3_600.0 * 1_000_000_000.0,
-        "d" => value * 86_400.0 * 1_000_000_000.0,
-        _ => {
-            return Err(Error::DecodeError(format!(
-                "unknown duration unit: {unit:?}"
-            )));
-        }
-    };
-
-    if !nanos_f64.is_finite() || nanos_f64 < 0.0 {
-        // TODO: pkl allows for negative durations
-        // supporting this would require adding a new type that can represent negative durations
+    let Ok(unit) = DurationUnit::try_from(unit) else {
         return Err(Error::DecodeError(format!(
-            "invalid duration value: {value} {unit}"
+            "unknown duration unit: {unit:?}"
         )));
-    }
-
-    let duration = std::time::Duration::from_nanos(nanos_f64.round() as u64);
+    };
 
-    Ok(PklNonPrimitive::Duration(type_id, duration))
+    Ok(PklNonPrimitive::Duration(
+        type_id,
+        Duration::new(value, unit),
+    ))
 }
 
 #[inline]
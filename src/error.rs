@@ -2,6 +2,13 @@ use std::fmt::{self, Display};
 
 use serde::{de, ser};
 
+use crate::api::command::PklVersion;
+use crate::value::PklValue;
+
+mod eval;
+
+pub use eval::{EvalError, SourceLocation, StackFrame};
+
 pub type Result<T> = std::result::Result<T, Error>;
 
 #[derive(Debug)]
@@ -10,16 +17,32 @@ pub enum Error {
 
     PklSend,
     PklRecv,
-    PklMalformedResponse { message: String },
-    PklProcessStart,
-    PklServerError { pkl_error: String },
+    PklMalformedResponse {
+        message: String,
+    },
+    /// The `pkl` executable could not be spawned
+    PklProcessStart(std::io::Error),
+    /// The pkl server failed to evaluate the module
+    PklServerError(EvalError),
+    /// The pkl executable is older than a feature the evaluator was configured with
+    UnsupportedPklVersion {
+        version: PklVersion,
+        required: PklVersion,
+        feature: &'static str,
+    },
+
+    Io(std::io::Error),
 
     DecodeError(String),
-    DeserializeError(String),
+    DeserializeError(DeserializeError),
 
     MsgpackSerializeError(rmp_serde::encode::Error),
     MsgpackEncodeError(rmpv::encode::Error),
     MsgpackDecodeError(rmpv::decode::Error),
+
+    /// The files of a watched module couldn't be watched
+    #[cfg(feature = "watch")]
+    Watch(notify::Error),
 }
 
 impl ser::Error for Error {
@@ -30,7 +53,44 @@ impl ser::Error for Error {
 
 impl de::Error for Error {
     fn custom<T: Display>(msg: T) -> Self {
-        Error::Message(msg.to_string())
+        Error::DeserializeError(DeserializeError::new(msg.to_string()))
+    }
+
+    fn invalid_type(unexp: de::Unexpected, exp: &dyn de::Expected) -> Self {
+        Error::DeserializeError(DeserializeError {
+            expected: Some(exp.to_string()),
+            ..DeserializeError::new(format!("invalid type: {unexp}, expected {exp}"))
+        })
+    }
+
+    fn invalid_value(unexp: de::Unexpected, exp: &dyn de::Expected) -> Self {
+        Error::DeserializeError(DeserializeError {
+            expected: Some(exp.to_string()),
+            ..DeserializeError::new(format!("invalid value: {unexp}, expected {exp}"))
+        })
+    }
+}
+
+impl Error {
+    /// Prepend a segment to the path of a deserialization error, used while unwinding out of nested values
+    pub(crate) fn with_parent(self, segment: PathSegment) -> Self {
+        let mut e = DeserializeError::from(self);
+        e.path.insert(0, segment);
+        Error::DeserializeError(e)
+    }
+
+    /// Records the type of the pkl value that failed to deserialize,
+    /// if the error was raised for this value and not one of its members
+    pub(crate) fn with_actual(self, value: &PklValue) -> Self {
+        match self {
+            Error::DeserializeError(mut e)
+                if e.expected.is_some() && e.actual.is_none() && e.path.is_empty() =>
+            {
+                e.actual = Some(value.type_name());
+                Error::DeserializeError(e)
+            }
+            e => e,
+        }
     }
 }
 
@@ -38,23 +98,186 @@ impl Display for Error {
     fn fmt(&self, formatter: &mut fmt::Formatter) -> fmt::Result {
         match self {
             Error::Message(msg)
-            | Error::DeserializeError(msg)
             | Error::DecodeError(msg)
-            | Error::PklServerError { pkl_error: msg }
             | Error::PklMalformedResponse { message: msg } => formatter.write_str(msg),
 
+            Error::PklSend => formatter.write_str("failed to send message to the pkl process"),
+            Error::PklRecv => formatter.write_str("failed to receive message from the pkl process"),
+            Error::PklProcessStart(e) => write!(formatter, "failed to start the pkl process: {e}"),
+            Error::PklServerError(e) => e.fmt(formatter),
+            Error::UnsupportedPklVersion {
+                version,
+                required,
+                feature,
+            } => write!(
+                formatter,
+                "pkl {version} doesn't support {feature}, which requires pkl {required} or newer"
+            ),
+
+            Error::Io(e) => e.fmt(formatter),
+
+            Error::DeserializeError(e) => e.fmt(formatter),
+
+            Error::MsgpackSerializeError(e) => {
+                write!(formatter, "failed to serialize message: {e}")
+            }
+            Error::MsgpackEncodeError(e) => write!(formatter, "failed to encode message: {e}"),
             Error::MsgpackDecodeError(e) => formatter.write_str(&e.to_string()),
 
-            _ => formatter.write_str("unknown error"),
+            #[cfg(feature = "watch")]
+            Error::Watch(e) => write!(formatter, "failed to watch files: {e}"),
+        }
+    }
+}
+
+impl std::error::Error for Error {
+    fn source(&self) -> Option<&(dyn std::error::Error + 'static)> {
+        match self {
+            Error::PklProcessStart(e) | Error::Io(e) => Some(e),
+            Error::PklServerError(e) => Some(e),
+            Error::DeserializeError(e) => Some(e),
+            Error::MsgpackSerializeError(e) => Some(e),
+            Error::MsgpackEncodeError(e) => Some(e),
+            Error::MsgpackDecodeError(e) => Some(e),
+            #[cfg(feature = "watch")]
+            Error::Watch(e) => Some(e),
+            _ => None,
+        }
+    }
+}
+
+/// A segment of the path to a value that failed to deserialize
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum PathSegment {
+    /// A property of an object, or a key of a map
+    Field(String),
+    /// An element of a list
+    Index(usize),
+    /// A non-string key of a map, rendered as a pkl expression, e.g. `80` or `5.min`
+    Key(String),
+}
+
+/// An error that occurred while deserializing an evaluated pkl value.
+///
+/// Carries the path to the member that failed to deserialize, e.g. `database.replicas[2].port`,
+/// and for type mismatches, what was expected and the type of the pkl value that was found.
+#[derive(Debug, Clone, PartialEq)]
+pub struct DeserializeError {
+    path: Vec<PathSegment>,
+    message: String,
+    expected: Option<String>,
+    actual: Option<&'static str>,
+}
+
+impl DeserializeError {
+    pub fn new(message: impl Into<String>) -> Self {
+        DeserializeError {
+            path: vec![],
+            message: message.into(),
+            expected: None,
+            actual: None,
+        }
+    }
+
+    /// A type mismatch between what the rust type `expected` and the type of the pkl value that was found
+    pub(crate) fn mismatch(expected: &str, actual: &'static str) -> Self {
+        DeserializeError {
+            expected: Some(expected.to_string()),
+            actual: Some(actual),
+            ..DeserializeError::new(format!("invalid type: {actual}, expected {expected}"))
+        }
+    }
+
+    /// The error message, without the path.
+    pub fn message(&self) -> &str {
+        &self.message
+    }
+
+    /// The path to the member that failed to deserialize, starting from the root.
+    ///
+    /// Empty if the error occurred at the root value.
+    pub fn path(&self) -> &[PathSegment] {
+        &self.path
+    }
+
+    /// What the rust type expected, e.g. `u16` or `a sequence`, if the error is a type mismatch.
+    pub fn expected(&self) -> Option<&str> {
+        self.expected.as_deref()
+    }
+
+    /// The type of the pkl value that was found, e.g. `String` or `Duration`, if the error is a type mismatch.
+    pub fn actual(&self) -> Option<&'static str> {
+        self.actual
+    }
+
+    /// Renders the path in pkl syntax, e.g. `database.replicas[2].port` or `paths["*"]`
+    pub fn path_string(&self) -> String {
+        let mut out = String::new();
+        for segment in &self.path {
+            match segment {
+                PathSegment::Field(field) if is_identifier(field) => {
+                    if !out.is_empty() {
+                        out.push('.');
+                    }
+                    out.push_str(field);
+                }
+                PathSegment::Field(field) => {
+                    out.push_str(&format!("[{field:?}]"));
+                }
+                PathSegment::Key(key) => {
+                    out.push('[');
+                    out.push_str(key);
+                    out.push(']');
+                }
+                PathSegment::Index(index) => {
+                    out.push('[');
+                    out.push_str(&index.to_string());
+                    out.push(']');
+                }
+            }
+        }
+        out
+    }
+}
+
+fn is_identifier(s: &str) -> bool {
+    let mut chars = s.chars();
+    chars
+        .next()
+        .is_some_and(|c| c.is_alphabetic() || c == '_' || c == '$')
+        && chars.all(|c| c.is_alphanumeric() || c == '_' || c == '$')
+}
+
+impl Display for DeserializeError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        if self.path.is_empty() {
+            f.write_str(&self.message)
+        } else {
+            write!(f, "{}: {}", self.path_string(), self.message)
+        }
+    }
+}
+
+impl std::error::Error for DeserializeError {}
+
+impl From<Error> for DeserializeError {
+    fn from(e: Error) -> Self {
+        match e {
+            Error::DeserializeError(e) => e,
+            e => DeserializeError::new(e.to_string()),
         }
     }
 }
 
-impl std::error::Error for Error {}
+impl From<DeserializeError> for Error {
+    fn from(e: DeserializeError) -> Self {
+        Error::DeserializeError(e)
+    }
+}
 
 impl From<std::io::Error> for Error {
     fn from(e: std::io::Error) -> Self {
-        Error::Message(e.to_string())
+        Error::Io(e)
     }
 }
 
@@ -76,8 +299,23 @@ impl From<rmpv::decode::Error> for Error {
     }
 }
 
+#[cfg(feature = "watch")]
+impl From<notify::Error> for Error {
+    fn from(e: notify::Error) -> Self {
+        Error::Watch(e)
+    }
+}
+
 impl From<Box<dyn std::error::Error>> for Error {
     fn from(e: Box<dyn std::error::Error>) -> Self {
-        Error::Message(e.to_string())
+        // keep the original error if it's one we know about, so `source()` isn't lost
+        let e = match e.downcast::<Error>() {
+            Ok(e) => return *e,
+            Err(e) => e,
+        };
+        match e.downcast::<std::io::Error>() {
+            Ok(e) => Error::Io(*e),
+            Err(e) => Error::Message(e.to_string()),
+        }
     }
 }
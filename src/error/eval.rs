@@ -0,0 +1,315 @@
+use std::fmt::{self, Display};
+
+/// An error returned by the pkl server when a module fails to evaluate.
+///
+/// Parsed from the error text rendered by pkl, e.g.
+///
+/// ```text
+/// –– Pkl Error ––
+/// Type constraint `isBetween(0, 65535)` violated.
+/// Value: 70000
+///
+/// 3 | port: Int(isBetween(0, 65535)) = 70000
+///               ^^^^^^^^^^^^^^^^^^^
+/// at config#port (file:///path/to/config.pkl)
+/// ```
+///
+/// The original text is available through [`EvalError::raw`] and is used for `Display`.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct EvalError {
+    title: String,
+    message: String,
+    frames: Vec<StackFrame>,
+    hint: Option<String>,
+    raw: String,
+}
+
+/// A frame of the pkl stack trace.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct StackFrame {
+    /// The member being evaluated, e.g. `config#port`. `None` if pkl reported it as `<unknown>`.
+    pub member: Option<String>,
+
+    /// Where in the source the frame is located.
+    pub location: SourceLocation,
+
+    /// The line of source code shown for this frame.
+    pub source: Option<String>,
+}
+
+/// A location within a pkl module.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct SourceLocation {
+    /// The URI of the module, e.g. `file:///path/to/config.pkl`
+    pub uri: String,
+
+    /// 1-based line number
+    pub line: Option<usize>,
+
+    /// 1-based column number
+    pub column: Option<usize>,
+}
+
+impl EvalError {
+    /// The title from the error header, usually `Pkl Error`.
+    pub fn title(&self) -> &str {
+        &self.title
+    }
+
+    /// The error message, without the stack trace.
+    pub fn message(&self) -> &str {
+        &self.message
+    }
+
+    /// The stack frames, innermost first.
+    pub fn frames(&self) -> &[StackFrame] {
+        &self.frames
+    }
+
+    /// The location of the innermost stack frame, i.e. where the error occurred.
+    pub fn location(&self) -> Option<&SourceLocation> {
+        self.frames.first().map(|f| &f.location)
+    }
+
+    /// Any text rendered after the stack trace, such as suggestions.
+    pub fn hint(&self) -> Option<&str> {
+        self.hint.as_deref()
+    }
+
+    /// The error exactly as rendered by pkl.
+    pub fn raw(&self) -> &str {
+        &self.raw
+    }
+
+    pub(crate) fn parse(raw: &str) -> Self {
+        let lines = raw.lines().collect::<Vec<_>>();
+        let mut i = 0;
+
+        let title = match lines.first().and_then(|line| parse_header(line)) {
+            Some(title) => {
+                i += 1;
+                title
+            }
+            None => "Pkl Error".to_owned(),
+        };
+
+        let mut message = vec![];
+        while i < lines.len()
+            && parse_snippet(lines[i]).is_none()
+            && parse_frame(lines[i]).is_none()
+        {
+            message.push(lines[i]);
+            i += 1;
+        }
+
+        let mut frames = vec![];
+        let mut snippet: Option<Snippet> = None;
+        let mut trailing = vec![];
+
+        for line in &lines[i..] {
+            if let Some(s) = parse_snippet(line) {
+                // only the first line of a multi-line snippet is relevant for the location
+                if snippet.is_none() {
+                    snippet = Some(s);
+                }
+                continue;
+            }
+
+            if let Some(s) = snippet.as_mut()
+                && s.column.is_none()
+                && is_caret_line(line)
+            {
+                let caret = line.find('^').unwrap_or_default();
+                s.column = caret.checked_sub(s.code_offset).map(|c| c + 1);
+                continue;
+            }
+
+            if let Some((member, uri, line_hint)) = parse_frame(line) {
+                let snippet = snippet.take();
+                frames.push(StackFrame {
+                    member,
+                    location: SourceLocation {
+                        uri,
+                        line: snippet.as_ref().map(|s| s.line).or(line_hint),
+                        column: snippet.as_ref().and_then(|s| s.column),
+                    },
+                    source: snippet.map(|s| s.code),
+                });
+                trailing.clear();
+                continue;
+            }
+
+            trailing.push(*line);
+        }
+
+        let hint = trailing.join("\n").trim().to_owned();
+
+        EvalError {
+            title,
+            message: message.join("\n").trim().to_owned(),
+            frames,
+            hint: (!hint.is_empty()).then_some(hint),
+            raw: raw.to_owned(),
+        }
+    }
+}
+
+impl Display for EvalError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(&self.raw)
+    }
+}
+
+impl std::error::Error for EvalError {}
+
+struct Snippet {
+    line: usize,
+    code: String,
+    /// offset of the code within the rendered line, used to compute the column from the carets
+    code_offset: usize,
+    column: Option<usize>,
+}
+
+/// `–– Pkl Error ––`
+fn parse_header(line: &str) -> Option<String> {
+    let trimmed = line.trim();
+    if !(trimmed.starts_with('–') || trimmed.starts_with("--")) {
+        return None;
+    }
+    let title = trimmed.trim_matches(|c: char| c == '–' || c == '-' || c.is_whitespace());
+    (!title.is_empty()).then(|| title.to_owned())
+}
+
+/// `3 | port: Int(isBetween(0, 65535)) = 70000`
+fn parse_snippet(line: &str) -> Option<Snippet> {
+    let indent = line.len() - line.trim_start().len();
+    let rest = &line[indent..];
+    let digits = rest.chars().take_while(char::is_ascii_digit).count();
+    if digits == 0 {
+        return None;
+    }
+    let line_number = rest[..digits].parse().ok()?;
+    let after = &rest[digits..];
+    let code = after.strip_prefix(" | ").or_else(|| {
+        // empty source lines are rendered without the trailing space
+        (after == " |").then_some("")
+    })?;
+
+    Some(Snippet {
+        line: line_number,
+        code: code.to_owned(),
+        code_offset: line.len() - code.len(),
+        column: None,
+    })
+}
+
+fn is_caret_line(line: &str) -> bool {
+    let trimmed = line.trim();
+    !trimmed.is_empty() && trimmed.chars().all(|c| c == '^')
+}
+
+/// `at config#port (file:///path/to/config.pkl)`
+///
+/// Returns the member, the URI and a line number if the location carries one
+/// (`(uri, line 3)` or `(https://.../base.pkl#L106)`).
+fn parse_frame(line: &str) -> Option<(Option<String>, String, Option<usize>)> {
+    let rest = line.trim().strip_prefix("at ")?;
+    let open = rest.find(" (")?;
+    let location = rest[open + 2..].strip_suffix(')')?;
+    let member = &rest[..open];
+    let member = (member != "<unknown>").then(|| member.to_owned());
+
+    if let Some((uri, line)) = location.rsplit_once(", line ")
+        && let Ok(line) = line.parse()
+    {
+        return Some((member, uri.to_owned(), Some(line)));
+    }
+
+    let line = location
+        .rsplit_once("#L")
+        .and_then(|(_, line)| line.parse().ok());
+
+    Some((member, location.to_owned(), line))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn parse_type_constraint_error() {
+        let raw = "–– Pkl Error ––
+Type constraint `isBetween(0, 65535)` violated.
+Value: 70000
+
+3 | port: Int(isBetween(0, 65535)) = 70000
+              ^^^^^^^^^^^^^^^^^^^
+at config#port (file:///path/to/config.pkl)
+
+106 | text = renderer.renderDocument(value)
+             ^^^^^^^^^^^^^^^^^^^^^^^^^^^^^^^
+at pkl.base#Module.output.text (https://github.com/apple/pkl/blob/0.29.0/stdlib/base.pkl#L106)
+";
+        let err = EvalError::parse(raw);
+
+        assert_eq!(err.title(), "Pkl Error");
+        assert_eq!(
+            err.message(),
+            "Type constraint `isBetween(0, 65535)` violated.\nValue: 70000"
+        );
+        assert_eq!(err.frames().len(), 2);
+        assert_eq!(err.hint(), None);
+        assert_eq!(err.to_string(), raw);
+
+        let frame = &err.frames()[0];
+        assert_eq!(frame.member.as_deref(), Some("config#port"));
+        assert_eq!(
+            frame.source.as_deref(),
+            Some("port: Int(isBetween(0, 65535)) = 70000")
+        );
+        assert_eq!(
+            err.location(),
+            Some(&SourceLocation {
+                uri: "file:///path/to/config.pkl".into(),
+                line: Some(3),
+                column: Some(11),
+            })
+        );
+
+        let frame = &err.frames()[1];
+        assert_eq!(frame.location.line, Some(106));
+        assert_eq!(frame.location.column, Some(8));
+    }
+
+    #[test]
+    fn parse_error_with_hint() {
+        let raw = "–– Pkl Error ––
+Cannot find property `usrname`.
+
+1 | name = usrname
+           ^^^^^^^
+at text#name (repl:text)
+
+Did you mean any of the following?
+username";
+        let err = EvalError::parse(raw);
+
+        assert_eq!(err.message(), "Cannot find property `usrname`.");
+        assert_eq!(err.frames().len(), 1);
+        assert_eq!(err.location().unwrap().column, Some(8));
+        assert_eq!(
+            err.hint(),
+            Some("Did you mean any of the following?\nusername")
+        );
+    }
+
+    #[test]
+    fn parse_unstructured_error() {
+        let err = EvalError::parse("Cannot find module `file:///missing.pkl`.");
+
+        assert_eq!(err.title(), "Pkl Error");
+        assert_eq!(err.message(), "Cannot find module `file:///missing.pkl`.");
+        assert!(err.frames().is_empty());
+        assert!(err.location().is_none());
+    }
+}
@@ -1,6 +1,8 @@
 pub const CREATE_EVALUATOR: u64 = 0x20;
+pub const CREATE_EVALUATOR_RESPONSE: u64 = 0x21;
 pub const CLOSE: u64 = 0x22;
 pub const EVALUATE_REQUEST: u64 = 0x23;
+pub const EVALUATE_RESPONSE: u64 = 0x24;
 
 pub const READ_RESOURCE_REQUEST: u64 = 0x26;
 pub const READ_RESOURCE_RESPONSE: u64 = 0x27;
@@ -18,3 +20,5 @@ pub const INITIALIZE_MODULE_READER_RESPONSE: u64 = 0x2f;
 pub const INITIALIZE_RESOURCE_READER_REQUEST: u64 = 0x30;
 pub const INITIALIZE_RESOURCE_READER_RESPONSE: u64 = 0x31;
 pub const CLOSE_EXTERNAL_PROCESS: u64 = 0x32;
+
+pub const LOG_MESSAGE: u64 = 0x100;
@@ -6,13 +6,14 @@ use serde::Serialize;
 
 use crate::{
     EvaluatorOptions,
-    api::{
-        evaluator::CREATE_EVALUATOR_REQUEST_ID,
-        reader::{PklModuleReader, PklResourceReader},
-    },
+    api::evaluator::{DEFAULT_ALLOWED_MODULES, DEFAULT_ALLOWED_RESOURCES},
+    api::reader::{PklModuleReader, PklResourceReader},
     internal::msgapi::impl_pkl_message,
 };
 
+#[cfg(feature = "async")]
+use crate::api::reader::{AsyncPklModuleReader, AsyncPklResourceReader};
+
 impl From<&dyn PklModuleReader> for ClientModuleReader {
     fn from(reader: &dyn PklModuleReader) -> Self {
         ClientModuleReader {
@@ -34,6 +35,29 @@ impl From<&dyn PklResourceReader> for ClientResourceReader {
     }
 }
 
+#[cfg(feature = "async")]
+impl From<&dyn AsyncPklModuleReader> for ClientModuleReader {
+    fn from(reader: &dyn AsyncPklModuleReader) -> Self {
+        ClientModuleReader {
+            scheme: reader.scheme().to_string(),
+            has_hierarchical_uris: reader.has_hierarchical_uris(),
+            is_globbable: reader.is_globbable(),
+            is_local: reader.is_local(),
+        }
+    }
+}
+
+#[cfg(feature = "async")]
+impl From<&dyn AsyncPklResourceReader> for ClientResourceReader {
+    fn from(reader: &dyn AsyncPklResourceReader) -> Self {
+        ClientResourceReader {
+            scheme: reader.scheme().to_string(),
+            has_hierarchical_uris: reader.has_hierarchical_uris(),
+            is_globbable: reader.is_globbable(),
+        }
+    }
+}
+
 /// HTTP proxy configuration for the CreateEvaluator message.
 #[derive(Serialize, Clone, Debug)]
 #[serde(rename_all = "camelCase")]
@@ -62,7 +86,7 @@ pub struct Http {
 
 #[derive(Serialize)]
 #[serde(rename_all = "camelCase")]
-pub(crate) struct CreateEvaluator<'a> {
+pub(crate) struct CreateEvaluator {
     pub request_id: u64,
     pub allowed_modules: Vec<String>,
     pub allowed_resources: Vec<String>,
@@ -77,12 +101,12 @@ pub(crate) struct CreateEvaluator<'a> {
     pub env: Option<HashMap<String, String>>,
 
     #[serde(skip_serializing_if = "Option::is_none")]
-    pub properties: Option<&'a MapImpl<String, String>>,
+    pub properties: Option<MapImpl<String, String>>,
 
     #[serde(skip_serializing_if = "Option::is_none")]
-    pub external_resource_readers: Option<&'a HashMap<String, ExternalReader>>,
+    pub external_resource_readers: Option<HashMap<String, ExternalReader>>,
     #[serde(skip_serializing_if = "Option::is_none")]
-    pub external_module_readers: Option<&'a HashMap<String, ExternalReader>>,
+    pub external_module_readers: Option<HashMap<String, ExternalReader>>,
 
     /// HTTP configuration for outgoing requests (proxy, CA certificates).
     /// Added in Pkl 0.26.0.
@@ -92,28 +116,86 @@ pub(crate) struct CreateEvaluator<'a> {
     /// Timeout in seconds for evaluating a source module.
     #[serde(skip_serializing_if = "Option::is_none")]
     pub timeout_seconds: Option<u64>,
+
+    /// The project whose dependencies can be imported with `@name/...`
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub project: Option<ProjectOrDependency>,
+
+    /// Directories and zip files searched by `modulepath:` URIs
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub module_paths: Option<Vec<String>>,
+
+    /// Restricts file-based modules and resources to this directory
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub root_dir: Option<String>,
+
+    /// Directory packages are cached in. Packages aren't cached if it isn't set.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub cache_dir: Option<String>,
+
+    /// Format of `output.text`, e.g. `json` or `yaml`
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub output_format: Option<String>,
+
+    /// Set when `allowed_modules` came from the options rather than the defaults,
+    /// in which case a project's `evaluatorSettings` don't replace them
+    #[serde(skip)]
+    pub custom_allowed_modules: bool,
+
+    /// Set when `allowed_resources` came from the options rather than the defaults
+    #[serde(skip)]
+    pub custom_allowed_resources: bool,
+
+    /// Set when `env` was configured by the options rather than inherited in full
+    #[serde(skip)]
+    pub custom_env: bool,
+}
+
+/// A project, or one of its dependencies, in the CreateEvaluator message.
+///
+/// `type` is `local` for projects on disk and `remote` for packages.
+#[derive(Serialize, Clone, Debug, PartialEq)]
+#[serde(rename_all = "camelCase")]
+pub(crate) struct ProjectOrDependency {
+    #[serde(rename = "type")]
+    pub kind: &'static str,
+
+    /// The canonical URI of the package
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub package_uri: Option<String>,
+
+    /// The URI of the `PklProject` file, for local projects
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub project_file_uri: Option<String>,
+
+    /// Checksums of a remote dependency
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub checksums: Option<Checksums>,
+
+    /// Dependencies of a local project, by name
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub dependencies: Option<HashMap<String, ProjectOrDependency>>,
+}
+
+#[derive(Serialize, serde::Deserialize, Clone, Debug, PartialEq)]
+pub(crate) struct Checksums {
+    pub sha256: String,
 }
 
-impl Default for CreateEvaluator<'_> {
+impl Default for CreateEvaluator {
     fn default() -> Self {
-        let env_vars: HashMap<String, String> = std::env::vars().collect();
         Self {
-            request_id: CREATE_EVALUATOR_REQUEST_ID,
-            allowed_modules: vec![
-                "pkl:".into(),
-                "repl:".into(),
-                "file:".into(),
-                "https:".into(),
-                "package:".into(),
-            ],
-            allowed_resources: vec![
-                "env:".into(),
-                "prop:".into(),
-                "package:".into(),
-                "https:".into(),
-                "projectpackage:".into(),
-            ],
-            env: Some(env_vars),
+            // assigned by the server connection when the message is sent
+            request_id: 0,
+            allowed_modules: DEFAULT_ALLOWED_MODULES
+                .iter()
+                .map(ToString::to_string)
+                .collect(),
+            allowed_resources: DEFAULT_ALLOWED_RESOURCES
+                .iter()
+                .map(ToString::to_string)
+                .collect(),
+            env: None,
 
             client_module_readers: None,
             client_resource_readers: None,
@@ -122,18 +204,37 @@ impl Default for CreateEvaluator<'_> {
             external_module_readers: None,
             http: None,
             timeout_seconds: None,
+            project: None,
+            module_paths: None,
+            root_dir: None,
+            cache_dir: None,
+            output_format: None,
+            custom_allowed_modules: false,
+            custom_allowed_resources: false,
+            custom_env: false,
         }
     }
 }
 
-impl<'a> From<&'a EvaluatorOptions> for CreateEvaluator<'a> {
-    fn from(opts: &'a EvaluatorOptions) -> Self {
+impl From<&EvaluatorOptions> for CreateEvaluator {
+    fn from(opts: &EvaluatorOptions) -> Self {
         let mut evaluator_message = CreateEvaluator::default();
 
         /* handle user defined options */
         {
+            // set before the reader schemes below are added to them
+            if let Some(allowed) = &opts.allowed_modules {
+                evaluator_message.allowed_modules = allowed.clone();
+                evaluator_message.custom_allowed_modules = true;
+            }
+
+            if let Some(allowed) = &opts.allowed_resources {
+                evaluator_message.allowed_resources = allowed.clone();
+                evaluator_message.custom_allowed_resources = true;
+            }
+
             if let Some(props) = &opts.properties {
-                evaluator_message.properties = Some(props);
+                evaluator_message.properties = Some(props.clone());
             }
 
             if let Some(readers) = opts.client_module_readers.as_ref() {
@@ -163,19 +264,45 @@ impl<'a> From<&'a EvaluatorOptions> for CreateEvaluator<'a> {
                 evaluator_message.client_resource_readers = Some(resource_readers);
             }
 
+            #[cfg(feature = "async")]
+            if let Some(readers) = opts.async_module_readers.as_ref() {
+                for reader in readers {
+                    evaluator_message
+                        .allowed_modules
+                        .push(reader.scheme().to_string());
+                }
+                evaluator_message
+                    .client_module_readers
+                    .get_or_insert_default()
+                    .extend(readers.iter().map(|r| r.as_ref().into()));
+            }
+
+            #[cfg(feature = "async")]
+            if let Some(readers) = opts.async_resource_readers.as_ref() {
+                for reader in readers {
+                    evaluator_message
+                        .allowed_resources
+                        .push(reader.scheme().to_string());
+                }
+                evaluator_message
+                    .client_resource_readers
+                    .get_or_insert_default()
+                    .extend(readers.iter().map(|r| r.as_ref().into()));
+            }
+
             if let Some(readers) = &opts.external_resource_readers {
                 for uri in readers.keys() {
                     evaluator_message.allowed_resources.push(uri.clone());
                 }
 
-                evaluator_message.external_resource_readers = Some(readers);
+                evaluator_message.external_resource_readers = Some(readers.clone());
             }
 
             if let Some(readers) = &opts.external_module_readers {
                 for uri in readers.keys() {
                     evaluator_message.allowed_modules.push(uri.clone());
                 }
-                evaluator_message.external_module_readers = Some(readers);
+                evaluator_message.external_module_readers = Some(readers.clone());
             }
 
             // Handle HTTP configuration (proxy, CA certificates)
@@ -194,6 +321,20 @@ impl<'a> From<&'a EvaluatorOptions> for CreateEvaluator<'a> {
             if let Some(timeout) = opts.timeout_seconds {
                 evaluator_message.timeout_seconds = Some(timeout);
             }
+
+            evaluator_message.module_paths = opts
+                .module_paths
+                .as_ref()
+                .map(|paths| paths.iter().map(|p| path_string(p)).collect());
+            evaluator_message.root_dir = opts.root_dir.as_deref().map(path_string);
+            evaluator_message.cache_dir = opts.cache_dir.as_deref().map(path_string);
+            evaluator_message.output_format = opts.output_format.clone();
+
+            let inherit = opts.inherit_env.clone().unwrap_or_default();
+            let mut env = inherit.filter(std::env::vars());
+            env.extend(opts.env.clone().unwrap_or_default());
+            evaluator_message.env = Some(env);
+            evaluator_message.custom_env = opts.env.is_some() || opts.inherit_env.is_some();
         }
         /* */
 
@@ -230,6 +371,11 @@ pub struct ClientResourceReader {
     pub is_globbable: bool,
 }
 
+/// Paths are sent to pkl as strings
+pub(crate) fn path_string(path: &std::path::Path) -> String {
+    path.to_string_lossy().into_owned()
+}
+
 #[derive(Serialize)]
 #[serde(rename_all = "camelCase")]
 pub struct CloseEvaluator {
@@ -242,6 +388,16 @@ pub struct EvaluateRequest {
     pub request_id: u64,
     pub evaluator_id: i64,
     pub module_uri: String,
+
+    /// The text contents of the module.
+    /// If set, the module is evaluated from this text instead of being read from `module_uri`.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub module_text: Option<String>,
+
+    /// The expression to evaluate within the module.
+    /// If not set, the whole module is evaluated.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub expr: Option<String>,
 }
 
 /// Code: 0x27
@@ -391,7 +547,7 @@ pub struct ExternalReader {
     pub arguments: Vec<String>,
 }
 
-impl_pkl_message!(CreateEvaluator<'a>, CREATE_EVALUATOR);
+impl_pkl_message!(CreateEvaluator, CREATE_EVALUATOR);
 impl_pkl_message!(EvaluateRequest, EVALUATE_REQUEST);
 impl_pkl_message!(CloseEvaluator, CLOSE);
 
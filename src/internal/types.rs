@@ -1,4 +1,5 @@
 use crate::IntoPklMap;
+use crate::internal::type_constants;
 use crate::pkl::de::PklVisitor;
 use crate::value::{DataSize, PklValue};
 use serde::{Deserialize, Serialize};
@@ -66,12 +67,16 @@ impl From<PklPrimitive> for PklValue {
 impl From<PklNonPrimitive> for PklValue {
     fn from(np: PklNonPrimitive) -> Self {
         match np {
-            PklNonPrimitive::TypedDynamic(_, _, _, children) => {
-                PklValue::Map(children.into_pkl_map())
-            }
-            PklNonPrimitive::List(_, items) | PklNonPrimitive::Set(_, items) => {
-                PklValue::List(items.into_iter().collect())
+            PklNonPrimitive::TypedDynamic(_, class_name, module_uri, children) => {
+                PklValue::Object {
+                    class_name,
+                    module_uri,
+                    members: children.into_pkl_map(),
+                }
             }
+            PklNonPrimitive::List(type_constants::LISTING, items) => PklValue::Listing(items),
+            PklNonPrimitive::List(_, items) => PklValue::List(items),
+            PklNonPrimitive::Set(_, items) => PklValue::Set(items),
             PklNonPrimitive::Mapping(_, m) => m,
             PklNonPrimitive::Duration(_, d) => PklValue::Duration(d),
             PklNonPrimitive::DataSize(_, ds) => PklValue::DataSize(ds),
@@ -107,6 +112,8 @@ pub enum Integer {
 /// Internal struct used for deserializing `.pkl` objects
 #[derive(Debug, Clone, Serialize)]
 #[serde(untagged)]
+// only lives for the duration of decoding, it's converted into a `PklValue` right after
+#[allow(clippy::large_enum_variant)]
 pub(crate) enum IPklValue {
     Primitive(PklPrimitive),
     NonPrimitive(PklNonPrimitive),
@@ -152,7 +159,7 @@ pub(crate) enum PklNonPrimitive {
     Mapping(u64, PklValue),
     Set(u64, Vec<PklValue>),
 
-    Duration(u64, std::time::Duration),
+    Duration(u64, crate::value::Duration),
     DataSize(u64, DataSize),
     Pair(u64, PklValue, PklValue),
     /// 0: type id, 1: start, 2: end, 3: step
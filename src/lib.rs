@@ -1,6 +1,7 @@
 pub mod api;
 #[cfg(feature = "codegen")]
 pub mod codegen;
+pub mod config;
 mod context;
 mod decoder;
 pub mod error;
@@ -8,10 +9,13 @@ mod internal;
 pub mod pkl;
 mod utils;
 pub mod value;
+#[cfg(feature = "watch")]
+pub mod watch;
 
 pub use error::{Error, Result};
 
-pub use api::evaluator::{EvaluatorOptions, HttpOptions, HttpProxy};
+pub use api::evaluator::{EvaluatorOptions, HttpOptions, HttpProxy, InheritEnv};
+pub use config::{ConfigBuilder, LayeredConfig, ListMerge};
 
 pub use value::PklValue as Value;
 
@@ -118,10 +122,281 @@ where
     let mut evaluator = api::Evaluator::new_from_options(options)?;
     let pkl_mod = evaluator.evaluate_module(path.as_ref())?;
 
+    deserialize_module(pkl_mod)
+}
+
+/// Evaluates pkl source text and deserializes it as `T`. The text is evaluated as the module `repl:text`.
+///
+/// # Example
+///
+/// ```no_run
+/// use serde::Deserialize;
+///
+/// #[derive(Deserialize)]
+/// struct Config {
+///     ip: String,
+///     port: u16,
+/// }
+///
+/// # fn main() -> Result<(), rpkl::Error> {
+/// let config: Config = rpkl::from_str(r#"
+///     ip = "127.0.0.1"
+///     port = 8080
+/// "#)?;
+/// #    Ok(())
+/// # }
+/// ```
+///
+/// # Errors
+/// - `DeserializeError`: If the deserialization fails.
+/// - If the evaluator fails to evaluate the module.
+pub fn from_str<T>(source: &str) -> Result<T>
+where
+    T: Sized + for<'de> serde::Deserialize<'de>,
+{
+    from_str_with_options(source, EvaluatorOptions::default())
+}
+
+/// Same as [`from_str`], but allows for passing options to the evaluator. See [`EvaluatorOptions`] for more information.
+///
+/// # Errors
+/// - `DeserializeError`: If the deserialization fails.
+/// - If the evaluator fails to evaluate the module.
+pub fn from_str_with_options<T>(source: &str, options: EvaluatorOptions) -> Result<T>
+where
+    T: Sized + for<'de> serde::Deserialize<'de>,
+{
+    let mut evaluator = api::Evaluator::new_from_options(options)?;
+    let pkl_mod = evaluator.evaluate_text(source, REPL_TEXT_URI)?;
+
+    deserialize_module(pkl_mod)
+}
+
+/// Evaluates the module at `uri` and deserializes it as `T`.
+///
+/// Any scheme allowed by the evaluator can be used, e.g. `https:`, `package:` or the scheme of a client module reader.
+///
+/// # Example
+///
+/// ```no_run
+/// use serde::Deserialize;
+///
+/// #[derive(Deserialize)]
+/// struct Config {
+///     ip: String,
+/// }
+///
+/// # fn main() -> Result<(), rpkl::Error> {
+/// let config: Config = rpkl::from_uri("https://example.com/config.pkl")?;
+/// #    Ok(())
+/// # }
+/// ```
+///
+/// # Errors
+/// - `DeserializeError`: If the deserialization fails.
+/// - If the evaluator fails to evaluate the module.
+pub fn from_uri<T>(uri: &str) -> Result<T>
+where
+    T: Sized + for<'de> serde::Deserialize<'de>,
+{
+    from_uri_with_options(uri, EvaluatorOptions::default())
+}
+
+/// Same as [`from_uri`], but allows for passing options to the evaluator. See [`EvaluatorOptions`] for more information.
+///
+/// # Errors
+/// - `DeserializeError`: If the deserialization fails.
+/// - If the evaluator fails to evaluate the module.
+pub fn from_uri_with_options<T>(uri: &str, options: EvaluatorOptions) -> Result<T>
+where
+    T: Sized + for<'de> serde::Deserialize<'de>,
+{
+    let mut evaluator = api::Evaluator::new_from_options(options)?;
+    let pkl_mod = evaluator.evaluate_uri(uri)?;
+
+    deserialize_module(pkl_mod)
+}
+
+/// Evaluates a single expression within a `.pkl` file and deserializes the result as `T`.
+///
+/// Useful for pulling a single subtree out of a large config or rendering `output.text`
+/// without deserializing the whole module.
+///
+/// # Example
+///
+/// ```no_run
+/// # fn main() -> Result<(), rpkl::Error> {
+/// let port: u16 = rpkl::from_expression("config.pkl", "database.port")?;
+/// let rendered: String = rpkl::from_expression("config.pkl", "output.text")?;
+/// #    Ok(())
+/// # }
+/// ```
+///
+/// # Errors
+/// - `DeserializeError`: If the deserialization fails.
+/// - If the evaluator fails to evaluate the expression.
+pub fn from_expression<T>(path: impl AsRef<std::path::Path>, expr: &str) -> Result<T>
+where
+    T: Sized + for<'de> serde::Deserialize<'de>,
+{
+    from_expression_with_options(path, expr, EvaluatorOptions::default())
+}
+
+/// Same as [`from_expression`], but allows for passing options to the evaluator. See [`EvaluatorOptions`] for more information.
+///
+/// # Errors
+/// - `DeserializeError`: If the deserialization fails.
+/// - If the evaluator fails to evaluate the expression.
+pub fn from_expression_with_options<T>(
+    path: impl AsRef<std::path::Path>,
+    expr: &str,
+    options: EvaluatorOptions,
+) -> Result<T>
+where
+    T: Sized + for<'de> serde::Deserialize<'de>,
+{
+    let mut evaluator = api::Evaluator::new_from_options(options)?;
+    let value = evaluator.evaluate_expression(path.as_ref(), expr)?;
+
+    utils::macros::_trace!("evaluated pkl expression {:?}", value);
+
+    T::deserialize(value.into_deserializer())
+}
+
+/// Deserializes an already evaluated [`Value`] as `T`.
+///
+/// Any value can be deserialized, not just modules, e.g. lists, scalars or nested objects.
+/// Useful for keeping part of a config as an opaque [`Value`] and converting it to a typed struct at the point of use.
+///
+/// # Example
+///
+/// ```
+/// use serde::Deserialize;
+///
+/// #[derive(Deserialize)]
+/// struct Database {
+///     host: String,
+///     port: u16,
+/// }
+///
+/// # fn main() -> Result<(), rpkl::Error> {
+/// let value = rpkl::pkl_value!({ "host": "localhost", "port": 5432 });
+///
+/// let database: Database = rpkl::from_value(value)?;
+/// assert_eq!(database.port, 5432);
+/// #    Ok(())
+/// # }
+/// ```
+///
+/// # Errors
+/// - `DeserializeError`: If the deserialization fails.
+pub fn from_value<T>(value: Value) -> Result<T>
+where
+    T: Sized + for<'de> serde::Deserialize<'de>,
+{
+    T::deserialize(value)
+}
+
+/// Same as [`from_value`], but deserializes from a reference so the value can be reused.
+///
+/// # Errors
+/// - `DeserializeError`: If the deserialization fails.
+pub fn from_value_ref<T>(value: &Value) -> Result<T>
+where
+    T: Sized + for<'de> serde::Deserialize<'de>,
+{
+    T::deserialize(value.into_deserializer())
+}
+
+/// Serializes `value` as the source text of a pkl module.
+///
+/// `value` must serialize to a struct or a map with string keys, each member becomes a property of the module.
+/// Nested structs are written as `new { ... }` objects, sequences as `new Listing { ... }` and maps as `new Mapping { ... }`.
+/// `std::time::Duration` and [`value::DataSize`] are written as pkl literals, e.g. `5.min` and `512.mb`.
+///
+/// Useful for generating amends files or fixtures from rust types.
+///
+/// # Example
+///
+/// ```
+/// use serde::Serialize;
+///
+/// #[derive(Serialize)]
+/// struct Config {
+///     ip: String,
+///     tags: Vec<String>,
+/// }
+///
+/// # fn main() -> Result<(), rpkl::Error> {
+/// let config = Config {
+///     ip: "127.0.0.1".into(),
+///     tags: vec!["web".into()],
+/// };
+///
+/// let source = rpkl::to_string(&config)?;
+/// assert_eq!(source, "ip = \"127.0.0.1\"\ntags = new Listing {\n  \"web\"\n}\n");
+/// #    Ok(())
+/// # }
+/// ```
+///
+/// # Errors
+/// - If `value` doesn't serialize to a struct or a map with string keys.
+/// - If `value` contains an integer that doesn't fit in a pkl `Int`.
+pub fn to_string<T>(value: &T) -> Result<String>
+where
+    T: ?Sized + serde::Serialize,
+{
+    let node = value.serialize(pkl::ser::Serializer)?;
+    pkl::ser::render_module(&node)
+}
+
+/// Serializes `value` into a [`Value`].
+///
+/// Structs become `Dynamic` objects, sequences become [`Value::Listing`] and maps become [`Value::Mapping`],
+/// or [`Value::MappingEntries`] when they have non-string keys, the same types [`to_string`] writes them as.
+/// `std::time::Duration` and [`value::DataSize`] are kept as [`Value::Duration`] and [`Value::DataSize`].
+///
+/// # Example
+///
+/// ```
+/// use serde::Serialize;
+///
+/// #[derive(Serialize)]
+/// struct Database {
+///     host: String,
+///     port: u16,
+/// }
+///
+/// # fn main() -> Result<(), rpkl::Error> {
+/// let value = rpkl::to_value(&Database {
+///     host: "localhost".into(),
+///     port: 5432,
+/// })?;
+/// assert_eq!(value["port"].as_u64(), Some(5432));
+/// #    Ok(())
+/// # }
+/// ```
+///
+/// # Errors
+/// - If `value` contains an integer that doesn't fit in a pkl `Int`.
+pub fn to_value<T>(value: &T) -> Result<Value>
+where
+    T: ?Sized + serde::Serialize,
+{
+    let node = value.serialize(pkl::ser::Serializer)?;
+    Ok(node.into())
+}
+
+/// URI used for modules evaluated from source text by [`from_str`]
+const REPL_TEXT_URI: &str = "repl:text";
+
+pub(crate) fn deserialize_module<T>(pkl_mod: pkl::PklMod) -> Result<T>
+where
+    T: Sized + for<'de> serde::Deserialize<'de>,
+{
     let pkld = pkl_mod.into_pkl_map();
 
     utils::macros::_trace!("serialized pkl data {:?}", pkld);
 
     T::deserialize(&mut Deserializer::from_pkl_map(&pkld))
-        .map_err(|e| Error::DeserializeError(format!("{e}")))
 }
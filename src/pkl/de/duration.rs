@@ -1,15 +1,57 @@
 use serde::de::{self, MapAccess};
 
 use super::KeyDeserializer;
+use crate::value::Duration;
 
+/// Yields a pkl `Duration` as `{ value, unit }`, the shape of [`Duration`]
 pub struct DurationMapAccess<'a> {
-    pub duration: &'a std::time::Duration,
+    pub duration: &'a Duration,
     pub state: u8,
 }
 
 impl<'de> MapAccess<'de> for DurationMapAccess<'_> {
     type Error = crate::Error;
 
+    fn next_key_seed<K>(&mut self, seed: K) -> Result<Option<K::Value>, Self::Error>
+    where
+        K: de::DeserializeSeed<'de>,
+    {
+        match self.state {
+            0 => {
+                self.state += 1;
+                seed.deserialize(KeyDeserializer("value")).map(Some)
+            }
+            1 => {
+                self.state += 1;
+                seed.deserialize(KeyDeserializer("unit")).map(Some)
+            }
+            _ => Ok(None),
+        }
+    }
+
+    fn next_value_seed<V>(&mut self, seed: V) -> Result<V::Value, Self::Error>
+    where
+        V: de::DeserializeSeed<'de>,
+    {
+        match self.state {
+            1 => seed.deserialize(de::value::F64Deserializer::new(self.duration.value())),
+            2 => seed.deserialize(de::value::StrDeserializer::new(
+                self.duration.unit().as_str(),
+            )),
+            _ => Err(de::Error::custom("unexpected state")),
+        }
+    }
+}
+
+/// Yields a pkl `Duration` as `{ secs, nanos }`, the shape of `std::time::Duration`
+pub struct StdDurationMapAccess {
+    pub duration: std::time::Duration,
+    pub state: u8,
+}
+
+impl<'de> MapAccess<'de> for StdDurationMapAccess {
+    type Error = crate::Error;
+
     fn next_key_seed<K>(&mut self, seed: K) -> Result<Option<K::Value>, Self::Error>
     where
         K: de::DeserializeSeed<'de>,
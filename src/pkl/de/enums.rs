@@ -1,14 +1,50 @@
-use serde::de::{self, DeserializeSeed, EnumAccess, VariantAccess, Visitor};
+use serde::de::{self, DeserializeSeed, EnumAccess, IntoDeserializer, VariantAccess, Visitor};
 
-use crate::pkl::deserializer::PklValueDeserializer;
+use crate::{
+    Value as PklValue,
+    pkl::deserializer::{PklValueDeserializer, simple_class_name},
+};
 
+/// Deserializes an enum from a non-string value.
+///
+/// The variant is selected by either:
+/// - the simple class name of a typed object when it's one of the enum's variants,
+///   e.g. `S3Backend` for an instance of `config#S3Backend`. The object itself is the content of the variant.
+/// - the key of a map or object with a single member, e.g. `{ S3Backend = ... }`.
+///   The value of that member is the content of the variant.
+///
+/// Only the externally tagged representation is supported: internally and adjacently tagged enums
+/// (`#[serde(tag = "...")]`) look for a tag member, and objects don't have one for their class name.
 pub struct EnumDeserializer<'a> {
-    de: PklValueDeserializer<'a>,
+    variant: &'a str,
+    content: &'a PklValue,
 }
 
 impl<'a> EnumDeserializer<'a> {
-    pub fn new(de: PklValueDeserializer<'a>) -> Self {
-        EnumDeserializer { de }
+    pub fn new(value: &'a PklValue, variants: &'static [&'static str]) -> crate::Result<Self> {
+        let class_name = value.class_name().and_then(simple_class_name);
+        if let Some(variant) = class_name.filter(|name| variants.contains(name)) {
+            return Ok(EnumDeserializer {
+                variant,
+                content: value,
+            });
+        }
+
+        match (value.as_map(), class_name) {
+            (Some(map), _) if map.len() == 1 => {
+                let (variant, content) = map.iter().next().expect("map has one entry");
+                Ok(EnumDeserializer { variant, content })
+            }
+            // reported as an unknown variant
+            (_, Some(variant)) => Ok(EnumDeserializer {
+                variant,
+                content: value,
+            }),
+            _ => Err(de::Error::invalid_type(
+                de::Unexpected::Other(value.type_name()),
+                &"a string, a typed object or a map with a single key",
+            )),
+        }
     }
 }
 
@@ -25,7 +61,8 @@ impl<'de> EnumAccess<'de> for EnumDeserializer<'_> {
     where
         V: DeserializeSeed<'de>,
     {
-        let val = seed.deserialize(self.de)?;
+        let variant: de::value::StrDeserializer<crate::Error> = self.variant.into_deserializer();
+        let val = seed.deserialize(variant)?;
 
         Ok((val, self))
     }
@@ -36,12 +73,16 @@ impl<'de> EnumAccess<'de> for EnumDeserializer<'_> {
 impl<'de> VariantAccess<'de> for EnumDeserializer<'_> {
     type Error = crate::Error;
 
-    // If the `Visitor` expected this variant to be a unit variant, the input
-    // should have been the plain string case handled in `deserialize_enum`.
+    // plain strings are handled in `deserialize_enum`,
+    // this is reached for typed objects selected by their class name, which must not have any members
     fn unit_variant(self) -> crate::Result<()> {
-        Err(crate::Error::Message(
-            "unit_variant :: ExpectedString".into(),
-        ))
+        match self.content.as_map() {
+            Some(members) if members.is_empty() => Ok(()),
+            _ => Err(de::Error::invalid_type(
+                de::Unexpected::Other(self.content.type_name()),
+                &"a unit variant",
+            )),
+        }
     }
 
     // deserialize the value here.
@@ -49,7 +90,7 @@ impl<'de> VariantAccess<'de> for EnumDeserializer<'_> {
     where
         T: DeserializeSeed<'de>,
     {
-        seed.deserialize(self.de)
+        seed.deserialize(PklValueDeserializer(self.content))
     }
 
     // deserialize the sequence of data here.
@@ -57,7 +98,7 @@ impl<'de> VariantAccess<'de> for EnumDeserializer<'_> {
     where
         V: Visitor<'de>,
     {
-        de::Deserializer::deserialize_seq(self.de, visitor)
+        de::Deserializer::deserialize_seq(PklValueDeserializer(self.content), visitor)
     }
 
     // deserialize the inner map here.
@@ -69,6 +110,6 @@ impl<'de> VariantAccess<'de> for EnumDeserializer<'_> {
     where
         V: Visitor<'de>,
     {
-        de::Deserializer::deserialize_map(self.de, visitor)
+        de::Deserializer::deserialize_map(PklValueDeserializer(self.content), visitor)
     }
 }
@@ -1,5 +1,5 @@
 use serde::{
-    de::{Deserializer, Visitor},
+    de::{self, Deserializer, Visitor},
     forward_to_deserialize_any,
 };
 
@@ -23,3 +23,78 @@ impl<'de> Deserializer<'de> for KeyDeserializer {
         visitor.visit_str(self.0)
     }
 }
+
+/// Deserializer for the string keys of a `Mapping`.
+///
+/// Keys are parsed when the target is a number or boolean,
+/// so `Mapping<String, ...>` with keys such as `"80"` can be read into `HashMap<u16, _>`.
+pub(crate) struct MapKeyDeserializer<'a>(pub(crate) &'a str);
+
+macro_rules! deserialize_parsed {
+    ($($method:ident => $visit:ident: $ty:ty),* $(,)?) => {
+        $(
+            fn $method<V>(self, visitor: V) -> Result<V::Value, Self::Error>
+            where
+                V: Visitor<'de>,
+            {
+                match self.0.parse::<$ty>() {
+                    Ok(v) => visitor.$visit(v),
+                    Err(_) => Err(de::Error::invalid_value(
+                        de::Unexpected::Str(self.0),
+                        &visitor,
+                    )),
+                }
+            }
+        )*
+    };
+}
+
+impl<'de> Deserializer<'de> for MapKeyDeserializer<'_> {
+    type Error = crate::Error;
+
+    forward_to_deserialize_any! {
+        char str string
+        bytes byte_buf option unit unit_struct newtype_struct seq
+        tuple tuple_struct map enum struct identifier ignored_any
+    }
+
+    deserialize_parsed! {
+        deserialize_bool => visit_bool: bool,
+        deserialize_i8 => visit_i8: i8,
+        deserialize_i16 => visit_i16: i16,
+        deserialize_i32 => visit_i32: i32,
+        deserialize_i64 => visit_i64: i64,
+        deserialize_i128 => visit_i128: i128,
+        deserialize_u8 => visit_u8: u8,
+        deserialize_u16 => visit_u16: u16,
+        deserialize_u32 => visit_u32: u32,
+        deserialize_u64 => visit_u64: u64,
+        deserialize_u128 => visit_u128: u128,
+        deserialize_f32 => visit_f32: f32,
+        deserialize_f64 => visit_f64: f64,
+    }
+
+    fn deserialize_any<V>(self, visitor: V) -> Result<V::Value, Self::Error>
+    where
+        V: Visitor<'de>,
+    {
+        visitor.visit_str(self.0)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use serde::Deserialize;
+
+    #[test]
+    fn parse_map_keys() {
+        assert_eq!(u16::deserialize(MapKeyDeserializer("80")).unwrap(), 80);
+        assert_eq!(i64::deserialize(MapKeyDeserializer("-1")).unwrap(), -1);
+        assert!(bool::deserialize(MapKeyDeserializer("true")).unwrap());
+        assert_eq!(String::deserialize(MapKeyDeserializer("80")).unwrap(), "80");
+
+        let err = u8::deserialize(MapKeyDeserializer("300")).unwrap_err();
+        assert!(err.to_string().contains("invalid value"), "{err}");
+    }
+}
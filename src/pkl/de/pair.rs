@@ -1,6 +1,6 @@
 use serde::de::{self, SeqAccess};
 
-use crate::{Value, pkl::deserializer::PklValueDeserializer};
+use crate::{Value, error::PathSegment, pkl::deserializer::PklValueDeserializer};
 
 pub struct TupleSeqAccess<'a> {
     pub pair: (&'a Value, &'a Value),
@@ -25,6 +25,8 @@ impl<'de> SeqAccess<'de> for TupleSeqAccess<'_> {
 
         self.index += 1;
 
-        seed.deserialize(PklValueDeserializer(element)).map(Some)
+        seed.deserialize(PklValueDeserializer(element))
+            .map(Some)
+            .map_err(|e| e.with_parent(PathSegment::Index(self.index - 1)))
     }
 }
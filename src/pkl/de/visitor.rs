@@ -228,10 +228,28 @@ impl<'de> Visitor<'de> for PklVisitor {
             let m = HashMap::new();
             m
         };
-        while let Some((key, value)) = map.next_entry()? {
-            map_impl.insert(key, value);
+        // keys stay strings until the first key of another type, after which entries are collected instead
+        let mut entries: Option<Vec<(Value, Value)>> = None;
+        while let Some((key, value)) = map.next_entry::<Value, Value>()? {
+            match (key, &mut entries) {
+                (key, Some(entries)) => entries.push((key, value)),
+                (Value::String(key), None) => {
+                    map_impl.insert(key, value);
+                }
+                (key, None) => {
+                    let mut collected = std::mem::take(&mut map_impl)
+                        .into_iter()
+                        .map(|(k, v)| (Value::String(k), v))
+                        .collect::<Vec<_>>();
+                    collected.push((key, value));
+                    entries = Some(collected);
+                }
+            }
+        }
+        match entries {
+            Some(entries) => Ok(Value::Entries(entries)),
+            None => Ok(Value::Map(map_impl)),
         }
-        Ok(Value::Map(map_impl))
     }
 
     fn visit_enum<A>(self, data: A) -> Result<Self::Value, A::Error>
@@ -1,4 +1,7 @@
-use crate::pkl::de::{DurationMapAccess, EnumDeserializer, RangeMapAccess, TupleSeqAccess};
+use crate::pkl::de::{
+    DurationMapAccess, EnumDeserializer, MapKeyDeserializer, RangeMapAccess, StdDurationMapAccess,
+    TupleSeqAccess,
+};
 use crate::value::datasize::DataSizeMapAccess;
 use crate::value::value::MapImpl;
 use serde::de::{self, DeserializeSeed, IntoDeserializer, MapAccess, SeqAccess, Visitor};
@@ -9,7 +12,7 @@ use tracing::{debug, trace};
 
 use crate::Value as PklValue;
 
-use crate::error::{Error, Result};
+use crate::error::{Error, PathSegment, Result};
 
 pub struct Deserializer<'de> {
     map: &'de MapImpl<String, PklValue>,
@@ -70,7 +73,7 @@ impl<'de> MapAccess<'de> for MapAccessImpl<'_, 'de> {
             self.index += 1;
             #[cfg(feature = "trace")]
             debug!("looking up key: {:?}", key);
-            seed.deserialize(key.as_str().into_deserializer()).map(Some)
+            seed.deserialize(MapKeyDeserializer(key)).map(Some)
         } else {
             Ok(None)
         }
@@ -92,12 +95,13 @@ impl<'de> MapAccess<'de> for MapAccessImpl<'_, 'de> {
         #[cfg(feature = "trace")]
         debug!("next_value_seed for key: {:?}: {:?}", key, value);
         seed.deserialize(value.into_deserializer())
+            .map_err(|e| e.with_parent(PathSegment::Field(key.clone())))
     }
 }
 
 ////////////////////////////////////////////////////////////////////////////////
 pub struct PklSeqAccess<'a> {
-    elements: std::slice::Iter<'a, PklValue>,
+    elements: std::iter::Enumerate<std::slice::Iter<'a, PklValue>>,
 }
 
 pub struct BytesSeqAccess<'a> {
@@ -107,7 +111,7 @@ pub struct BytesSeqAccess<'a> {
 impl<'a> PklSeqAccess<'a> {
     fn new(elements: &'a [PklValue]) -> Self {
         PklSeqAccess {
-            elements: elements.iter(),
+            elements: elements.iter().enumerate(),
         }
     }
 }
@@ -128,9 +132,11 @@ impl<'de> SeqAccess<'de> for PklSeqAccess<'_> {
         T: serde::de::DeserializeSeed<'de>,
     {
         match self.elements.next() {
-            Some(element) => {
+            Some((index, element)) => {
                 let deserializer = element.into_deserializer();
-                seed.deserialize(deserializer).map(Some)
+                seed.deserialize(deserializer)
+                    .map(Some)
+                    .map_err(|e| e.with_parent(PathSegment::Index(index)))
             }
             None => Ok(None),
         }
@@ -173,6 +179,16 @@ impl<'a> PklMapAccess<'a> {
     }
 }
 
+/// The simple name of a class, e.g. `S3Backend` for `config#S3Backend`.
+/// Returns `None` for `Dynamic` objects, which don't carry a meaningful class.
+pub(crate) fn simple_class_name(class_name: &str) -> Option<&str> {
+    let name = class_name
+        .rsplit_once('#')
+        .map_or(class_name, |(_, name)| name);
+    let name = name.rsplit_once('.').map_or(name, |(_, name)| name);
+    (name != "Dynamic").then_some(name)
+}
+
 impl<'de> MapAccess<'de> for PklMapAccess<'_> {
     type Error = Error;
 
@@ -188,7 +204,7 @@ impl<'de> MapAccess<'de> for PklMapAccess<'_> {
             self.index += 1;
             #[cfg(feature = "trace")]
             debug!("looking up key: {:?}", key);
-            seed.deserialize(key.as_str().into_deserializer()).map(Some)
+            seed.deserialize(MapKeyDeserializer(key)).map(Some)
         } else {
             Ok(None)
         }
@@ -210,6 +226,57 @@ impl<'de> MapAccess<'de> for PklMapAccess<'_> {
         #[cfg(feature = "trace")]
         debug!("next_value_seed for key: {:?}: {:?}", key, value);
         seed.deserialize(value.into_deserializer())
+            .map_err(|e| e.with_parent(PathSegment::Field(key.clone())))
+    }
+}
+
+/// Map access for the entries of a map with non-string keys
+struct EntriesMapAccess<'a> {
+    entries: std::slice::Iter<'a, (PklValue, PklValue)>,
+    value: Option<(&'a PklValue, &'a PklValue)>,
+}
+
+impl<'a> EntriesMapAccess<'a> {
+    fn new(entries: &'a [(PklValue, PklValue)]) -> Self {
+        EntriesMapAccess {
+            entries: entries.iter(),
+            value: None,
+        }
+    }
+}
+
+impl<'de> MapAccess<'de> for EntriesMapAccess<'_> {
+    type Error = Error;
+
+    fn next_key_seed<K>(&mut self, seed: K) -> Result<Option<K::Value>>
+    where
+        K: DeserializeSeed<'de>,
+    {
+        match self.entries.next() {
+            Some((key, value)) => {
+                self.value = Some((key, value));
+                seed.deserialize(PklValueDeserializer(key))
+                    .map(Some)
+                    .map_err(|e| e.with_parent(PathSegment::Key(key.to_pkl_string())))
+            }
+            None => Ok(None),
+        }
+    }
+
+    fn next_value_seed<V>(&mut self, seed: V) -> Result<V::Value>
+    where
+        V: DeserializeSeed<'de>,
+    {
+        let Some((key, value)) = self.value.take() else {
+            return Err(Error::Message("value requested before key".into()));
+        };
+
+        seed.deserialize(PklValueDeserializer(value))
+            .map_err(|e| e.with_parent(PathSegment::Key(key.to_pkl_string())))
+    }
+
+    fn size_hint(&self) -> Option<usize> {
+        Some(self.entries.len())
     }
 }
 
@@ -221,15 +288,63 @@ impl<'de> serde::Deserializer<'de> for PklValueDeserializer<'_> {
     type Error = crate::Error;
 
     forward_to_deserialize_any! {
-        bool i8 i16 i32 i64 u8 u16 u32 u64 f32 f64 char str string
+        bool i8 i16 i32 i64 u8 u16 u32 f32 f64 char
         unit unit_struct newtype_struct
-        tuple tuple_struct map struct identifier ignored_any
+        tuple tuple_struct identifier ignored_any
+    }
+
+    // durations and data sizes can be read as pkl literals such as `5.min`
+    fn deserialize_str<V>(self, visitor: V) -> std::result::Result<V::Value, Self::Error>
+    where
+        V: Visitor<'de>,
+    {
+        match self.0 {
+            PklValue::Duration(duration) => visitor
+                .visit_string(duration.to_string())
+                .map_err(|e: Error| e.with_actual(self.0)),
+            PklValue::DataSize(size) => visitor
+                .visit_string(size.to_string())
+                .map_err(|e: Error| e.with_actual(self.0)),
+            _ => self.deserialize_any(visitor),
+        }
+    }
+
+    fn deserialize_string<V>(self, visitor: V) -> std::result::Result<V::Value, Self::Error>
+    where
+        V: Visitor<'de>,
+    {
+        self.deserialize_str(visitor)
+    }
+
+    // data sizes can be read directly as byte counts
+    fn deserialize_u64<V>(self, visitor: V) -> std::result::Result<V::Value, Self::Error>
+    where
+        V: Visitor<'de>,
+    {
+        match self.0 {
+            PklValue::DataSize(size) => visitor
+                .visit_u64(u64::try_from(*size)?)
+                .map_err(|e: Error| e.with_actual(self.0)),
+            _ => self.deserialize_any(visitor),
+        }
+    }
+
+    fn deserialize_u128<V>(self, visitor: V) -> std::result::Result<V::Value, Self::Error>
+    where
+        V: Visitor<'de>,
+    {
+        match self.0 {
+            PklValue::DataSize(size) if size.value() >= 0.0 => visitor
+                .visit_u128(size.to_bytes())
+                .map_err(|e: Error| e.with_actual(self.0)),
+            _ => self.deserialize_any(visitor),
+        }
     }
 
     fn deserialize_enum<V>(
         self,
         _name: &'static str,
-        _variants: &'static [&'static str],
+        variants: &'static [&'static str],
         visitor: V,
     ) -> std::result::Result<V::Value, Self::Error>
     where
@@ -239,7 +354,46 @@ impl<'de> serde::Deserializer<'de> for PklValueDeserializer<'_> {
         if let Some(str_val) = self.0.as_str() {
             visitor.visit_enum(str_val.into_deserializer())
         } else {
-            visitor.visit_enum(EnumDeserializer::new(self))
+            visitor.visit_enum(EnumDeserializer::new(self.0, variants)?)
+        }
+        .map_err(|e| e.with_actual(self.0))
+    }
+
+    fn deserialize_map<V>(self, visitor: V) -> std::result::Result<V::Value, Self::Error>
+    where
+        V: Visitor<'de>,
+    {
+        match self.0 {
+            PklValue::Map(m) | PklValue::Mapping(m) | PklValue::Object { members: m, .. } => {
+                visitor
+                    .visit_map(PklMapAccess::new(m))
+                    .map_err(|e| e.with_actual(self.0))
+            }
+            PklValue::Entries(entries) | PklValue::MappingEntries(entries) => visitor
+                .visit_map(EntriesMapAccess::new(entries))
+                .map_err(|e| e.with_actual(self.0)),
+            _ => self.deserialize_any(visitor),
+        }
+    }
+
+    fn deserialize_struct<V>(
+        self,
+        _name: &'static str,
+        fields: &'static [&'static str],
+        visitor: V,
+    ) -> std::result::Result<V::Value, Self::Error>
+    where
+        V: Visitor<'de>,
+    {
+        match self.0 {
+            // `std::time::Duration` can't hold negative durations, unlike `value::Duration`
+            PklValue::Duration(duration) if fields == ["secs", "nanos"] => {
+                let duration = std::time::Duration::try_from(*duration)?;
+                visitor
+                    .visit_map(StdDurationMapAccess { duration, state: 0 })
+                    .map_err(|e| e.with_actual(self.0))
+            }
+            _ => self.deserialize_map(visitor),
         }
     }
 
@@ -252,6 +406,7 @@ impl<'de> serde::Deserializer<'de> for PklValueDeserializer<'_> {
         } else {
             visitor.visit_some(self)
         }
+        .map_err(|e| e.with_actual(self.0))
     }
 
     fn deserialize_bytes<V>(self, visitor: V) -> std::result::Result<V::Value, Self::Error>
@@ -259,7 +414,9 @@ impl<'de> serde::Deserializer<'de> for PklValueDeserializer<'_> {
         V: Visitor<'de>,
     {
         match self.0 {
-            PklValue::Bytes(bytes) => visitor.visit_bytes(bytes),
+            PklValue::Bytes(bytes) => visitor
+                .visit_bytes(bytes)
+                .map_err(|e: Error| e.with_actual(self.0)),
             _ => self.deserialize_any(visitor),
         }
     }
@@ -269,7 +426,9 @@ impl<'de> serde::Deserializer<'de> for PklValueDeserializer<'_> {
         V: Visitor<'de>,
     {
         match self.0 {
-            PklValue::Bytes(bytes) => visitor.visit_byte_buf(bytes.clone()),
+            PklValue::Bytes(bytes) => visitor
+                .visit_byte_buf(bytes.clone())
+                .map_err(|e: Error| e.with_actual(self.0)),
             _ => self.deserialize_any(visitor),
         }
     }
@@ -279,8 +438,14 @@ impl<'de> serde::Deserializer<'de> for PklValueDeserializer<'_> {
         V: Visitor<'de>,
     {
         match self.0 {
-            PklValue::Bytes(bytes) => visitor.visit_seq(BytesSeqAccess::new(bytes)),
-            PklValue::List(elements) => visitor.visit_seq(PklSeqAccess::new(elements)),
+            PklValue::Bytes(bytes) => visitor
+                .visit_seq(BytesSeqAccess::new(bytes))
+                .map_err(|e| e.with_actual(self.0)),
+            PklValue::List(elements) | PklValue::Listing(elements) | PklValue::Set(elements) => {
+                visitor
+                    .visit_seq(PklSeqAccess::new(elements))
+                    .map_err(|e| e.with_actual(self.0))
+            }
             _ => self.deserialize_any(visitor),
         }
     }
@@ -292,6 +457,15 @@ impl<'de> serde::Deserializer<'de> for PklValueDeserializer<'_> {
         #[cfg(feature = "trace")]
         trace!("[PklValueDeserializer] deserialize_any : {:?}", self.0);
 
+        self.visit_value(visitor).map_err(|e| e.with_actual(self.0))
+    }
+}
+
+impl PklValueDeserializer<'_> {
+    fn visit_value<'de, V>(self, visitor: V) -> Result<V::Value>
+    where
+        V: Visitor<'de>,
+    {
         match self.0 {
             PklValue::Int(i) => match i {
                 crate::internal::Integer::Pos(u) => visitor.visit_u64(*u),
@@ -303,13 +477,17 @@ impl<'de> serde::Deserializer<'de> for PklValueDeserializer<'_> {
             PklValue::Boolean(b) => visitor.visit_bool(*b),
             PklValue::Null => visitor.visit_unit(),
 
-            PklValue::List(elements) => visitor.visit_seq(PklSeqAccess::new(elements)),
+            PklValue::List(elements) | PklValue::Listing(elements) | PklValue::Set(elements) => {
+                visitor.visit_seq(PklSeqAccess::new(elements))
+            }
 
             PklValue::IntSeq(crate::value::IntSeq { start, end, step }) => {
                 if *step != 1 {
-                    return Err(crate::Error::DeserializeError(format!(
-                        "cannot deserialize IntSeq with step={step} into std::ops::Range<i64>"
-                    )));
+                    return Err(crate::Error::DeserializeError(
+                        crate::error::DeserializeError::new(format!(
+                            "cannot deserialize IntSeq with step={step} into std::ops::Range<i64>"
+                        )),
+                    ));
                 }
                 visitor.visit_map(RangeMapAccess {
                     start,
@@ -326,7 +504,12 @@ impl<'de> serde::Deserializer<'de> for PklValueDeserializer<'_> {
                 pair: (a, b),
             }),
             PklValue::DataSize(d) => visitor.visit_map(DataSizeMapAccess { input: d, state: 0 }),
-            PklValue::Map(m) => visitor.visit_map(PklMapAccess::new(m)),
+            PklValue::Map(m) | PklValue::Mapping(m) | PklValue::Object { members: m, .. } => {
+                visitor.visit_map(PklMapAccess::new(m))
+            }
+            PklValue::Entries(entries) | PklValue::MappingEntries(entries) => {
+                visitor.visit_map(EntriesMapAccess::new(entries))
+            }
             PklValue::Bytes(bytes) => visitor.visit_bytes(bytes),
         }
     }
@@ -338,6 +521,67 @@ impl PklValue {
     }
 }
 
+impl<'de, 'v> IntoDeserializer<'de, Error> for &'v PklValue {
+    type Deserializer = PklValueDeserializer<'v>;
+
+    fn into_deserializer(self) -> Self::Deserializer {
+        PklValueDeserializer(self)
+    }
+}
+
+/// Forwards each method to the borrowing [`PklValueDeserializer`],
+/// nothing is borrowed from the value so it can be dropped once the visitor returns.
+macro_rules! forward_to_value_deserializer {
+    ($($method:ident ( $($arg:ident : $ty:ty),* ))*) => {
+        $(
+            fn $method<V>(self, $($arg: $ty,)* visitor: V) -> Result<V::Value>
+            where
+                V: Visitor<'de>,
+            {
+                PklValueDeserializer(&self).$method($($arg,)* visitor)
+            }
+        )*
+    };
+}
+
+/// Allows a [`PklValue`] to be deserialized into any type implementing `Deserialize`, see [`crate::from_value`].
+impl<'de> serde::Deserializer<'de> for PklValue {
+    type Error = Error;
+
+    forward_to_value_deserializer! {
+        deserialize_any()
+        deserialize_bool()
+        deserialize_i8()
+        deserialize_i16()
+        deserialize_i32()
+        deserialize_i64()
+        deserialize_u8()
+        deserialize_u16()
+        deserialize_u32()
+        deserialize_u64()
+        deserialize_u128()
+        deserialize_f32()
+        deserialize_f64()
+        deserialize_char()
+        deserialize_str()
+        deserialize_string()
+        deserialize_bytes()
+        deserialize_byte_buf()
+        deserialize_option()
+        deserialize_unit()
+        deserialize_unit_struct(name: &'static str)
+        deserialize_newtype_struct(name: &'static str)
+        deserialize_seq()
+        deserialize_tuple(len: usize)
+        deserialize_tuple_struct(name: &'static str, len: usize)
+        deserialize_map()
+        deserialize_struct(name: &'static str, fields: &'static [&'static str])
+        deserialize_enum(name: &'static str, variants: &'static [&'static str])
+        deserialize_identifier()
+        deserialize_ignored_any()
+    }
+}
+
 #[cfg(test)]
 mod tests {
     use super::*;
@@ -554,6 +798,415 @@ mod tests {
         assert_eq!(deserialized.second, "2");
     }
 
+    #[test]
+    fn deserialize_expression_result() {
+        #[derive(Debug, PartialEq, Deserialize)]
+        struct Database {
+            username: String,
+            port: u16,
+        }
+
+        // result of evaluating the expression `database`
+        let ast = Value::Array(vec![
+            Value::Integer(1.into()),
+            Value::String("Dynamic".into()),
+            Value::String("pkl:base".into()),
+            Value::Array(vec![
+                Value::Array(vec![
+                    Value::Integer(16.into()),
+                    Value::String("username".into()),
+                    Value::String("admin".into()),
+                ]),
+                Value::Array(vec![
+                    Value::Integer(16.into()),
+                    Value::String("port".into()),
+                    Value::Integer(5432.into()),
+                ]),
+            ]),
+        ]);
+
+        let value = crate::decoder::decode_value(&ast).expect("failed to decode value");
+        let database = Database::deserialize(value.into_deserializer()).unwrap();
+        assert_eq!(
+            database,
+            Database {
+                username: "admin".into(),
+                port: 5432
+            }
+        );
+
+        // primitive results aren't wrapped in an array
+        let value = crate::decoder::decode_value(&Value::String("text".into())).unwrap();
+        assert_eq!(value, PklValue::String("text".into()));
+    }
+
+    #[test]
+    fn deserialize_enum_from_class_name() {
+        fn object(class_name: &str, members: &[(&str, PklValue)]) -> PklValue {
+            PklValue::Object {
+                class_name: class_name.into(),
+                module_uri: "file:///config.pkl".into(),
+                members: members
+                    .iter()
+                    .map(|(k, v)| (k.to_string(), v.clone()))
+                    .collect(),
+            }
+        }
+
+        #[derive(Debug, PartialEq, Deserialize)]
+        #[allow(clippy::enum_variant_names)] // variants are named after the pkl classes
+        enum Backend {
+            S3Backend { bucket: String },
+            GcsBackend(Gcs),
+            Local,
+        }
+
+        #[derive(Debug, PartialEq, Deserialize)]
+        struct Gcs {
+            project: String,
+        }
+
+        let s3 = object(
+            "config#S3Backend",
+            &[("bucket", PklValue::String("logs".into()))],
+        );
+        let gcs = object(
+            "config#GcsBackend",
+            &[("project", PklValue::String("prod".into()))],
+        );
+
+        assert_eq!(
+            Backend::deserialize(s3.into_deserializer()).unwrap(),
+            Backend::S3Backend {
+                bucket: "logs".into()
+            }
+        );
+        assert_eq!(
+            Backend::deserialize(gcs.into_deserializer()).unwrap(),
+            Backend::GcsBackend(Gcs {
+                project: "prod".into()
+            })
+        );
+        assert_eq!(
+            Backend::deserialize(object("config#Local", &[]).into_deserializer()).unwrap(),
+            Backend::Local
+        );
+
+        // `Dynamic` objects fall back to the externally tagged representation
+        let dynamic = object(
+            "pkl.base#Dynamic",
+            &[(
+                "S3Backend",
+                object(
+                    "pkl.base#Dynamic",
+                    &[("bucket", PklValue::String("logs".into()))],
+                ),
+            )],
+        );
+        assert_eq!(
+            Backend::deserialize(dynamic.into_deserializer()).unwrap(),
+            Backend::S3Backend {
+                bucket: "logs".into()
+            }
+        );
+
+        // typed objects whose class isn't a variant use the externally tagged representation
+        let storage = object(
+            "config#Storage",
+            &[(
+                "GcsBackend",
+                object("config#Gcs", &[("project", "prod".into())]),
+            )],
+        );
+        assert_eq!(
+            Backend::deserialize(storage.into_deserializer()).unwrap(),
+            Backend::GcsBackend(Gcs {
+                project: "prod".into()
+            })
+        );
+        let err =
+            Backend::deserialize(object("config#Other", &[]).into_deserializer()).unwrap_err();
+        assert!(err.to_string().contains("unknown variant `Other`"), "{err}");
+
+        // unit variants can't have content
+        let local = object("config#Storage", &[("Local", 5.into())]);
+        assert!(Backend::deserialize(local.into_deserializer()).is_err());
+        let local = object("config#Local", &[("path", "/tmp".into())]);
+        assert!(Backend::deserialize(local.into_deserializer()).is_err());
+
+        // the class name isn't part of the object's members
+        let value = PklValue::deserialize(s3.into_deserializer()).unwrap();
+        assert_eq!(value.as_map().unwrap().len(), 1);
+    }
+
+    #[test]
+    fn objects_only_expose_their_members() {
+        #[derive(Debug, PartialEq, Deserialize)]
+        #[serde(deny_unknown_fields)]
+        struct S3 {
+            bucket: String,
+        }
+
+        #[derive(Debug, PartialEq, Deserialize)]
+        #[serde(untagged)]
+        enum Backend {
+            S3(S3),
+            Other(serde_json::Value),
+        }
+
+        let s3 = PklValue::Object {
+            class_name: "config#S3Backend".into(),
+            module_uri: "file:///config.pkl".into(),
+            members: [("bucket".to_string(), PklValue::String("logs".into()))]
+                .into_iter()
+                .collect(),
+        };
+
+        assert_eq!(
+            crate::from_value_ref::<serde_json::Value>(&s3).unwrap(),
+            serde_json::json!({ "bucket": "logs" })
+        );
+        assert_eq!(
+            crate::from_value_ref::<Backend>(&s3).unwrap(),
+            Backend::S3(S3 {
+                bucket: "logs".into()
+            })
+        );
+
+        // a property that happens to be named like a serde tag is a regular member
+        let tagged = crate::pkl_value!({ "__class": "S3Backend", "bucket": "logs" });
+        let value = PklValue::deserialize(tagged.into_deserializer()).unwrap();
+        assert_eq!(value, tagged);
+    }
+
+    #[test]
+    fn decode_object_and_set() {
+        let ast = Value::Array(vec![
+            Value::Integer(1.into()),
+            Value::String("config#Database".into()),
+            Value::String("file:///config.pkl".into()),
+            Value::Array(vec![Value::Array(vec![
+                Value::Integer(16.into()),
+                Value::String("ports".into()),
+                Value::Array(vec![
+                    Value::Integer(6.into()),
+                    Value::Array(vec![Value::Integer(80.into()), Value::Integer(443.into())]),
+                ]),
+            ])]),
+        ]);
+
+        let value = crate::decoder::decode_value(&ast).expect("failed to decode value");
+        assert!(value.is_object());
+        assert_eq!(value.class_name(), Some("config#Database"));
+        assert_eq!(value.module_uri(), Some("file:///config.pkl"));
+
+        let ports = value.as_map().unwrap().get("ports").unwrap();
+        assert!(ports.is_set());
+        // sets are arrays too, as they were when they were decoded as lists
+        assert_eq!(ports.as_array().map(Vec::len), Some(2));
+
+        #[derive(Debug, Deserialize)]
+        struct Database {
+            ports: std::collections::BTreeSet<u16>,
+        }
+        let database = Database::deserialize(value.into_deserializer()).unwrap();
+        assert_eq!(database.ports, [80, 443].into());
+    }
+
+    #[test]
+    fn decode_mapping_with_int_keys() {
+        let ast = Value::Array(vec![
+            Value::Integer(3.into()),
+            Value::Map(vec![
+                (Value::Integer(80.into()), Value::String("http".into())),
+                (Value::Integer(443.into()), Value::String("https".into())),
+            ]),
+        ]);
+
+        let value = crate::decoder::decode_value(&ast).expect("failed to decode value");
+        let entries = value.as_entries().expect("expected entries");
+        assert_eq!(
+            entries[0].0,
+            PklValue::Int(crate::internal::Integer::Pos(80))
+        );
+
+        let ports =
+            std::collections::BTreeMap::<u16, String>::deserialize(value.into_deserializer())
+                .unwrap();
+        assert_eq!(ports[&443], "https");
+
+        let err = std::collections::HashMap::<u16, u8>::deserialize(value.into_deserializer())
+            .unwrap_err();
+        let Error::DeserializeError(err) = err else {
+            panic!("expected DeserializeError, got {err:?}");
+        };
+        assert_eq!(err.path_string(), "[80]");
+
+        // string keys are parsed when the target key type is a number
+        let value: PklValue = serde_json::from_str(r#"{"80": "http"}"#).unwrap();
+        let ports =
+            std::collections::HashMap::<u16, String>::deserialize(value.into_deserializer())
+                .unwrap();
+        assert_eq!(ports[&80], "http");
+    }
+
+    #[test]
+    fn deserialize_negative_duration() {
+        use crate::value::{Duration, DurationUnit};
+
+        #[derive(Debug, Deserialize)]
+        struct Config {
+            offset: Duration,
+        }
+
+        #[derive(Debug, Deserialize)]
+        #[allow(dead_code)]
+        struct StdConfig {
+            offset: std::time::Duration,
+        }
+
+        let value = PklValue::Map(
+            [(
+                "offset".to_string(),
+                PklValue::Duration(Duration::new(-5.0, DurationUnit::Minutes)),
+            )]
+            .into_iter()
+            .collect(),
+        );
+
+        let config = Config::deserialize(value.into_deserializer()).unwrap();
+        assert_eq!(config.offset, Duration::new(-5.0, DurationUnit::Minutes));
+
+        #[derive(Debug, Deserialize)]
+        struct StringConfig {
+            offset: String,
+        }
+        let config = StringConfig::deserialize(value.into_deserializer()).unwrap();
+        assert_eq!(config.offset, "-5.min");
+
+        let err = StdConfig::deserialize(value.into_deserializer()).unwrap_err();
+        let Error::DeserializeError(err) = err else {
+            panic!("expected DeserializeError, got {err:?}");
+        };
+        assert_eq!(err.path_string(), "offset");
+        assert!(err.message().contains("negative duration"), "{err}");
+    }
+
+    #[test]
+    fn deserialize_data_size_as_bytes() {
+        use crate::value::{DataSize, datasize::DataSizeUnit};
+
+        #[derive(Debug, Deserialize)]
+        struct Limits {
+            memory_limit: u64,
+            disk_limit: u128,
+        }
+
+        let value = PklValue::Map(
+            [
+                (
+                    "memory_limit".to_string(),
+                    PklValue::DataSize(DataSize::new(1.5, DataSizeUnit::Gibibytes)),
+                ),
+                (
+                    "disk_limit".to_string(),
+                    PklValue::DataSize(DataSize::new(2.0, DataSizeUnit::Terabytes)),
+                ),
+            ]
+            .into_iter()
+            .collect(),
+        );
+
+        let limits = Limits::deserialize(value.into_deserializer()).unwrap();
+        assert_eq!(limits.memory_limit, 1_610_612_736);
+        assert_eq!(limits.disk_limit, 2_000_000_000_000);
+    }
+
+    #[test]
+    fn deserialize_error_path() {
+        #[derive(Debug, Deserialize)]
+        #[allow(dead_code)]
+        struct Config {
+            database: Database,
+        }
+
+        #[derive(Debug, Deserialize)]
+        #[allow(dead_code)]
+        struct Database {
+            replicas: Vec<Replica>,
+        }
+
+        #[derive(Debug, Deserialize)]
+        #[allow(dead_code)]
+        struct Replica {
+            port: u16,
+        }
+
+        let value: PklValue = serde_json::from_str(
+            r#"{"database": {"replicas": [{"port": 5432}, {"port": 5433}, {"port": "5434"}]}}"#,
+        )
+        .unwrap();
+
+        let err = Config::deserialize(&mut Deserializer::from_pkl_map(value.as_map().unwrap()))
+            .unwrap_err();
+        let Error::DeserializeError(err) = err else {
+            panic!("expected DeserializeError, got {err:?}");
+        };
+
+        assert_eq!(err.path_string(), "database.replicas[2].port");
+        assert_eq!(err.expected(), Some("u16"));
+        assert_eq!(err.actual(), Some("String"));
+        assert!(
+            err.to_string()
+                .starts_with("database.replicas[2].port: invalid type")
+        );
+    }
+
+    #[test]
+    fn deserialize_error_path_mapping_key() {
+        #[derive(Debug, Deserialize)]
+        #[allow(dead_code)]
+        struct Config {
+            paths: std::collections::HashMap<String, Vec<String>>,
+        }
+
+        let value: PklValue = serde_json::from_str(r#"{"paths": {"*": ["@admins", 1]}}"#).unwrap();
+
+        let err = Config::deserialize(value.into_deserializer()).unwrap_err();
+        let Error::DeserializeError(err) = err else {
+            panic!("expected DeserializeError, got {err:?}");
+        };
+
+        assert_eq!(err.path_string(), r#"paths["*"][1]"#);
+        assert_eq!(err.actual(), Some("Int"));
+    }
+
+    #[test]
+    fn deserialize_error_missing_field() {
+        #[derive(Debug, Deserialize)]
+        #[allow(dead_code)]
+        struct Config {
+            database: Database,
+        }
+
+        #[derive(Debug, Deserialize)]
+        #[allow(dead_code)]
+        struct Database {
+            username: String,
+            password: String,
+        }
+
+        let value: PklValue =
+            serde_json::from_str(r#"{"database": {"username": "admin"}}"#).unwrap();
+
+        let err = Config::deserialize(value.into_deserializer()).unwrap_err();
+
+        assert_eq!(
+            err.to_string(),
+            "database: missing field `password`".to_string()
+        );
+    }
+
     #[test]
     fn decoded_positive_int_is_u64() {
         let ast = rmpv::Value::Array(vec![
@@ -574,4 +1227,98 @@ mod tests {
         assert!(port.is_u64(), "positive integer should be u64 variant");
         assert!(!port.is_i64(), "positive integer should not be i64 variant");
     }
+
+    #[test]
+    fn decoded_collections_keep_their_pkl_type() {
+        let member = |name: &str, type_id: u64, value: rmpv::Value| {
+            rmpv::Value::Array(vec![
+                rmpv::Value::Integer(16.into()),
+                rmpv::Value::String(name.into()),
+                rmpv::Value::Array(vec![rmpv::Value::Integer(type_id.into()), value]),
+            ])
+        };
+        let items = || rmpv::Value::Array(vec![rmpv::Value::Integer(1.into())]);
+        let entries = || {
+            rmpv::Value::Map(vec![(
+                rmpv::Value::String("a".into()),
+                rmpv::Value::Integer(1.into()),
+            )])
+        };
+        let ast = rmpv::Value::Array(vec![
+            rmpv::Value::Integer(1.into()),
+            rmpv::Value::String("test".into()),
+            rmpv::Value::String("file:///test.pkl".into()),
+            rmpv::Value::Array(vec![
+                member("list", 4, items()),
+                member("listing", 5, items()),
+                member("map", 2, entries()),
+                member("mapping", 3, entries()),
+            ]),
+        ]);
+
+        let pkl_mod = crate::decoder::decode_module(&ast).unwrap();
+        let map = pkl_mod.into_pkl_map();
+
+        assert_eq!(map["list"].type_name(), "List");
+        assert_eq!(map["list"].to_pkl_string(), "List(1)");
+        assert_eq!(map["listing"].type_name(), "Listing");
+        assert_eq!(map["listing"].to_pkl_string(), "new Listing {\n  1\n}");
+        assert_eq!(map["map"].type_name(), "Map");
+        assert_eq!(map["map"].to_pkl_string(), "Map(\"a\", 1)");
+        assert_eq!(map["mapping"].type_name(), "Mapping");
+        assert_eq!(
+            map["mapping"].to_pkl_string(),
+            "new Mapping {\n  [\"a\"] = 1\n}"
+        );
+
+        // both kinds deserialize the same way
+        assert_eq!(
+            Vec::<u8>::deserialize(map["list"].into_deserializer()).unwrap(),
+            [1]
+        );
+        assert_eq!(
+            Vec::<u8>::deserialize(map["listing"].into_deserializer()).unwrap(),
+            [1]
+        );
+    }
+
+    #[test]
+    fn deserialize_from_value() {
+        #[derive(Debug, PartialEq, Deserialize)]
+        struct Database {
+            host: String,
+            port: u16,
+        }
+
+        let value = crate::pkl_value!({
+            "databases": [{ "host": "a", "port": 1 }, { "host": "b", "port": 2 }],
+            "retries": 3,
+        });
+
+        let databases: Vec<Database> = crate::from_value_ref(&value["databases"]).unwrap();
+        assert_eq!(
+            databases[1],
+            Database {
+                host: "b".into(),
+                port: 2
+            }
+        );
+
+        let retries: u8 = crate::from_value(value["retries"].clone()).unwrap();
+        assert_eq!(retries, 3);
+
+        let err = crate::from_value::<Vec<Database>>(value).unwrap_err();
+        assert!(err.to_string().contains("expected a sequence"), "{err}");
+
+        // errors keep their variant and path
+        let databases = crate::pkl_value!([{ "host": "a", "port": "none" }]);
+        let Err(Error::DeserializeError(err)) = crate::from_value::<Vec<Database>>(databases)
+        else {
+            panic!("expected a deserialize error");
+        };
+        assert_eq!(err.path_string(), "[0].port");
+        let negative = crate::pkl_value!("-5.min".parse::<crate::value::Duration>().unwrap());
+        let err = crate::from_value_ref::<std::time::Duration>(&negative).unwrap_err();
+        assert!(matches!(err, Error::Message(_)), "{err:?}");
+    }
 }
@@ -1,6 +1,7 @@
 pub(crate) mod de;
 mod deserializer;
 pub mod pkl_mod;
+pub(crate) mod ser;
 mod serializer;
 
 pub use crate::internal::IntSeq;
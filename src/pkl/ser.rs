@@ -0,0 +1,7 @@
+mod node;
+mod render;
+mod serializer;
+
+pub(crate) use node::*;
+pub(crate) use render::*;
+pub(crate) use serializer::*;
@@ -0,0 +1,285 @@
+use std::collections::HashMap;
+
+use super::parse_data_size;
+use crate::{
+    internal::Integer,
+    value::{DataSize, Duration, IntSeq, PklValue, value::MapImpl},
+};
+
+/// Intermediate representation of a value being written as pkl source.
+///
+/// Unlike [`PklValue`], objects and mappings keep their members in the order they were serialized,
+/// so the rendered source follows the field order of the rust type.
+#[derive(Debug, Clone, PartialEq)]
+pub(crate) enum Node {
+    Null,
+    Boolean(bool),
+    Number(Integer),
+    String(String),
+    Bytes(Vec<u8>),
+    Duration(Duration),
+    DataSize(DataSize),
+    Regex(String),
+    Pair(Box<Node>, Box<Node>),
+    IntSeq(IntSeq),
+    /// Rendered as `new { name = value }`
+    Object(Vec<(String, Node)>),
+    /// Rendered as `new Listing { value }`
+    Listing(Vec<Node>),
+    /// Rendered as `new Mapping { [key] = value }`
+    Mapping(Vec<(Node, Node)>),
+    /// Rendered as `List(value, ...)`
+    List(Vec<Node>),
+    /// Rendered as `Map(key, value, ...)`
+    Map(Vec<(Node, Node)>),
+    /// Rendered as `Set(value, ...)`
+    Set(Vec<Node>),
+}
+
+impl Node {
+    /// Converts the node to the pkl type it's assigned to, e.g. `1` to `1.0` for a `Float` property,
+    /// `"5.s"` to a duration or a `Listing` to a `List`.
+    ///
+    /// `type_name` is the simple name of the declared type, nodes that can't be converted are kept as-is.
+    pub(crate) fn into_type(self, type_name: &str) -> Node {
+        match (type_name, self) {
+            ("Float", Node::Number(Integer::Pos(n))) => Node::Number(Integer::Float(n as f64)),
+            ("Float", Node::Number(Integer::Neg(n))) => Node::Number(Integer::Float(n as f64)),
+            ("Duration", Node::String(s)) => match s.parse::<Duration>() {
+                Ok(d) => Node::Duration(d),
+                Err(_) => Node::String(s),
+            },
+            ("DataSize", Node::String(s)) => match parse_data_size(&s) {
+                Some(ds) => Node::DataSize(ds),
+                None => Node::String(s),
+            },
+            ("String", Node::Duration(d)) => Node::String(d.to_string()),
+            ("String", Node::DataSize(ds)) => Node::String(ds.to_string()),
+            ("List", Node::Listing(items) | Node::Set(items)) => Node::List(items),
+            ("Listing", Node::List(items) | Node::Set(items)) => Node::Listing(items),
+            ("Set", Node::List(items) | Node::Listing(items)) => Node::Set(items),
+            ("Map", Node::Mapping(entries)) => Node::Map(entries),
+            ("Mapping", Node::Map(entries)) => Node::Mapping(entries),
+            (_, node) => node,
+        }
+    }
+
+    /// Whether [`Node::into_type`] may convert the node, so its declared type has to be resolved
+    pub(crate) fn needs_type(&self) -> bool {
+        match self {
+            Node::Number(Integer::Pos(_) | Integer::Neg(_)) => true,
+            Node::String(s) => s.parse::<Duration>().is_ok() || parse_data_size(s).is_some(),
+            // parsed overrides such as `5.s` may be meant as strings
+            Node::Duration(_) | Node::DataSize(_) => true,
+            Node::List(_) | Node::Listing(_) | Node::Set(_) | Node::Map(_) | Node::Mapping(_) => {
+                true
+            }
+            _ => false,
+        }
+    }
+
+    /// Collects `path` and the paths of the node's members whose declared type decides how they're written,
+    /// see [`Node::needs_type`]. Maps with string keys may be written as objects, so their members are included.
+    pub(crate) fn typed_paths(&self, path: &mut Vec<String>, out: &mut Vec<Vec<String>>) {
+        if self.needs_type() {
+            out.push(path.clone());
+        }
+        let members: Vec<(&str, &Node)> = match self {
+            Node::Object(members) => members.iter().map(|(k, v)| (k.as_str(), v)).collect(),
+            Node::Map(entries) | Node::Mapping(entries) => entries
+                .iter()
+                .filter_map(|(k, v)| match k {
+                    Node::String(k) => Some((k.as_str(), v)),
+                    _ => None,
+                })
+                .collect(),
+            _ => return,
+        };
+        for (name, member) in members {
+            path.push(name.to_string());
+            member.typed_paths(path, out);
+            path.pop();
+        }
+    }
+
+    /// Converts the node and its members with [`Node::into_type`], looking up the declared type of each path in `types`.
+    ///
+    /// A map with string keys assigned to a class-typed property is written as an object, so its members are
+    /// converted to their declared types as well. Elements of listings and mappings are kept as-is.
+    pub(crate) fn into_declared(
+        self,
+        path: &mut Vec<String>,
+        types: &HashMap<Vec<String>, String>,
+    ) -> Node {
+        let type_name = types.get(path.as_slice()).map_or("", String::as_str);
+        let node = match self {
+            Node::Object(members) => Node::Object(declared_members(members, path, types)),
+            Node::Map(entries) | Node::Mapping(entries)
+                if !matches!(type_name, "" | "Any" | "Map" | "Mapping")
+                    && entries.iter().all(|(k, _)| matches!(k, Node::String(_))) =>
+            {
+                let members = entries
+                    .into_iter()
+                    .filter_map(|(k, v)| match k {
+                        Node::String(k) => Some((k, v)),
+                        _ => None,
+                    })
+                    .collect();
+                Node::Object(declared_members(members, path, types))
+            }
+            node => node,
+        };
+        node.into_type(type_name)
+    }
+
+    /// Whether the node or any value nested in it is a `Bytes` literal
+    pub(crate) fn contains_bytes(&self) -> bool {
+        match self {
+            Node::Bytes(_) => true,
+            Node::Pair(first, second) => first.contains_bytes() || second.contains_bytes(),
+            Node::Object(members) => members.iter().any(|(_, v)| v.contains_bytes()),
+            Node::Listing(items) | Node::List(items) | Node::Set(items) => {
+                items.iter().any(Node::contains_bytes)
+            }
+            Node::Mapping(entries) | Node::Map(entries) => entries
+                .iter()
+                .any(|(k, v)| k.contains_bytes() || v.contains_bytes()),
+            _ => false,
+        }
+    }
+}
+
+fn declared_members(
+    members: Vec<(String, Node)>,
+    path: &mut Vec<String>,
+    types: &HashMap<Vec<String>, String>,
+) -> Vec<(String, Node)> {
+    members
+        .into_iter()
+        .map(|(name, member)| {
+            path.push(name);
+            let member = member.into_declared(path, types);
+            let name = path.pop().expect("the member name was pushed");
+            (name, member)
+        })
+        .collect()
+}
+
+fn members(map: &MapImpl<String, PklValue>) -> Vec<(String, Node)> {
+    #[allow(unused_mut)]
+    let mut members = map
+        .iter()
+        .map(|(k, v)| (k.clone(), Node::from(v)))
+        .collect::<Vec<_>>();
+    // keep the output stable when the map doesn't preserve insertion order
+    #[cfg(not(feature = "indexmap"))]
+    members.sort_by(|(a, _), (b, _)| a.cmp(b));
+    members
+}
+
+fn string_entries(map: &MapImpl<String, PklValue>) -> Vec<(Node, Node)> {
+    members(map)
+        .into_iter()
+        .map(|(k, v)| (Node::String(k), v))
+        .collect()
+}
+
+fn entries_of(entries: &[(PklValue, PklValue)]) -> Vec<(Node, Node)> {
+    entries
+        .iter()
+        .map(|(k, v)| (Node::from(k), Node::from(v)))
+        .collect()
+}
+
+impl From<&PklValue> for Node {
+    fn from(value: &PklValue) -> Self {
+        match value {
+            PklValue::Map(map) => Node::Map(string_entries(map)),
+            PklValue::Mapping(map) => Node::Mapping(string_entries(map)),
+            PklValue::Entries(entries) => Node::Map(entries_of(entries)),
+            PklValue::MappingEntries(entries) => Node::Mapping(entries_of(entries)),
+            PklValue::Object { members: m, .. } => Node::Object(members(m)),
+            PklValue::List(items) => Node::List(items.iter().map(Node::from).collect()),
+            PklValue::Listing(items) => Node::Listing(items.iter().map(Node::from).collect()),
+            PklValue::Set(items) => Node::Set(items.iter().map(Node::from).collect()),
+            PklValue::Regex(r) => Node::Regex(r.clone()),
+            PklValue::String(s) => Node::String(s.clone()),
+            PklValue::Int(i) => Node::Number(*i),
+            PklValue::Boolean(b) => Node::Boolean(*b),
+            PklValue::Duration(d) => Node::Duration(*d),
+            PklValue::Pair(a, b) => Node::Pair(
+                Box::new(Node::from(a.as_ref())),
+                Box::new(Node::from(b.as_ref())),
+            ),
+            PklValue::IntSeq(seq) => Node::IntSeq(seq.clone()),
+            PklValue::DataSize(ds) => Node::DataSize(*ds),
+            PklValue::Bytes(b) => Node::Bytes(b.clone()),
+            PklValue::Null => Node::Null,
+        }
+    }
+}
+
+/// Objects are built as `Dynamic` objects since the class of a serialized rust type is unknown.
+/// Maps and mappings with only string keys become [`PklValue::Map`] and [`PklValue::Mapping`],
+/// any other keys [`PklValue::Entries`] and [`PklValue::MappingEntries`].
+impl From<Node> for PklValue {
+    fn from(node: Node) -> Self {
+        match node {
+            Node::Null => PklValue::Null,
+            Node::Boolean(b) => PklValue::Boolean(b),
+            Node::Number(i) => PklValue::Int(i),
+            Node::String(s) => PklValue::String(s),
+            Node::Bytes(b) => PklValue::Bytes(b),
+            Node::Duration(d) => PklValue::Duration(d),
+            Node::DataSize(ds) => PklValue::DataSize(ds),
+            Node::Regex(r) => PklValue::Regex(r),
+            Node::Pair(a, b) => {
+                PklValue::Pair(Box::new(PklValue::from(*a)), Box::new(PklValue::from(*b)))
+            }
+            Node::IntSeq(seq) => PklValue::IntSeq(seq),
+            Node::Object(members) => PklValue::Object {
+                class_name: "pkl.base#Dynamic".into(),
+                module_uri: "pkl:base".into(),
+                members: members
+                    .into_iter()
+                    .map(|(k, v)| (k, PklValue::from(v)))
+                    .collect(),
+            },
+            Node::List(items) => PklValue::List(values(items)),
+            Node::Listing(items) => PklValue::Listing(values(items)),
+            Node::Set(items) => PklValue::Set(values(items)),
+            Node::Map(entries) => match string_keys(entries) {
+                Ok(members) => PklValue::Map(members),
+                Err(entries) => PklValue::Entries(entries),
+            },
+            Node::Mapping(entries) => match string_keys(entries) {
+                Ok(members) => PklValue::Mapping(members),
+                Err(entries) => PklValue::MappingEntries(entries),
+            },
+        }
+    }
+}
+
+fn values(items: Vec<Node>) -> Vec<PklValue> {
+    items.into_iter().map(PklValue::from).collect()
+}
+
+/// Collects the entries into a map if every key is a string
+fn string_keys(
+    entries: Vec<(Node, Node)>,
+) -> Result<MapImpl<String, PklValue>, Vec<(PklValue, PklValue)>> {
+    if entries.iter().all(|(k, _)| matches!(k, Node::String(_))) {
+        Ok(entries
+            .into_iter()
+            .filter_map(|(k, v)| match k {
+                Node::String(k) => Some((k, PklValue::from(v))),
+                _ => None,
+            })
+            .collect())
+    } else {
+        Err(entries
+            .into_iter()
+            .map(|(k, v)| (PklValue::from(k), PklValue::from(v)))
+            .collect())
+    }
+}
@@ -0,0 +1,624 @@
+use std::fmt::Write as _;
+
+use crate::{error::Error, internal::Integer};
+
+use super::Node;
+
+const INDENT: &str = "  ";
+
+/// Keywords and reserved words that can't be used as a bare identifier
+/// <https://pkl-lang.org/main/current/language-reference/index.html#keywords>
+const KEYWORDS: &[&str] = &[
+    "abstract",
+    "amends",
+    "as",
+    "case",
+    "class",
+    "const",
+    "delete",
+    "else",
+    "extends",
+    "external",
+    "false",
+    "fixed",
+    "for",
+    "function",
+    "hidden",
+    "if",
+    "import",
+    "in",
+    "is",
+    "let",
+    "local",
+    "module",
+    "new",
+    "nothing",
+    "null",
+    "open",
+    "out",
+    "outer",
+    "override",
+    "protected",
+    "read",
+    "record",
+    "super",
+    "switch",
+    "this",
+    "throw",
+    "trace",
+    "true",
+    "typealias",
+    "unknown",
+    "vararg",
+    "when",
+];
+
+/// Renders the members of a module, one `name = value` per line.
+///
+/// The node must be an object, or a map or mapping with string keys.
+pub(crate) fn render_module(node: &Node) -> Result<String, Error> {
+    let members = match node {
+        Node::Object(members) => members.iter().map(|(k, v)| (k.as_str(), v)).collect(),
+        Node::Mapping(entries) | Node::Map(entries) => entries
+            .iter()
+            .map(|(k, v)| match k {
+                Node::String(k) => Ok((k.as_str(), v)),
+                _ => Err(Error::Message(
+                    "module members must have string keys".to_string(),
+                )),
+            })
+            .collect::<Result<Vec<_>, _>>()?,
+        _ => {
+            return Err(Error::Message(
+                "only structs and maps can be rendered as a pkl module".to_string(),
+            ));
+        }
+    };
+
+    let mut out = String::new();
+    for (name, value) in members {
+        write_identifier(&mut out, name);
+        out.push_str(" = ");
+        write_node(&mut out, value, 0);
+        out.push('\n');
+    }
+    Ok(out)
+}
+
+/// A member of a generated amends module, either a new value or a block amending the object below it
+enum Amend<'a> {
+    Value(Node),
+    Block(Vec<(&'a str, Amend<'a>)>),
+}
+
+/// Renders a module amending `base_uri` that sets each path to its value.
+///
+/// Paths are the segments of a dotted path, `["database", "port"]` is rendered as `database { port = 5433 }`
+/// so the rest of `database` keeps the values from the base module. A later path replaces an earlier one.
+/// Segments are member names, indexed paths such as `servers[0]` have to be rejected by the caller.
+pub(crate) fn render_amends(base_uri: &str, overrides: Vec<(Vec<&str>, Node)>) -> String {
+    let mut root = vec![];
+    for (path, value) in overrides {
+        debug_assert!(
+            !path.iter().any(|segment| segment.contains(['[', ']'])),
+            "indexed override path {path:?}"
+        );
+        let Some((last, parents)) = path.split_last() else {
+            continue;
+        };
+
+        let mut members = &mut root;
+        for segment in parents {
+            let index = match members.iter().position(|(name, _)| name == segment) {
+                Some(index) => index,
+                None => {
+                    members.push((*segment, Amend::Block(vec![])));
+                    members.len() - 1
+                }
+            };
+            let member = &mut members[index].1;
+            if let Amend::Value(_) = member {
+                *member = Amend::Block(vec![]);
+            }
+            let Amend::Block(block) = member else {
+                unreachable!("values were replaced by a block")
+            };
+            members = block;
+        }
+
+        match members.iter_mut().find(|(name, _)| name == last) {
+            Some((_, member)) => *member = Amend::Value(value),
+            None => members.push((*last, Amend::Value(value))),
+        }
+    }
+
+    let mut out = String::from("amends ");
+    write_string(&mut out, base_uri);
+    out.push_str("\n\n");
+    write_amends(&mut out, &root, 0);
+    out
+}
+
+/// Resolves the declared type of a property through `pkl:reflect`, e.g. `Float` for `ratio: Float?`.
+/// Type aliases are followed, and `""` is returned for unknown, union and function types
+/// and for members of `Dynamic` objects or mappings.
+const TYPE_QUERY_FUNCTIONS: &str = r#"local function typeName(t: reflect.Type): String =
+  if (t is reflect.NullableType) typeName(t.member)
+  else if (t is reflect.DeclaredType)
+    if (t.referent is reflect.TypeAlias) typeName(t.referent.referent)
+    else t.referent.name
+  else ""
+
+local function propertyType(clazz: reflect.Class?, name: String): String =
+  if (clazz == null) ""
+  else
+    let (property = clazz.properties.getOrNull(name))
+      let (found = if (property == null) "" else typeName(property.type))
+        if (found.isEmpty) propertyType(clazz.superclass, name) else found
+
+local function memberType(owner: Any, name: String): String =
+  if (owner is Typed || owner is Module) propertyType(reflect.Class(owner.getClass()), name)
+  else ""
+
+local function member(owner: Any, name: String): Any =
+  if ((owner is Typed || owner is Module || owner is Dynamic) && owner.hasProperty(name))
+    owner.getProperty(name)
+  else null
+"#;
+
+/// Renders a module whose `types` property lists the declared type of each path in the module at `base_uri`,
+/// see [`TYPE_QUERY_FUNCTIONS`]
+pub(crate) fn render_type_query<S: AsRef<str>>(base_uri: &str, paths: &[Vec<S>]) -> String {
+    let mut out = String::from("import \"pkl:reflect\"\n\nlocal base = import(");
+    write_string(&mut out, base_uri);
+    out.push_str(")\n\n");
+    out.push_str(TYPE_QUERY_FUNCTIONS);
+    out.push_str("\ntypes = List(");
+    for (i, path) in paths.iter().enumerate() {
+        if i > 0 {
+            out.push_str(", ");
+        }
+        let Some((last, parents)) = path.split_last() else {
+            out.push_str("\"\"");
+            continue;
+        };
+        // `member(member(base, "a"), "b")` for the owner of `a.b.c`
+        let mut owner = String::from("base");
+        for segment in parents {
+            let mut access = String::from("member(");
+            access.push_str(&owner);
+            access.push_str(", ");
+            write_string(&mut access, segment.as_ref());
+            access.push(')');
+            owner = access;
+        }
+        let _ = write!(out, "memberType({owner}, ");
+        write_string(&mut out, last.as_ref());
+        out.push(')');
+    }
+    out.push_str(")\n");
+    out
+}
+
+fn write_amends(out: &mut String, members: &[(&str, Amend)], depth: usize) {
+    for (name, member) in members {
+        out.push_str(&INDENT.repeat(depth));
+        write_identifier(out, name);
+        match member {
+            Amend::Value(value) => {
+                out.push_str(" = ");
+                write_node(out, value, depth);
+            }
+            Amend::Block(block) => {
+                out.push_str(" {\n");
+                write_amends(out, block, depth + 1);
+                out.push_str(&INDENT.repeat(depth));
+                out.push('}');
+            }
+        }
+        out.push('\n');
+    }
+}
+
+/// Renders a node as a pkl expression
+pub(crate) fn render_expr(node: &Node) -> String {
+    let mut out = String::new();
+    write_node(&mut out, node, 0);
+    out
+}
+
+fn write_node(out: &mut String, node: &Node, depth: usize) {
+    match node {
+        Node::Null => out.push_str("null"),
+        Node::Boolean(b) => out.push_str(if *b { "true" } else { "false" }),
+        Node::Number(n) => write_number(out, n),
+        Node::String(s) => write_string(out, s),
+        Node::Regex(r) => {
+            out.push_str("Regex(");
+            write_string(out, r);
+            out.push(')');
+        }
+        Node::Bytes(bytes) => {
+            out.push_str("Bytes(");
+            for (i, b) in bytes.iter().enumerate() {
+                if i > 0 {
+                    out.push_str(", ");
+                }
+                let _ = write!(out, "{b}");
+            }
+            out.push(')');
+        }
+        Node::Duration(d) => write_quantity(out, d.value(), d.unit()),
+        Node::DataSize(ds) => write_quantity(out, ds.value(), ds.unit()),
+        Node::Set(items) => write_call(out, "Set", items.iter(), depth),
+        Node::List(items) => write_call(out, "List", items.iter(), depth),
+        Node::Map(entries) => {
+            write_call(out, "Map", entries.iter().flat_map(|(k, v)| [k, v]), depth)
+        }
+        Node::Pair(a, b) => {
+            out.push_str("Pair(");
+            write_node(out, a, depth);
+            out.push_str(", ");
+            write_node(out, b, depth);
+            out.push(')');
+        }
+        Node::IntSeq(seq) => {
+            let _ = write!(out, "IntSeq({}, {})", seq.start, seq.end);
+            if seq.step != 1 {
+                let _ = write!(out, ".step({})", seq.step);
+            }
+        }
+        Node::Object(members) => write_body(out, "new", members, depth, |out, (name, value)| {
+            write_identifier(out, name);
+            out.push_str(" = ");
+            write_node(out, value, depth + 1);
+        }),
+        Node::Listing(items) => write_body(out, "new Listing", items, depth, |out, item| {
+            write_node(out, item, depth + 1);
+        }),
+        Node::Mapping(entries) => {
+            write_body(out, "new Mapping", entries, depth, |out, (key, value)| {
+                out.push('[');
+                write_node(out, key, depth + 1);
+                out.push_str("] = ");
+                write_node(out, value, depth + 1);
+            })
+        }
+    }
+}
+
+/// Writes `<name>(arg, ...)`, e.g. `Set(1, 2)` or `Map("a", 1)`
+fn write_call<'a>(
+    out: &mut String,
+    name: &str,
+    args: impl Iterator<Item = &'a Node>,
+    depth: usize,
+) {
+    out.push_str(name);
+    out.push('(');
+    for (i, arg) in args.enumerate() {
+        if i > 0 {
+            out.push_str(", ");
+        }
+        write_node(out, arg, depth);
+    }
+    out.push(')');
+}
+
+/// Writes `<prefix> { ... }` with one member per line, or `<prefix> {}` if there are no members
+fn write_body<T>(
+    out: &mut String,
+    prefix: &str,
+    members: &[T],
+    depth: usize,
+    mut write_member: impl FnMut(&mut String, &T),
+) {
+    out.push_str(prefix);
+    if members.is_empty() {
+        out.push_str(" {}");
+        return;
+    }
+
+    out.push_str(" {\n");
+    for member in members {
+        out.push_str(&INDENT.repeat(depth + 1));
+        write_member(out, member);
+        out.push('\n');
+    }
+    out.push_str(&INDENT.repeat(depth));
+    out.push('}');
+}
+
+fn write_number(out: &mut String, n: &Integer) {
+    match n {
+        Integer::Pos(n) => {
+            let _ = write!(out, "{n}");
+        }
+        Integer::Neg(n) => {
+            let _ = write!(out, "{n}");
+        }
+        Integer::Float(f) if f.is_nan() => out.push_str("NaN"),
+        Integer::Float(f) if f.is_infinite() => {
+            out.push_str(if *f > 0.0 { "Infinity" } else { "-Infinity" });
+        }
+        // debug formatting always includes a decimal point or an exponent, so the value stays a Float
+        Integer::Float(f) => {
+            let _ = write!(out, "{f:?}");
+        }
+    }
+}
+
+/// Writes a value with a unit such as `5.min`, `1.5.gib` or `-90.s`
+fn write_quantity(out: &mut String, value: f64, unit: impl std::fmt::Display) {
+    if value.fract() == 0.0 && value.abs() < 2f64.powi(53) {
+        let _ = write!(out, "{}.{unit}", value as i64);
+    } else {
+        write_number(out, &Integer::Float(value));
+        let _ = write!(out, ".{unit}");
+    }
+}
+
+fn write_string(out: &mut String, s: &str) {
+    out.push('"');
+    for c in s.chars() {
+        match c {
+            '"' => out.push_str("\\\""),
+            '\\' => out.push_str("\\\\"),
+            '\n' => out.push_str("\\n"),
+            '\r' => out.push_str("\\r"),
+            '\t' => out.push_str("\\t"),
+            c if c.is_control() => {
+                let _ = write!(out, "\\u{{{:x}}}", c as u32);
+            }
+            c => out.push(c),
+        }
+    }
+    out.push('"');
+}
+
+/// Writes the name as-is if it's a valid identifier, otherwise quoted with backticks
+fn write_identifier(out: &mut String, name: &str) {
+    if is_identifier(name) {
+        out.push_str(name);
+    } else {
+        out.push('`');
+        out.push_str(name);
+        out.push('`');
+    }
+}
+
+fn is_identifier(name: &str) -> bool {
+    let mut chars = name.chars();
+    chars
+        .next()
+        .is_some_and(|c| c.is_alphabetic() || c == '_' || c == '$')
+        && chars.all(|c| c.is_alphanumeric() || c == '_' || c == '$')
+        && !KEYWORDS.contains(&name)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::value::{DataSize, Duration, DurationUnit, datasize::DataSizeUnit};
+    use std::time::Duration as StdDuration;
+
+    #[test]
+    fn render_amends_module() {
+        let source = render_amends(
+            "file:///app/base.pkl",
+            vec![
+                (vec!["database", "port"], Node::Number(Integer::Pos(5433))),
+                (vec!["database", "host"], Node::String("db".into())),
+                (
+                    vec!["timeout"],
+                    Node::Duration(StdDuration::from_secs(30).into()),
+                ),
+                (vec!["my-flag"], Node::Boolean(false)),
+                (vec!["my-flag"], Node::Boolean(true)),
+            ],
+        );
+        assert_eq!(
+            source,
+            "amends \"file:///app/base.pkl\"\n\ndatabase {\n  port = 5433\n  host = \"db\"\n}\ntimeout = 30.s\n`my-flag` = true\n"
+        );
+    }
+
+    #[test]
+    fn render_scalars() {
+        let cases = [
+            (Node::Null, "null"),
+            (Node::Number(Integer::Neg(-3)), "-3"),
+            (Node::Number(Integer::Float(1.0)), "1.0"),
+            (Node::Number(Integer::Float(f64::NEG_INFINITY)), "-Infinity"),
+            (
+                Node::String("say \"hi\"\n\\(x)\u{7}".into()),
+                r#""say \"hi\"\n\\(x)\u{7}""#,
+            ),
+            (Node::Duration(StdDuration::from_secs(300).into()), "5.min"),
+            (
+                Node::Duration(StdDuration::from_millis(1500).into()),
+                "1500.ms",
+            ),
+            (Node::Duration(StdDuration::ZERO.into()), "0.s"),
+            (
+                Node::Duration(Duration::new(-90.0, DurationUnit::Minutes)),
+                "-90.min",
+            ),
+            (
+                Node::Duration(Duration::new(2.5, DurationUnit::Milliseconds)),
+                "2.5.ms",
+            ),
+            (
+                Node::DataSize(DataSize::new(512.0, DataSizeUnit::Megabytes)),
+                "512.mb",
+            ),
+            (
+                Node::DataSize(DataSize::new(1.5, DataSizeUnit::Gibibytes)),
+                "1.5.gib",
+            ),
+            (Node::Bytes(vec![1, 2, 255]), "Bytes(1, 2, 255)"),
+            (
+                Node::List(vec![Node::Number(Integer::Pos(1)), Node::Null]),
+                "List(1, null)",
+            ),
+            (
+                Node::Map(vec![(Node::String("a".into()), Node::Boolean(true))]),
+                "Map(\"a\", true)",
+            ),
+        ];
+
+        for (node, expected) in cases {
+            assert_eq!(render_expr(&node), expected);
+        }
+    }
+
+    #[test]
+    fn render_nested_module() {
+        let node = Node::Object(vec![
+            ("name".into(), Node::String("app".into())),
+            ("class".into(), Node::Boolean(true)),
+            ("x-forwarded-for".into(), Node::Null),
+            (
+                "database".into(),
+                Node::Object(vec![
+                    (
+                        "replicas".into(),
+                        Node::Listing(vec![Node::Number(Integer::Pos(1))]),
+                    ),
+                    ("tags".into(), Node::Listing(vec![])),
+                ]),
+            ),
+            (
+                "paths".into(),
+                Node::Mapping(vec![(
+                    Node::String("*".into()),
+                    Node::Object(vec![("port".into(), Node::Number(Integer::Pos(80)))]),
+                )]),
+            ),
+        ]);
+
+        let expected = r#"name = "app"
+`class` = true
+`x-forwarded-for` = null
+database = new {
+  replicas = new Listing {
+    1
+  }
+  tags = new Listing {}
+}
+paths = new Mapping {
+  ["*"] = new {
+    port = 80
+  }
+}
+"#;
+        assert_eq!(render_module(&node).unwrap(), expected);
+    }
+
+    #[test]
+    fn render_module_rejects_non_objects() {
+        assert!(render_module(&Node::Listing(vec![])).is_err());
+        assert!(
+            render_module(&Node::Mapping(vec![(
+                Node::Number(Integer::Pos(1)),
+                Node::Null
+            )]))
+            .is_err()
+        );
+    }
+
+    #[test]
+    fn render_type_query_module() {
+        let source = render_type_query(
+            "file:///app/base.pkl",
+            &[vec!["database", "port"], vec!["ratio"], vec![]],
+        );
+        assert!(source.starts_with(
+            "import \"pkl:reflect\"\n\nlocal base = import(\"file:///app/base.pkl\")\n"
+        ));
+        assert!(source.ends_with(
+            "\ntypes = List(memberType(member(base, \"database\"), \"port\"), memberType(base, \"ratio\"), \"\")\n"
+        ));
+    }
+
+    #[test]
+    fn nodes_into_declared_types() {
+        let cases = [
+            (Node::Number(Integer::Pos(1)), "Float", "1.0"),
+            (Node::Number(Integer::Neg(-2)), "Float", "-2.0"),
+            (Node::Number(Integer::Pos(1)), "Int", "1"),
+            (Node::String("5.s".into()), "Duration", "5.s"),
+            (Node::String("5.s".into()), "String", "\"5.s\""),
+            (Node::String("soon".into()), "Duration", "\"soon\""),
+            (Node::String("512.mb".into()), "DataSize", "512.mb"),
+            (
+                Node::Duration(Duration::new(5.0, DurationUnit::Seconds)),
+                "String",
+                "\"5.s\"",
+            ),
+            (
+                Node::Listing(vec![Node::String("a".into())]),
+                "List",
+                "List(\"a\")",
+            ),
+            (
+                Node::List(vec![Node::Number(Integer::Pos(1))]),
+                "Set",
+                "Set(1)",
+            ),
+            (
+                Node::Mapping(vec![(Node::String("a".into()), Node::Boolean(true))]),
+                "Map",
+                "Map(\"a\", true)",
+            ),
+        ];
+
+        for (node, type_name, expected) in cases {
+            assert_eq!(render_expr(&node.into_type(type_name)), expected);
+        }
+
+        // members of objects and of maps written as objects use their own declared types
+        let node = Node::Map(vec![
+            (Node::String("timeout".into()), Node::String("5.s".into())),
+            (Node::String("host".into()), Node::String("db".into())),
+        ]);
+        let mut paths = vec![];
+        node.typed_paths(&mut vec!["database".into()], &mut paths);
+        assert_eq!(
+            paths,
+            [
+                vec!["database".to_string()],
+                vec!["database".into(), "timeout".into()]
+            ]
+        );
+        let types = [
+            (vec!["database".to_string()], "Database".to_string()),
+            (vec!["database".into(), "timeout".into()], "Duration".into()),
+        ]
+        .into_iter()
+        .collect();
+        assert_eq!(
+            render_expr(&node.into_declared(&mut vec!["database".into()], &types)),
+            "new {\n  timeout = 5.s\n  host = \"db\"\n}"
+        );
+
+        assert!(!Node::Boolean(true).needs_type());
+        assert!(!Node::String("db".into()).needs_type());
+        assert!(!Node::Number(Integer::Float(0.5)).needs_type());
+        assert!(Node::String("5.s".into()).needs_type());
+        assert!(Node::String("512.mb".into()).needs_type());
+    }
+
+    #[test]
+    fn nodes_containing_bytes() {
+        assert!(Node::Bytes(vec![]).contains_bytes());
+        assert!(
+            Node::Object(vec![(
+                "data".into(),
+                Node::List(vec![Node::Bytes(vec![1])])
+            )])
+            .contains_bytes()
+        );
+        assert!(!Node::List(vec![Node::String("bytes".into())]).contains_bytes());
+    }
+}
@@ -0,0 +1,605 @@
+use serde::ser::{self, Serialize};
+
+use crate::{
+    error::{Error, Result},
+    internal::Integer,
+    value::{
+        DataSize, Duration, DurationUnit, IntSeq,
+        datasize::DataSizeUnit,
+        ser::{
+            DATA_SIZE_TOKEN, DURATION_TOKEN, INT_SEQ_TOKEN, LIST_TOKEN, MAP_TOKEN, OBJECT_TOKEN,
+            PAIR_TOKEN, REGEX_TOKEN, SET_TOKEN,
+        },
+    },
+};
+
+use super::Node;
+
+/// Serializes rust values into a [`Node`], which can then be rendered as pkl source.
+pub(crate) struct Serializer;
+
+fn int(v: i64) -> Integer {
+    if v < 0 {
+        Integer::Neg(v)
+    } else {
+        Integer::Pos(v as u64)
+    }
+}
+
+impl ser::Serializer for Serializer {
+    type Ok = Node;
+    type Error = Error;
+
+    type SerializeSeq = SeqSerializer;
+    type SerializeTuple = SeqSerializer;
+    type SerializeTupleStruct = SeqSerializer;
+    type SerializeTupleVariant = TupleVariantSerializer;
+    type SerializeMap = MapSerializer;
+    type SerializeStruct = StructSerializer;
+    type SerializeStructVariant = StructVariantSerializer;
+
+    fn serialize_bool(self, v: bool) -> Result<Node> {
+        Ok(Node::Boolean(v))
+    }
+
+    fn serialize_i8(self, v: i8) -> Result<Node> {
+        self.serialize_i64(v.into())
+    }
+
+    fn serialize_i16(self, v: i16) -> Result<Node> {
+        self.serialize_i64(v.into())
+    }
+
+    fn serialize_i32(self, v: i32) -> Result<Node> {
+        self.serialize_i64(v.into())
+    }
+
+    fn serialize_i64(self, v: i64) -> Result<Node> {
+        Ok(Node::Number(int(v)))
+    }
+
+    fn serialize_u8(self, v: u8) -> Result<Node> {
+        self.serialize_u64(v.into())
+    }
+
+    fn serialize_u16(self, v: u16) -> Result<Node> {
+        self.serialize_u64(v.into())
+    }
+
+    fn serialize_u32(self, v: u32) -> Result<Node> {
+        self.serialize_u64(v.into())
+    }
+
+    fn serialize_u64(self, v: u64) -> Result<Node> {
+        // pkl integers are 64-bit signed
+        if v > i64::MAX as u64 {
+            return Err(Error::Message(format!("{v} is out of range for a pkl Int")));
+        }
+        Ok(Node::Number(Integer::Pos(v)))
+    }
+
+    fn serialize_f32(self, v: f32) -> Result<Node> {
+        self.serialize_f64(v.into())
+    }
+
+    fn serialize_f64(self, v: f64) -> Result<Node> {
+        Ok(Node::Number(Integer::Float(v)))
+    }
+
+    fn serialize_char(self, v: char) -> Result<Node> {
+        Ok(Node::String(v.to_string()))
+    }
+
+    fn serialize_str(self, v: &str) -> Result<Node> {
+        Ok(Node::String(v.to_owned()))
+    }
+
+    fn serialize_bytes(self, v: &[u8]) -> Result<Node> {
+        Ok(Node::Bytes(v.to_vec()))
+    }
+
+    fn serialize_none(self) -> Result<Node> {
+        Ok(Node::Null)
+    }
+
+    fn serialize_some<T: ?Sized + Serialize>(self, value: &T) -> Result<Node> {
+        value.serialize(self)
+    }
+
+    fn serialize_unit(self) -> Result<Node> {
+        Ok(Node::Null)
+    }
+
+    fn serialize_unit_struct(self, _name: &'static str) -> Result<Node> {
+        Ok(Node::Null)
+    }
+
+    /// Unit variants are written as string literals, which is how pkl represents enums (`"Dev"|"Production"`)
+    fn serialize_unit_variant(
+        self,
+        _name: &'static str,
+        _variant_index: u32,
+        variant: &'static str,
+    ) -> Result<Node> {
+        Ok(Node::String(variant.to_owned()))
+    }
+
+    fn serialize_newtype_struct<T: ?Sized + Serialize>(
+        self,
+        name: &'static str,
+        value: &T,
+    ) -> Result<Node> {
+        let node = value.serialize(self)?;
+        Ok(from_token(name, node))
+    }
+
+    fn serialize_newtype_variant<T: ?Sized + Serialize>(
+        self,
+        _name: &'static str,
+        _variant_index: u32,
+        variant: &'static str,
+        value: &T,
+    ) -> Result<Node> {
+        Ok(Node::Object(vec![(
+            variant.to_owned(),
+            value.serialize(Serializer)?,
+        )]))
+    }
+
+    fn serialize_seq(self, len: Option<usize>) -> Result<SeqSerializer> {
+        Ok(SeqSerializer {
+            items: Vec::with_capacity(len.unwrap_or_default()),
+        })
+    }
+
+    fn serialize_tuple(self, len: usize) -> Result<SeqSerializer> {
+        self.serialize_seq(Some(len))
+    }
+
+    fn serialize_tuple_struct(self, _name: &'static str, len: usize) -> Result<SeqSerializer> {
+        self.serialize_seq(Some(len))
+    }
+
+    fn serialize_tuple_variant(
+        self,
+        _name: &'static str,
+        _variant_index: u32,
+        variant: &'static str,
+        len: usize,
+    ) -> Result<TupleVariantSerializer> {
+        Ok(TupleVariantSerializer {
+            variant,
+            items: Vec::with_capacity(len),
+        })
+    }
+
+    fn serialize_map(self, len: Option<usize>) -> Result<MapSerializer> {
+        Ok(MapSerializer {
+            entries: Vec::with_capacity(len.unwrap_or_default()),
+            next_key: None,
+        })
+    }
+
+    fn serialize_struct(self, name: &'static str, len: usize) -> Result<StructSerializer> {
+        Ok(StructSerializer {
+            name,
+            members: Vec::with_capacity(len),
+        })
+    }
+
+    fn serialize_struct_variant(
+        self,
+        _name: &'static str,
+        _variant_index: u32,
+        variant: &'static str,
+        len: usize,
+    ) -> Result<StructVariantSerializer> {
+        Ok(StructVariantSerializer {
+            variant,
+            members: Vec::with_capacity(len),
+        })
+    }
+}
+
+/// Restores the pkl types that `PklValue` wraps in a marker newtype when it's serialized.
+/// Values that don't have the expected shape are kept as-is.
+fn from_token(name: &str, node: Node) -> Node {
+    match (name, node) {
+        (DURATION_TOKEN, Node::String(s)) => match s.parse::<Duration>() {
+            Ok(d) => Node::Duration(d),
+            Err(_) => Node::String(s),
+        },
+        (DATA_SIZE_TOKEN, Node::String(s)) => match parse_data_size(&s) {
+            Some(ds) => Node::DataSize(ds),
+            None => Node::String(s),
+        },
+        (REGEX_TOKEN, Node::String(s)) => Node::Regex(s),
+        (SET_TOKEN, Node::Listing(items)) => Node::Set(items),
+        (LIST_TOKEN, Node::Listing(items)) => Node::List(items),
+        (MAP_TOKEN, Node::Mapping(entries)) => Node::Map(entries),
+        (OBJECT_TOKEN, Node::Mapping(entries))
+            if entries.iter().all(|(k, _)| matches!(k, Node::String(_))) =>
+        {
+            Node::Object(
+                entries
+                    .into_iter()
+                    .filter_map(|(k, v)| match k {
+                        Node::String(k) => Some((k, v)),
+                        _ => None,
+                    })
+                    .collect(),
+            )
+        }
+        (PAIR_TOKEN, Node::Listing(mut items)) if items.len() == 2 => {
+            let b = items.pop().unwrap();
+            let a = items.pop().unwrap();
+            Node::Pair(Box::new(a), Box::new(b))
+        }
+        (INT_SEQ_TOKEN, Node::Object(members)) => match members.as_slice() {
+            [
+                (_, Node::Number(start)),
+                (_, Node::Number(end)),
+                (_, Node::Number(step)),
+            ] => match (as_i64(start), as_i64(end), as_i64(step)) {
+                (Some(start), Some(end), Some(step)) => Node::IntSeq(IntSeq { start, end, step }),
+                _ => Node::Object(members),
+            },
+            _ => Node::Object(members),
+        },
+        (_, node) => node,
+    }
+}
+
+fn as_i64(n: &Integer) -> Option<i64> {
+    match n {
+        Integer::Pos(n) => i64::try_from(*n).ok(),
+        Integer::Neg(n) => Some(*n),
+        Integer::Float(_) => None,
+    }
+}
+
+/// Splits a pkl literal such as `1.5.min` into its value and unit
+fn split_literal(s: &str) -> Option<(f64, &str)> {
+    let (value, unit) = s.rsplit_once('.')?;
+    Some((value.parse().ok()?, unit))
+}
+
+/// Parses a pkl data size literal, e.g. `512.mb`
+pub(crate) fn parse_data_size(s: &str) -> Option<DataSize> {
+    let (value, unit) = split_literal(s)?;
+    let unit = DataSizeUnit::try_from(unit).ok()?;
+    Some(DataSize::new(value, unit))
+}
+
+pub(crate) struct SeqSerializer {
+    items: Vec<Node>,
+}
+
+impl ser::SerializeSeq for SeqSerializer {
+    type Ok = Node;
+    type Error = Error;
+
+    fn serialize_element<T: ?Sized + Serialize>(&mut self, value: &T) -> Result<()> {
+        self.items.push(value.serialize(Serializer)?);
+        Ok(())
+    }
+
+    fn end(self) -> Result<Node> {
+        Ok(Node::Listing(self.items))
+    }
+}
+
+impl ser::SerializeTuple for SeqSerializer {
+    type Ok = Node;
+    type Error = Error;
+
+    fn serialize_element<T: ?Sized + Serialize>(&mut self, value: &T) -> Result<()> {
+        ser::SerializeSeq::serialize_element(self, value)
+    }
+
+    fn end(self) -> Result<Node> {
+        ser::SerializeSeq::end(self)
+    }
+}
+
+impl ser::SerializeTupleStruct for SeqSerializer {
+    type Ok = Node;
+    type Error = Error;
+
+    fn serialize_field<T: ?Sized + Serialize>(&mut self, value: &T) -> Result<()> {
+        ser::SerializeSeq::serialize_element(self, value)
+    }
+
+    fn end(self) -> Result<Node> {
+        ser::SerializeSeq::end(self)
+    }
+}
+
+pub(crate) struct TupleVariantSerializer {
+    variant: &'static str,
+    items: Vec<Node>,
+}
+
+impl ser::SerializeTupleVariant for TupleVariantSerializer {
+    type Ok = Node;
+    type Error = Error;
+
+    fn serialize_field<T: ?Sized + Serialize>(&mut self, value: &T) -> Result<()> {
+        self.items.push(value.serialize(Serializer)?);
+        Ok(())
+    }
+
+    fn end(self) -> Result<Node> {
+        Ok(Node::Object(vec![(
+            self.variant.to_owned(),
+            Node::Listing(self.items),
+        )]))
+    }
+}
+
+pub(crate) struct MapSerializer {
+    entries: Vec<(Node, Node)>,
+    next_key: Option<Node>,
+}
+
+impl ser::SerializeMap for MapSerializer {
+    type Ok = Node;
+    type Error = Error;
+
+    fn serialize_key<T: ?Sized + Serialize>(&mut self, key: &T) -> Result<()> {
+        self.next_key = Some(key.serialize(Serializer)?);
+        Ok(())
+    }
+
+    fn serialize_value<T: ?Sized + Serialize>(&mut self, value: &T) -> Result<()> {
+        let key = self
+            .next_key
+            .take()
+            .ok_or_else(|| Error::Message("serialize_value called before serialize_key".into()))?;
+        self.entries.push((key, value.serialize(Serializer)?));
+        Ok(())
+    }
+
+    fn end(self) -> Result<Node> {
+        Ok(Node::Mapping(self.entries))
+    }
+}
+
+pub(crate) struct StructSerializer {
+    name: &'static str,
+    members: Vec<(String, Node)>,
+}
+
+impl StructSerializer {
+    /// `std::time::Duration`, [`Duration`] and [`DataSize`] serialize as plain structs,
+    /// recognize them so they can be written as pkl literals (`5.min`, `512.mb`)
+    fn into_literal(self) -> Node {
+        match (self.name, self.members.as_slice()) {
+            (
+                "Duration",
+                [
+                    (secs, Node::Number(Integer::Pos(s))),
+                    (nanos, Node::Number(Integer::Pos(n))),
+                ],
+            ) if secs == "secs" && nanos == "nanos" => {
+                return Node::Duration(std::time::Duration::new(*s, *n as u32).into());
+            }
+            (
+                "Duration",
+                [
+                    (value, Node::Number(Integer::Float(v))),
+                    (unit, Node::String(u)),
+                ],
+            ) if value == "value" && unit == "unit" => {
+                if let Ok(unit) = DurationUnit::try_from(u.as_str()) {
+                    return Node::Duration(Duration::new(*v, unit));
+                }
+            }
+            (
+                "DataSize",
+                [
+                    (value, Node::Number(Integer::Float(v))),
+                    (unit, Node::String(u)),
+                ],
+            ) if value == "value" && unit == "unit" => {
+                if let Ok(unit) = DataSizeUnit::try_from(u.as_str()) {
+                    return Node::DataSize(DataSize::new(*v, unit));
+                }
+            }
+            _ => {}
+        }
+        Node::Object(self.members)
+    }
+}
+
+impl ser::SerializeStruct for StructSerializer {
+    type Ok = Node;
+    type Error = Error;
+
+    fn serialize_field<T: ?Sized + Serialize>(
+        &mut self,
+        key: &'static str,
+        value: &T,
+    ) -> Result<()> {
+        self.members
+            .push((key.to_owned(), value.serialize(Serializer)?));
+        Ok(())
+    }
+
+    fn end(self) -> Result<Node> {
+        Ok(self.into_literal())
+    }
+}
+
+pub(crate) struct StructVariantSerializer {
+    variant: &'static str,
+    members: Vec<(String, Node)>,
+}
+
+impl ser::SerializeStructVariant for StructVariantSerializer {
+    type Ok = Node;
+    type Error = Error;
+
+    fn serialize_field<T: ?Sized + Serialize>(
+        &mut self,
+        key: &'static str,
+        value: &T,
+    ) -> Result<()> {
+        self.members
+            .push((key.to_owned(), value.serialize(Serializer)?));
+        Ok(())
+    }
+
+    fn end(self) -> Result<Node> {
+        Ok(Node::Object(vec![(
+            self.variant.to_owned(),
+            Node::Object(self.members),
+        )]))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::pkl::ser::render_module;
+    use crate::value::PklValue;
+    use serde::Serialize;
+    use std::collections::BTreeMap;
+    use std::time::Duration;
+
+    #[derive(Serialize)]
+    #[allow(dead_code)]
+    enum Mode {
+        Dev,
+        Production,
+    }
+
+    #[derive(Serialize)]
+    struct Config {
+        name: String,
+        port: u16,
+        mode: Mode,
+        timeout: Duration,
+        max_upload: DataSize,
+        ratio: f32,
+        tags: Vec<&'static str>,
+        limits: BTreeMap<&'static str, u32>,
+        fallback: Option<String>,
+        database: Database,
+    }
+
+    #[derive(Serialize)]
+    struct Database {
+        username: String,
+        #[serde(rename = "local")]
+        is_default: bool,
+    }
+
+    #[test]
+    fn serialize_struct_to_module() {
+        let config = Config {
+            name: "app".into(),
+            port: 8080,
+            mode: Mode::Dev,
+            timeout: Duration::from_secs(30),
+            max_upload: DataSize::new(512.0, DataSizeUnit::Megabytes),
+            ratio: 0.5,
+            tags: vec!["a", "b"],
+            limits: BTreeMap::from([("*", 10), ("admin", 100)]),
+            fallback: None,
+            database: Database {
+                username: "root".into(),
+                is_default: true,
+            },
+        };
+
+        let node = config.serialize(Serializer).unwrap();
+        let expected = r#"name = "app"
+port = 8080
+mode = "Dev"
+timeout = 30.s
+max_upload = 512.mb
+ratio = 0.5
+tags = new Listing {
+  "a"
+  "b"
+}
+limits = new Mapping {
+  ["*"] = 10
+  ["admin"] = 100
+}
+fallback = null
+database = new {
+  username = "root"
+  `local` = true
+}
+"#;
+        assert_eq!(render_module(&node).unwrap(), expected);
+    }
+
+    #[test]
+    fn serialize_pkl_value() {
+        let value = crate::Value::Map(
+            [
+                (
+                    "timeout".to_string(),
+                    crate::Value::Duration(crate::value::Duration::new(
+                        90.0,
+                        DurationUnit::Minutes,
+                    )),
+                ),
+                ("pattern".to_string(), crate::Value::Regex("[a-z]+".into())),
+            ]
+            .into_iter()
+            .collect(),
+        );
+
+        let node = value.serialize(Serializer).unwrap();
+        let rendered = render_module(&node).unwrap();
+
+        assert!(rendered.contains("timeout = 90.min\n"));
+        assert!(rendered.contains("pattern = Regex(\"[a-z]+\")\n"));
+    }
+
+    #[test]
+    fn serialize_out_of_range_int() {
+        assert!(u64::MAX.serialize(Serializer).is_err());
+    }
+
+    #[test]
+    fn serialize_to_value_round_trip() {
+        #[derive(Debug, PartialEq, Serialize, serde::Deserialize)]
+        struct Service {
+            name: String,
+            port: u16,
+            timeout: Duration,
+            weights: BTreeMap<String, f64>,
+            ports: BTreeMap<u16, String>,
+            tags: Vec<String>,
+            fallback: Option<String>,
+        }
+
+        let service = Service {
+            name: "api".into(),
+            port: 8080,
+            timeout: Duration::from_millis(1500),
+            weights: BTreeMap::from([("a".into(), 0.25)]),
+            ports: BTreeMap::from([(443, "https".into())]),
+            tags: vec!["web".into()],
+            fallback: None,
+        };
+
+        let value = crate::to_value(&service).unwrap();
+        assert!(matches!(value, PklValue::Object { .. }));
+        assert_eq!(value["port"].as_u64(), Some(8080));
+        assert!(value["timeout"].is_duration());
+        assert!(matches!(value["weights"], PklValue::Mapping(_)));
+        assert!(matches!(value["ports"], PklValue::MappingEntries(_)));
+        assert!(matches!(value["tags"], PklValue::Listing(_)));
+        assert!(value["fallback"].is_null());
+
+        let ports: BTreeMap<u16, String> = crate::from_value_ref(&value["ports"]).unwrap();
+        assert_eq!(ports, service.ports);
+        assert_eq!(crate::from_value::<Service>(value).unwrap(), service);
+    }
+}
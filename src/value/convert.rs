@@ -0,0 +1,449 @@
+//! Conversions between [`PklValue`] and rust values
+
+use std::collections::{BTreeMap, HashMap};
+
+#[cfg(feature = "indexmap")]
+use indexmap::IndexMap;
+
+use crate::{
+    error::{DeserializeError, Error, PathSegment},
+    internal::Integer,
+    value::{DataSize, Duration, IntSeq, PklValue},
+};
+
+impl From<bool> for PklValue {
+    fn from(b: bool) -> Self {
+        PklValue::Boolean(b)
+    }
+}
+
+impl From<String> for PklValue {
+    fn from(s: String) -> Self {
+        PklValue::String(s)
+    }
+}
+
+impl From<&str> for PklValue {
+    fn from(s: &str) -> Self {
+        PklValue::String(s.to_string())
+    }
+}
+
+macro_rules! from_unsigned {
+    ($($ty:ty),*) => {
+        $(
+            impl From<$ty> for PklValue {
+                fn from(n: $ty) -> Self {
+                    PklValue::Int(Integer::Pos(n as u64))
+                }
+            }
+        )*
+    };
+}
+
+// non-negative integers are always `Integer::Pos`, like the ones decoded from pkl
+macro_rules! from_signed {
+    ($($ty:ty),*) => {
+        $(
+            impl From<$ty> for PklValue {
+                fn from(n: $ty) -> Self {
+                    match u64::try_from(n) {
+                        Ok(n) => PklValue::Int(Integer::Pos(n)),
+                        Err(_) => PklValue::Int(Integer::Neg(n as i64)),
+                    }
+                }
+            }
+        )*
+    };
+}
+
+from_unsigned!(u8, u16, u32, u64, usize);
+from_signed!(i8, i16, i32, i64, isize);
+
+impl From<f32> for PklValue {
+    fn from(f: f32) -> Self {
+        PklValue::Int(Integer::Float(f64::from(f)))
+    }
+}
+
+impl From<f64> for PklValue {
+    fn from(f: f64) -> Self {
+        PklValue::Int(Integer::Float(f))
+    }
+}
+
+impl From<Duration> for PklValue {
+    fn from(d: Duration) -> Self {
+        PklValue::Duration(d)
+    }
+}
+
+impl From<std::time::Duration> for PklValue {
+    fn from(d: std::time::Duration) -> Self {
+        PklValue::Duration(d.into())
+    }
+}
+
+impl From<DataSize> for PklValue {
+    fn from(ds: DataSize) -> Self {
+        PklValue::DataSize(ds)
+    }
+}
+
+impl From<IntSeq> for PklValue {
+    fn from(seq: IntSeq) -> Self {
+        PklValue::IntSeq(seq)
+    }
+}
+
+/// `None` becomes `null`
+impl<T: Into<PklValue>> From<Option<T>> for PklValue {
+    fn from(value: Option<T>) -> Self {
+        value.map_or(PklValue::Null, Into::into)
+    }
+}
+
+/// Builds a `List`, use [`PklValue::Bytes`] for `Bytes`
+impl<T: Into<PklValue>> From<Vec<T>> for PklValue {
+    fn from(items: Vec<T>) -> Self {
+        PklValue::List(items.into_iter().map(Into::into).collect())
+    }
+}
+
+impl<T: Into<PklValue> + Clone> From<&[T]> for PklValue {
+    fn from(items: &[T]) -> Self {
+        PklValue::List(items.iter().cloned().map(Into::into).collect())
+    }
+}
+
+impl<V: Into<PklValue>> From<HashMap<String, V>> for PklValue {
+    fn from(map: HashMap<String, V>) -> Self {
+        map.into_iter().collect()
+    }
+}
+
+impl<V: Into<PklValue>> From<BTreeMap<String, V>> for PklValue {
+    fn from(map: BTreeMap<String, V>) -> Self {
+        map.into_iter().collect()
+    }
+}
+
+#[cfg(feature = "indexmap")]
+impl<V: Into<PklValue>> From<IndexMap<String, V>> for PklValue {
+    fn from(map: IndexMap<String, V>) -> Self {
+        map.into_iter().collect()
+    }
+}
+
+/// Collects into a `List`
+impl<T: Into<PklValue>> FromIterator<T> for PklValue {
+    fn from_iter<I: IntoIterator<Item = T>>(iter: I) -> Self {
+        PklValue::List(iter.into_iter().map(Into::into).collect())
+    }
+}
+
+/// Collects into a `Map`
+impl<K: Into<String>, V: Into<PklValue>> FromIterator<(K, V)> for PklValue {
+    fn from_iter<I: IntoIterator<Item = (K, V)>>(iter: I) -> Self {
+        PklValue::Map(
+            iter.into_iter()
+                .map(|(k, v)| (k.into(), v.into()))
+                .collect(),
+        )
+    }
+}
+
+fn mismatch(expected: &str, value: &PklValue) -> Error {
+    Error::DeserializeError(DeserializeError::mismatch(expected, value.type_name()))
+}
+
+impl TryFrom<PklValue> for bool {
+    type Error = Error;
+    fn try_from(value: PklValue) -> Result<Self, Self::Error> {
+        match value {
+            PklValue::Boolean(b) => Ok(b),
+            value => Err(mismatch("a boolean", &value)),
+        }
+    }
+}
+
+impl TryFrom<PklValue> for String {
+    type Error = Error;
+    fn try_from(value: PklValue) -> Result<Self, Self::Error> {
+        match value {
+            PklValue::String(s) => Ok(s),
+            value => Err(mismatch("a string", &value)),
+        }
+    }
+}
+
+macro_rules! try_from_int {
+    ($($ty:ty),*) => {
+        $(
+            impl TryFrom<PklValue> for $ty {
+                type Error = Error;
+                fn try_from(value: PklValue) -> Result<Self, Self::Error> {
+                    let n = match &value {
+                        PklValue::Int(Integer::Pos(n)) => <$ty>::try_from(*n).ok(),
+                        PklValue::Int(Integer::Neg(n)) => <$ty>::try_from(*n).ok(),
+                        _ => return Err(mismatch(stringify!($ty), &value)),
+                    };
+                    n.ok_or_else(|| {
+                        Error::DeserializeError(DeserializeError::new(format!(
+                            "invalid value: integer `{}`, expected {}",
+                            value.to_pkl_string(),
+                            stringify!($ty)
+                        )))
+                    })
+                }
+            }
+        )*
+    };
+}
+
+try_from_int!(u8, u16, u32, u64, usize, i8, i16, i32, i64, isize);
+
+/// Accepts both `Float` and `Int` values
+impl TryFrom<PklValue> for f64 {
+    type Error = Error;
+    fn try_from(value: PklValue) -> Result<Self, Self::Error> {
+        value.as_f64().ok_or_else(|| mismatch("f64", &value))
+    }
+}
+
+impl TryFrom<PklValue> for Duration {
+    type Error = Error;
+    fn try_from(value: PklValue) -> Result<Self, Self::Error> {
+        match value {
+            PklValue::Duration(d) => Ok(d),
+            value => Err(mismatch("a Duration", &value)),
+        }
+    }
+}
+
+impl TryFrom<PklValue> for std::time::Duration {
+    type Error = Error;
+    fn try_from(value: PklValue) -> Result<Self, Self::Error> {
+        Duration::try_from(value)?.try_into()
+    }
+}
+
+impl TryFrom<PklValue> for DataSize {
+    type Error = Error;
+    fn try_from(value: PklValue) -> Result<Self, Self::Error> {
+        match value {
+            PklValue::DataSize(ds) => Ok(ds),
+            value => Err(mismatch("a DataSize", &value)),
+        }
+    }
+}
+
+/// Converts the elements of a `List`, `Listing` or `Set`
+impl<T> TryFrom<PklValue> for Vec<T>
+where
+    T: TryFrom<PklValue, Error = Error>,
+{
+    type Error = Error;
+    fn try_from(value: PklValue) -> Result<Self, Self::Error> {
+        match value {
+            PklValue::List(items) | PklValue::Listing(items) | PklValue::Set(items) => items
+                .into_iter()
+                .enumerate()
+                .map(|(i, item)| {
+                    T::try_from(item).map_err(|e| e.with_parent(PathSegment::Index(i)))
+                })
+                .collect(),
+            value => Err(mismatch("a sequence", &value)),
+        }
+    }
+}
+
+fn try_from_members<M, T>(value: PklValue) -> Result<M, Error>
+where
+    M: FromIterator<(String, T)>,
+    T: TryFrom<PklValue, Error = Error>,
+{
+    match value {
+        PklValue::Map(members) | PklValue::Mapping(members) | PklValue::Object { members, .. } => {
+            members
+                .into_iter()
+                .map(|(k, v)| match T::try_from(v) {
+                    Ok(v) => Ok((k, v)),
+                    Err(e) => Err(e.with_parent(PathSegment::Field(k))),
+                })
+                .collect()
+        }
+        value => Err(mismatch("a map", &value)),
+    }
+}
+
+/// Converts the members of a `Map`, `Mapping` or object
+impl<T> TryFrom<PklValue> for HashMap<String, T>
+where
+    T: TryFrom<PklValue, Error = Error>,
+{
+    type Error = Error;
+    fn try_from(value: PklValue) -> Result<Self, Self::Error> {
+        try_from_members(value)
+    }
+}
+
+/// Converts the members of a `Map`, `Mapping` or object
+impl<T> TryFrom<PklValue> for BTreeMap<String, T>
+where
+    T: TryFrom<PklValue, Error = Error>,
+{
+    type Error = Error;
+    fn try_from(value: PklValue) -> Result<Self, Self::Error> {
+        try_from_members(value)
+    }
+}
+
+/// Converts the members of a `Map`, `Mapping` or object
+#[cfg(feature = "indexmap")]
+impl<T> TryFrom<PklValue> for IndexMap<String, T>
+where
+    T: TryFrom<PklValue, Error = Error>,
+{
+    type Error = Error;
+    fn try_from(value: PklValue) -> Result<Self, Self::Error> {
+        try_from_members(value)
+    }
+}
+
+/// Builds a [`PklValue`](crate::Value) with a JSON-like syntax.
+///
+/// Objects become `Map`s, arrays become `List`s, `null` is `Null`, and anything else is converted with `From`.
+///
+/// # Example
+/// ```
+/// let value = rpkl::pkl_value!({
+///     "host": "localhost",
+///     "port": 8080,
+///     "replicas": [{ "weight": 0.5 }, null],
+///     "timeout": std::time::Duration::from_secs(30),
+/// });
+///
+/// assert_eq!(value.get_path("replicas[0].weight").and_then(|w| w.as_f64()), Some(0.5));
+/// assert_eq!(value["timeout"].to_pkl_string(), "30.s");
+/// ```
+#[macro_export]
+macro_rules! pkl_value {
+    (null) => {
+        $crate::Value::Null
+    };
+    ([ $($tt:tt)* ]) => {
+        $crate::Value::List($crate::pkl_value!(@list [] [] $($tt)*))
+    };
+    ({ $($tt:tt)* }) => {{
+        #[allow(unused_mut)]
+        let mut members = $crate::value::value::MapImpl::new();
+        $crate::pkl_value!(@object members () $($tt)*);
+        $crate::Value::Map(members)
+    }};
+
+    // lists: collect the tokens of each element up to the next comma
+    (@list [$($out:expr,)*] []) => {
+        vec![$($out,)*]
+    };
+    (@list [$($out:expr,)*] [$($cur:tt)+]) => {
+        vec![$($out,)* $crate::pkl_value!($($cur)+)]
+    };
+    (@list [$($out:expr,)*] [$($cur:tt)+] , $($rest:tt)*) => {
+        $crate::pkl_value!(@list [$($out,)* $crate::pkl_value!($($cur)+),] [] $($rest)*)
+    };
+    (@list [$($out:expr,)*] [$($cur:tt)*] $next:tt $($rest:tt)*) => {
+        $crate::pkl_value!(@list [$($out,)*] [$($cur)* $next] $($rest)*)
+    };
+
+    // objects: `key: value` pairs, collecting the tokens of each value up to the next comma
+    (@object $members:ident ()) => {};
+    (@object $members:ident () $key:tt : $($rest:tt)*) => {
+        $crate::pkl_value!(@object $members ($key) [] $($rest)*)
+    };
+    (@object $members:ident ($key:tt) [$($cur:tt)+]) => {
+        $members.insert(::std::string::String::from($key), $crate::pkl_value!($($cur)+));
+    };
+    (@object $members:ident ($key:tt) [$($cur:tt)+] , $($rest:tt)*) => {
+        $members.insert(::std::string::String::from($key), $crate::pkl_value!($($cur)+));
+        $crate::pkl_value!(@object $members () $($rest)*);
+    };
+    (@object $members:ident ($key:tt) [$($cur:tt)*] $next:tt $($rest:tt)*) => {
+        $crate::pkl_value!(@object $members ($key) [$($cur)* $next] $($rest)*)
+    };
+
+    ($other:expr) => {
+        $crate::Value::from($other)
+    };
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::value::value::MapImpl;
+
+    #[test]
+    fn accessors() {
+        let value = crate::pkl_value!({
+            "port": 8080,
+            "offset": -3,
+            "ratio": 0.25,
+            "big": u64::MAX,
+        });
+        assert_eq!(value["port"].as_i64(), Some(8080));
+        assert_eq!(value["port"].as_u64(), Some(8080));
+        assert_eq!(value["port"].as_f64(), Some(8080.0));
+        assert_eq!(value["offset"].as_i64(), Some(-3));
+        assert_eq!(value["offset"].as_u64(), None);
+        assert_eq!(value["ratio"].as_f64(), Some(0.25));
+        assert_eq!(value["ratio"].as_i64(), None);
+        assert_eq!(value["big"].as_i64(), None);
+        assert!(value["missing"].is_null());
+
+        let pair = PklValue::Pair(Box::new(1.into()), Box::new("a".into()));
+        assert_eq!(pair.as_pair(), Some((&1.into(), &"a".into())));
+        assert_eq!(PklValue::Regex("a+".into()).as_regex(), Some("a+"));
+    }
+
+    #[test]
+    fn from_rust_values() {
+        assert_eq!(PklValue::from(5i32), PklValue::Int(Integer::Pos(5)));
+        assert_eq!(PklValue::from(-5i32), PklValue::Int(Integer::Neg(-5)));
+        assert_eq!(PklValue::from(None::<bool>), PklValue::Null);
+        assert_eq!(
+            PklValue::from(vec!["a", "b"]),
+            crate::pkl_value!(["a", "b"])
+        );
+
+        let nested = crate::pkl_value!({
+            "list": [1, [2, 3], { "x": -1 }],
+            "empty": {},
+            "expr": 1 + 2,
+        });
+        assert_eq!(nested.get_path("list[1][1]"), Some(&3.into()));
+        assert_eq!(nested.get_path("list[2].x"), Some(&(-1).into()));
+        assert_eq!(nested["empty"], PklValue::Map(MapImpl::new()));
+        assert_eq!(nested["expr"], 3.into());
+    }
+
+    #[test]
+    fn try_from_values() {
+        assert_eq!(u16::try_from(PklValue::from(8080)).unwrap(), 8080);
+        assert!(u8::try_from(PklValue::from(300)).is_err());
+        assert!(u32::try_from(PklValue::from(-1)).is_err());
+        assert_eq!(f64::try_from(PklValue::from(2)).unwrap(), 2.0);
+
+        let ports: Vec<u16> = crate::pkl_value!([80, 443]).try_into().unwrap();
+        assert_eq!(ports, [80, 443]);
+
+        let err = HashMap::<String, Vec<u16>>::try_from(crate::pkl_value!({
+            "web": [80, "443"],
+        }))
+        .unwrap_err();
+        let Error::DeserializeError(err) = err else {
+            panic!("expected a deserialize error, got {err:?}");
+        };
+        assert_eq!(err.path_string(), "web[1]");
+        assert_eq!(err.actual(), Some("String"));
+    }
+}
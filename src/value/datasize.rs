@@ -1,6 +1,6 @@
 use serde::{Deserialize, Serialize};
 
-#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize)]
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
 /// Implementation of Pkl [Data Size](https://pkl-lang.org/main/current/language-reference/index.html#data-sizes)
 ///
 /// Decimal units: KB, MB, GB, TB, PB
@@ -100,6 +100,23 @@ impl DataSizeUnit {
         }
     }
 
+    /// The number of bytes in one of this unit
+    pub(crate) fn bytes(self) -> u64 {
+        match self {
+            DataSizeUnit::Bytes => 1,
+            DataSizeUnit::Kilobytes => 1_000,
+            DataSizeUnit::Megabytes => 1_000_000,
+            DataSizeUnit::Gigabytes => 1_000_000_000,
+            DataSizeUnit::Terabytes => 1_000_000_000_000,
+            DataSizeUnit::Petabytes => 1_000_000_000_000_000,
+            DataSizeUnit::Kibibytes => 1 << 10,
+            DataSizeUnit::Mebibytes => 1 << 20,
+            DataSizeUnit::Gibibytes => 1 << 30,
+            DataSizeUnit::Tebibytes => 1 << 40,
+            DataSizeUnit::Pebibytes => 1 << 50,
+        }
+    }
+
     pub fn as_str(&self) -> &str {
         match self {
             DataSizeUnit::Bytes => "b",
@@ -117,7 +134,14 @@ impl DataSizeUnit {
     }
 }
 
-#[derive(Debug, Copy, Clone, PartialEq, Deserialize, Serialize)]
+/// Implementation of Pkl [Data Size](https://pkl-lang.org/main/current/language-reference/index.html#data-sizes)
+///
+/// Sizes are compared by their number of bytes regardless of the unit, so `1.kb == 1000.b` and `1.kib > 1.kb`.
+///
+/// Displayed in pkl syntax, e.g. `1.5.gib`.
+/// Besides `DataSize`, a pkl data size can be deserialized into `u64` or `u128` byte counts,
+/// or into `bytesize::ByteSize` with the `bytesize` feature.
+#[derive(Debug, Copy, Clone, Deserialize, Serialize)]
 pub struct DataSize {
     value: f64,
     unit: DataSizeUnit,
@@ -126,6 +150,16 @@ pub struct DataSize {
 use serde::de::{self, Visitor};
 use std::fmt::{self, Display};
 
+/// Serialized as the unit suffix used by pkl (e.g. `mb`), matching what `Deserialize` accepts
+impl Serialize for DataSizeUnit {
+    fn serialize<S>(&self, serializer: S) -> Result<S::Ok, S::Error>
+    where
+        S: serde::Serializer,
+    {
+        serializer.serialize_str(self.as_str())
+    }
+}
+
 impl<'de> Deserialize<'de> for DataSizeUnit {
     fn deserialize<D>(deserializer: D) -> Result<Self, D::Error>
     where
@@ -185,6 +219,102 @@ impl DataSize {
     pub fn is_decimal(&self) -> bool {
         self.unit.is_decimal()
     }
+
+    /// The number of bytes, rounded to the nearest byte.
+    ///
+    /// Negative and `NaN` sizes are 0, sizes too large for a `u128` saturate to `u128::MAX`.
+    pub fn to_bytes(&self) -> u128 {
+        let per_unit = u128::from(self.unit.bytes());
+        // the whole part is converted exactly, only the fraction is rounded
+        let whole = (self.value.trunc() as u128).saturating_mul(per_unit);
+        let fraction = (self.value.fract() * per_unit as f64).round() as u128;
+        whole.saturating_add(fraction)
+    }
+
+    /// The same size expressed in another unit, e.g. `1536.mib` as `1.5.gib`
+    pub fn convert_to(&self, unit: DataSizeUnit) -> DataSize {
+        if unit == self.unit {
+            return *self;
+        }
+        let value = self.value * self.unit.bytes() as f64 / unit.bytes() as f64;
+        DataSize::new(value, unit)
+    }
+
+    /// The number of bytes as a float, used for comparisons across units
+    fn as_f64(&self) -> f64 {
+        self.value * self.unit.bytes() as f64
+    }
+}
+
+impl PartialEq for DataSize {
+    fn eq(&self, other: &Self) -> bool {
+        self.as_f64() == other.as_f64()
+    }
+}
+
+impl PartialOrd for DataSize {
+    fn partial_cmp(&self, other: &Self) -> Option<std::cmp::Ordering> {
+        self.as_f64().partial_cmp(&other.as_f64())
+    }
+}
+
+/// Displayed in pkl syntax, e.g. `512.mb` or `1.5.gib`
+impl Display for DataSize {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.write_str(&render_expr(&Node::DataSize(*self)))
+    }
+}
+
+#[cfg(feature = "bytesize")]
+impl From<::bytesize::ByteSize> for DataSize {
+    fn from(size: ::bytesize::ByteSize) -> Self {
+        DataSize::new(size.as_u64() as f64, DataSizeUnit::Bytes)
+    }
+}
+
+#[cfg(feature = "bytesize")]
+impl TryFrom<DataSize> for ::bytesize::ByteSize {
+    type Error = crate::Error;
+    fn try_from(size: DataSize) -> Result<Self, Self::Error> {
+        size.try_into().map(::bytesize::ByteSize::b)
+    }
+}
+
+/// Converts to a byte count, failing for negative sizes and sizes that don't fit in a `u64`
+impl TryFrom<DataSize> for u64 {
+    type Error = crate::Error;
+    fn try_from(size: DataSize) -> Result<Self, Self::Error> {
+        if size.value < 0.0 || size.value.is_nan() {
+            return Err(crate::Error::Message(format!(
+                "cannot convert data size {size} to a byte count"
+            )));
+        }
+        u64::try_from(size.to_bytes())
+            .map_err(|_| crate::Error::Message(format!("data size {size} doesn't fit in a u64")))
+    }
+}
+
+/// Deserialize a `bytesize::ByteSize` field from a pkl `DataSize`,
+/// for use with `#[serde(with = "rpkl::value::datasize::bytesize")]`
+#[cfg(feature = "bytesize")]
+pub mod bytesize {
+    use serde::{Deserialize, Deserializer, Serialize, Serializer, de};
+
+    pub fn deserialize<'de, D>(deserializer: D) -> Result<::bytesize::ByteSize, D::Error>
+    where
+        D: Deserializer<'de>,
+    {
+        super::DataSize::deserialize(deserializer)?
+            .try_into()
+            .map_err(de::Error::custom)
+    }
+
+    pub fn serialize<S>(size: &::bytesize::ByteSize, serializer: S) -> Result<S::Ok, S::Error>
+    where
+        S: Serializer,
+    {
+        super::DataSize::from(*size).serialize(serializer)
+    }
 }
 
 use serde::{
@@ -192,7 +322,10 @@ use serde::{
     forward_to_deserialize_any,
 };
 
-use crate::pkl::de::KeyDeserializer;
+use crate::pkl::{
+    de::KeyDeserializer,
+    ser::{Node, render_expr},
+};
 
 pub struct DataSizeDeserializer<'a> {
     pub input: &'a DataSize,
@@ -258,3 +391,35 @@ impl<'de> MapAccess<'de> for DataSizeMapAccess<'_> {
         }
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn conversions() {
+        let size = DataSize::new(1.5, DataSizeUnit::Gibibytes);
+        assert_eq!(size.to_bytes(), 1_610_612_736);
+        assert_eq!(
+            size.convert_to(DataSizeUnit::Mebibytes),
+            DataSize::new(1536.0, DataSizeUnit::Mebibytes)
+        );
+        assert_eq!(size.to_string(), "1.5.gib");
+        assert_eq!(u64::try_from(size).unwrap(), 1_610_612_736);
+        assert!(u64::try_from(DataSize::new(-1.0, DataSizeUnit::Bytes)).is_err());
+        assert_eq!(DataSize::new(-1.0, DataSizeUnit::Bytes).to_bytes(), 0);
+    }
+
+    #[test]
+    fn ordering_across_units() {
+        let kb = DataSize::new(1.0, DataSizeUnit::Kilobytes);
+        let kib = DataSize::new(1.0, DataSizeUnit::Kibibytes);
+
+        assert_eq!(kb, DataSize::new(1000.0, DataSizeUnit::Bytes));
+        assert!(kib > kb);
+        assert!(
+            DataSize::new(0.5, DataSizeUnit::Megabytes)
+                < DataSize::new(0.5, DataSizeUnit::Mebibytes)
+        );
+    }
+}
@@ -0,0 +1,576 @@
+use std::fmt::{self, Display};
+use std::str::FromStr;
+
+use serde::{
+    Deserialize, Serialize,
+    de::{self, MapAccess, Visitor},
+    forward_to_deserialize_any,
+};
+
+use crate::pkl::{
+    de::DurationMapAccess,
+    ser::{Node, render_expr},
+};
+
+/// Units of a Pkl [Duration](https://pkl-lang.org/main/current/language-reference/index.html#durations)
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum DurationUnit {
+    Nanoseconds,
+    Microseconds,
+    Milliseconds,
+    Seconds,
+    Minutes,
+    Hours,
+    Days,
+}
+
+impl TryFrom<&str> for DurationUnit {
+    type Error = crate::Error;
+    fn try_from(unit: &str) -> Result<Self, Self::Error> {
+        match unit {
+            "ns" => Ok(DurationUnit::Nanoseconds),
+            "us" => Ok(DurationUnit::Microseconds),
+            "ms" => Ok(DurationUnit::Milliseconds),
+            "s" => Ok(DurationUnit::Seconds),
+            "min" => Ok(DurationUnit::Minutes),
+            "h" => Ok(DurationUnit::Hours),
+            "d" => Ok(DurationUnit::Days),
+            _ => Err(crate::Error::Message(format!(
+                "invalid duration unit: {unit}"
+            ))),
+        }
+    }
+}
+
+impl Display for DurationUnit {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.write_str(self.as_str())
+    }
+}
+
+impl DurationUnit {
+    /// Units from largest to smallest
+    const DESCENDING: [DurationUnit; 7] = [
+        DurationUnit::Days,
+        DurationUnit::Hours,
+        DurationUnit::Minutes,
+        DurationUnit::Seconds,
+        DurationUnit::Milliseconds,
+        DurationUnit::Microseconds,
+        DurationUnit::Nanoseconds,
+    ];
+
+    /// The number of nanoseconds in one of this unit
+    pub(crate) fn nanos(self) -> u64 {
+        match self {
+            DurationUnit::Nanoseconds => 1,
+            DurationUnit::Microseconds => 1_000,
+            DurationUnit::Milliseconds => 1_000_000,
+            DurationUnit::Seconds => 1_000_000_000,
+            DurationUnit::Minutes => 60_000_000_000,
+            DurationUnit::Hours => 3_600_000_000_000,
+            DurationUnit::Days => 86_400_000_000_000,
+        }
+    }
+
+    pub fn as_str(&self) -> &str {
+        match self {
+            DurationUnit::Nanoseconds => "ns",
+            DurationUnit::Microseconds => "us",
+            DurationUnit::Milliseconds => "ms",
+            DurationUnit::Seconds => "s",
+            DurationUnit::Minutes => "min",
+            DurationUnit::Hours => "h",
+            DurationUnit::Days => "d",
+        }
+    }
+}
+
+/// Implementation of Pkl [Duration](https://pkl-lang.org/main/current/language-reference/index.html#durations)
+///
+/// Unlike `std::time::Duration`, the value can be negative (`-5.min`),
+/// and the unit is kept as written, so `90.min` isn't normalized to `1.5.h`.
+/// Durations are compared by their length regardless of the unit, so `60.s == 1.min` and `1.h > 59.min`.
+///
+/// Converts to `std::time::Duration` with `TryFrom` (failing for negative durations),
+/// and to `chrono::TimeDelta` and `time::Duration` behind the `chrono` and `time` features.
+///
+/// Displayed in pkl syntax, e.g. `5.min`, and deserialized from either `{ value, unit }` or such a string.
+#[derive(Debug, Copy, Clone, Serialize)]
+pub struct Duration {
+    value: f64,
+    unit: DurationUnit,
+}
+
+/// Serialized as the unit suffix used by pkl (e.g. `min`), matching what `Deserialize` accepts
+impl Serialize for DurationUnit {
+    fn serialize<S>(&self, serializer: S) -> Result<S::Ok, S::Error>
+    where
+        S: serde::Serializer,
+    {
+        serializer.serialize_str(self.as_str())
+    }
+}
+
+impl<'de> Deserialize<'de> for DurationUnit {
+    fn deserialize<D>(deserializer: D) -> Result<Self, D::Error>
+    where
+        D: serde::Deserializer<'de>,
+    {
+        struct DurationUnitVisitor;
+
+        impl Visitor<'_> for DurationUnitVisitor {
+            type Value = DurationUnit;
+
+            fn expecting(&self, formatter: &mut fmt::Formatter) -> fmt::Result {
+                formatter.write_str("a valid duration unit")
+            }
+
+            fn visit_str<E>(self, value: &str) -> Result<DurationUnit, E>
+            where
+                E: de::Error,
+            {
+                DurationUnit::try_from(value).map_err(E::custom)
+            }
+        }
+
+        deserializer.deserialize_str(DurationUnitVisitor)
+    }
+}
+
+impl<'de> Deserialize<'de> for Duration {
+    fn deserialize<D>(deserializer: D) -> Result<Self, D::Error>
+    where
+        D: serde::Deserializer<'de>,
+    {
+        struct DurationVisitor;
+
+        impl<'de> Visitor<'de> for DurationVisitor {
+            type Value = Duration;
+
+            fn expecting(&self, formatter: &mut fmt::Formatter) -> fmt::Result {
+                formatter.write_str("a duration as `{ value, unit }` or a string such as \"5.min\"")
+            }
+
+            fn visit_str<E>(self, value: &str) -> Result<Duration, E>
+            where
+                E: de::Error,
+            {
+                value.parse().map_err(E::custom)
+            }
+
+            fn visit_map<A>(self, mut map: A) -> Result<Duration, A::Error>
+            where
+                A: MapAccess<'de>,
+            {
+                let mut value = None;
+                let mut unit = None;
+                while let Some(key) = map.next_key::<String>()? {
+                    match key.as_str() {
+                        "value" => value = Some(map.next_value::<f64>()?),
+                        "unit" => unit = Some(map.next_value::<DurationUnit>()?),
+                        _ => return Err(de::Error::unknown_field(&key, &["value", "unit"])),
+                    }
+                }
+                let value = value.ok_or_else(|| de::Error::missing_field("value"))?;
+                let unit = unit.ok_or_else(|| de::Error::missing_field("unit"))?;
+                Ok(Duration::new(value, unit))
+            }
+        }
+
+        deserializer.deserialize_any(DurationVisitor)
+    }
+}
+
+/// Parses a pkl duration literal, e.g. `5.min` or `-1.5.s`
+impl FromStr for Duration {
+    type Err = crate::Error;
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let invalid = || crate::Error::Message(format!("invalid duration: {s:?}"));
+        let (value, unit) = s.trim().rsplit_once('.').ok_or_else(invalid)?;
+        let value = value.parse::<f64>().map_err(|_| invalid())?;
+        Ok(Duration::new(value, DurationUnit::try_from(unit)?))
+    }
+}
+
+impl PartialEq for Duration {
+    fn eq(&self, other: &Self) -> bool {
+        self.as_f64() == other.as_f64()
+    }
+}
+
+impl PartialOrd for Duration {
+    fn partial_cmp(&self, other: &Self) -> Option<std::cmp::Ordering> {
+        self.as_f64().partial_cmp(&other.as_f64())
+    }
+}
+
+/// Displayed in pkl syntax, e.g. `5.min` or `1.5.s`
+impl Display for Duration {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.write_str(&render_expr(&Node::Duration(*self)))
+    }
+}
+
+impl Duration {
+    pub fn new(value: f64, unit: DurationUnit) -> Self {
+        Duration { value, unit }
+    }
+
+    pub fn value(&self) -> f64 {
+        self.value
+    }
+
+    pub fn unit(&self) -> DurationUnit {
+        self.unit
+    }
+
+    pub fn is_negative(&self) -> bool {
+        self.value < 0.0
+    }
+
+    /// The same duration expressed in another unit, e.g. `90.min` as `1.5.h`
+    pub fn to_unit(&self, unit: DurationUnit) -> Duration {
+        if unit == self.unit {
+            return *self;
+        }
+        let value = self.value * self.unit.nanos() as f64 / unit.nanos() as f64;
+        Duration::new(value, unit)
+    }
+
+    /// The duration in seconds, negative for negative durations
+    pub fn as_secs_f64(&self) -> f64 {
+        self.value * self.unit.nanos() as f64 / 1e9
+    }
+
+    /// The total number of nanoseconds, rounded to the nearest nanosecond.
+    ///
+    /// Returns `None` if the value is `NaN`, infinite or doesn't fit in an `i128`.
+    pub fn as_nanos(&self) -> Option<i128> {
+        if !self.value.is_finite() {
+            return None;
+        }
+        let per_unit = i128::from(self.unit.nanos());
+        // the whole part is converted exactly, only the fraction is rounded
+        let whole = self.value.trunc();
+        if whole.abs() >= (i128::MAX / per_unit) as f64 {
+            return None;
+        }
+        let fraction = (self.value.fract() * per_unit as f64).round() as i128;
+        Some(whole as i128 * per_unit + fraction)
+    }
+
+    /// A duration of `nanos` nanoseconds in the largest unit that represents it exactly, e.g. `5.min` or `1500.ms`
+    pub(crate) fn from_nanos(nanos: i128) -> Self {
+        if nanos == 0 {
+            return Duration::new(0.0, DurationUnit::Seconds);
+        }
+        let unit = DurationUnit::DESCENDING
+            .into_iter()
+            .find(|unit| nanos % i128::from(unit.nanos()) == 0)
+            .unwrap_or(DurationUnit::Nanoseconds);
+        Duration::new((nanos / i128::from(unit.nanos())) as f64, unit)
+    }
+
+    /// The total number of nanoseconds, used for comparisons
+    fn as_f64(&self) -> f64 {
+        self.value * self.unit.nanos() as f64
+    }
+
+    /// Splits the duration into whole seconds and the remaining nanoseconds, both with the sign of the duration
+    fn split(&self) -> crate::Result<(i128, i128)> {
+        let nanos = self.as_nanos().ok_or_else(|| {
+            crate::Error::Message(format!(
+                "duration out of range: {}.{}",
+                self.value, self.unit
+            ))
+        })?;
+        Ok((nanos / 1_000_000_000, nanos % 1_000_000_000))
+    }
+}
+
+impl From<std::time::Duration> for Duration {
+    fn from(duration: std::time::Duration) -> Self {
+        Duration::from_nanos(duration.as_nanos() as i128)
+    }
+}
+
+impl TryFrom<Duration> for std::time::Duration {
+    type Error = crate::Error;
+    fn try_from(duration: Duration) -> Result<Self, Self::Error> {
+        if duration.is_negative() {
+            return Err(crate::Error::Message(format!(
+                "cannot convert negative duration {}.{} to std::time::Duration",
+                duration.value, duration.unit
+            )));
+        }
+        let (secs, nanos) = duration.split()?;
+        let secs = u64::try_from(secs).map_err(|_| {
+            crate::Error::Message(format!(
+                "duration out of range: {}.{}",
+                duration.value, duration.unit
+            ))
+        })?;
+        Ok(std::time::Duration::new(secs, nanos as u32))
+    }
+}
+
+/// Deserializer for a [`Duration`], yielding it as `{ value, unit }`,
+/// or as a pkl literal such as `"5.min"` when a string is expected
+pub struct DurationDeserializer<'a> {
+    pub input: &'a Duration,
+}
+
+impl<'de> serde::Deserializer<'de> for DurationDeserializer<'_> {
+    type Error = crate::Error;
+
+    forward_to_deserialize_any! {
+        bool i8 i16 i32 u8 u16 u32 f32 char
+        bytes byte_buf option unit unit_struct newtype_struct seq
+        tuple tuple_struct map enum struct identifier ignored_any
+
+        i64 u64 f64
+    }
+
+    fn deserialize_any<V>(self, visitor: V) -> Result<V::Value, Self::Error>
+    where
+        V: Visitor<'de>,
+    {
+        visitor.visit_map(DurationMapAccess {
+            duration: self.input,
+            state: 0,
+        })
+    }
+
+    fn deserialize_str<V>(self, visitor: V) -> Result<V::Value, Self::Error>
+    where
+        V: Visitor<'de>,
+    {
+        visitor.visit_string(self.input.to_string())
+    }
+
+    fn deserialize_string<V>(self, visitor: V) -> Result<V::Value, Self::Error>
+    where
+        V: Visitor<'de>,
+    {
+        self.deserialize_str(visitor)
+    }
+}
+
+#[cfg(feature = "chrono")]
+impl From<::chrono::TimeDelta> for Duration {
+    fn from(delta: ::chrono::TimeDelta) -> Self {
+        Duration::from_nanos(
+            i128::from(delta.num_seconds()) * 1_000_000_000 + i128::from(delta.subsec_nanos()),
+        )
+    }
+}
+
+#[cfg(feature = "chrono")]
+impl TryFrom<Duration> for ::chrono::TimeDelta {
+    type Error = crate::Error;
+    fn try_from(duration: Duration) -> Result<Self, Self::Error> {
+        let (secs, nanos) = duration.split()?;
+        // `TimeDelta::new` expects non-negative nanoseconds
+        let (secs, nanos) = if nanos < 0 {
+            (secs - 1, nanos + 1_000_000_000)
+        } else {
+            (secs, nanos)
+        };
+        i64::try_from(secs)
+            .ok()
+            .and_then(|secs| ::chrono::TimeDelta::new(secs, nanos as u32))
+            .ok_or_else(|| {
+                crate::Error::Message(format!(
+                    "duration out of range for chrono::TimeDelta: {}.{}",
+                    duration.value, duration.unit
+                ))
+            })
+    }
+}
+
+#[cfg(feature = "time")]
+impl From<::time::Duration> for Duration {
+    fn from(duration: ::time::Duration) -> Self {
+        Duration::from_nanos(duration.whole_nanoseconds())
+    }
+}
+
+#[cfg(feature = "time")]
+impl TryFrom<Duration> for ::time::Duration {
+    type Error = crate::Error;
+    fn try_from(duration: Duration) -> Result<Self, Self::Error> {
+        let (secs, nanos) = duration.split()?;
+        let secs = i64::try_from(secs).map_err(|_| {
+            crate::Error::Message(format!(
+                "duration out of range for time::Duration: {}.{}",
+                duration.value, duration.unit
+            ))
+        })?;
+        Ok(::time::Duration::new(secs, nanos as i32))
+    }
+}
+
+/// (De)serialize a `chrono::TimeDelta` field from a pkl `Duration`,
+/// for use with `#[serde(with = "rpkl::value::duration::chrono")]`
+#[cfg(feature = "chrono")]
+pub mod chrono {
+    use serde::{Deserialize, Deserializer, Serialize, Serializer, de};
+
+    pub fn deserialize<'de, D>(deserializer: D) -> Result<::chrono::TimeDelta, D::Error>
+    where
+        D: Deserializer<'de>,
+    {
+        super::Duration::deserialize(deserializer)?
+            .try_into()
+            .map_err(de::Error::custom)
+    }
+
+    pub fn serialize<S>(delta: &::chrono::TimeDelta, serializer: S) -> Result<S::Ok, S::Error>
+    where
+        S: Serializer,
+    {
+        super::Duration::from(*delta).serialize(serializer)
+    }
+}
+
+/// (De)serialize a `time::Duration` field from a pkl `Duration`,
+/// for use with `#[serde(with = "rpkl::value::duration::time")]`
+#[cfg(feature = "time")]
+pub mod time {
+    use serde::{Deserialize, Deserializer, Serialize, Serializer, de};
+
+    pub fn deserialize<'de, D>(deserializer: D) -> Result<::time::Duration, D::Error>
+    where
+        D: Deserializer<'de>,
+    {
+        super::Duration::deserialize(deserializer)?
+            .try_into()
+            .map_err(de::Error::custom)
+    }
+
+    pub fn serialize<S>(duration: &::time::Duration, serializer: S) -> Result<S::Ok, S::Error>
+    where
+        S: Serializer,
+    {
+        super::Duration::from(*duration).serialize(serializer)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn negative_durations() {
+        let duration = Duration::new(-1.5, DurationUnit::Seconds);
+        assert!(duration.is_negative());
+        assert_eq!(duration.as_nanos(), Some(-1_500_000_000));
+        assert!(std::time::Duration::try_from(duration).is_err());
+
+        let duration = Duration::new(90.0, DurationUnit::Minutes);
+        assert_eq!(
+            std::time::Duration::try_from(duration).unwrap(),
+            std::time::Duration::from_secs(5400)
+        );
+        assert_eq!(
+            Duration::from(std::time::Duration::from_secs(5400)),
+            duration
+        );
+        assert_eq!(
+            Duration::new(f64::NAN, DurationUnit::Seconds).as_nanos(),
+            None
+        );
+    }
+
+    #[test]
+    fn unit_conversions() {
+        let duration = Duration::new(90.0, DurationUnit::Minutes);
+        let hours = duration.to_unit(DurationUnit::Hours);
+        assert_eq!((hours.value(), hours.unit()), (1.5, DurationUnit::Hours));
+        assert_eq!(duration.as_secs_f64(), 5400.0);
+        assert_eq!(duration.to_string(), "90.min");
+        assert_eq!(
+            Duration::new(-2.5, DurationUnit::Seconds).to_string(),
+            "-2.5.s"
+        );
+        assert_eq!(
+            "-2.5.s".parse::<Duration>().unwrap(),
+            Duration::new(-2.5, DurationUnit::Seconds)
+        );
+        assert!("5.minutes".parse::<Duration>().is_err());
+    }
+
+    #[test]
+    fn compare_across_units() {
+        assert_eq!(
+            Duration::new(60.0, DurationUnit::Seconds),
+            Duration::new(1.0, DurationUnit::Minutes)
+        );
+        assert!(
+            Duration::new(1.0, DurationUnit::Hours) > Duration::new(59.0, DurationUnit::Minutes)
+        );
+        assert!(
+            Duration::new(-1.0, DurationUnit::Days) < Duration::new(1.0, DurationUnit::Nanoseconds)
+        );
+        assert_ne!(
+            Duration::new(f64::NAN, DurationUnit::Seconds),
+            Duration::new(f64::NAN, DurationUnit::Seconds)
+        );
+    }
+
+    #[test]
+    fn deserialize_duration() {
+        let expected = Duration::new(5.0, DurationUnit::Minutes);
+        assert_eq!(
+            serde_json::from_str::<Duration>(r#""5.min""#).unwrap(),
+            expected
+        );
+        assert_eq!(
+            serde_json::from_str::<Duration>(r#"{"value": 5.0, "unit": "min"}"#).unwrap(),
+            expected
+        );
+
+        #[derive(Debug, PartialEq, Deserialize)]
+        struct Custom {
+            value: f64,
+            unit: String,
+        }
+        let custom = Custom::deserialize(DurationDeserializer { input: &expected }).unwrap();
+        assert_eq!(
+            custom,
+            Custom {
+                value: 5.0,
+                unit: "min".into()
+            }
+        );
+        assert_eq!(
+            String::deserialize(DurationDeserializer { input: &expected }).unwrap(),
+            "5.min"
+        );
+    }
+
+    #[cfg(feature = "chrono")]
+    #[test]
+    fn chrono_conversions() {
+        let duration = Duration::new(-1.5, DurationUnit::Seconds);
+        let delta = ::chrono::TimeDelta::try_from(duration).unwrap();
+        assert_eq!(delta, ::chrono::TimeDelta::milliseconds(-1500));
+        assert_eq!(
+            Duration::from(delta),
+            Duration::new(-1500.0, DurationUnit::Milliseconds)
+        );
+    }
+
+    #[cfg(feature = "time")]
+    #[test]
+    fn time_conversions() {
+        let duration = Duration::new(-1.5, DurationUnit::Seconds);
+        let converted = ::time::Duration::try_from(duration).unwrap();
+        assert_eq!(converted, ::time::Duration::milliseconds(-1500));
+        assert_eq!(
+            Duration::from(converted),
+            Duration::new(-1500.0, DurationUnit::Milliseconds)
+        );
+    }
+}
@@ -0,0 +1,371 @@
+use std::ops::Index;
+
+use crate::internal::Integer;
+
+use super::PklValue;
+
+static NULL: PklValue = PklValue::Null;
+
+/// A type that can index into a [`PklValue`], see [`PklValue::get`].
+///
+/// Implemented for `str` and `String`, which look up a member of a map or object,
+/// and `usize`, which looks up an element of a list, set or pair, or an entry of a map with `Int` keys.
+pub trait ValueIndex: private::Sealed {
+    #[doc(hidden)]
+    fn index_into<'v>(&self, value: &'v PklValue) -> Option<&'v PklValue>;
+
+    #[doc(hidden)]
+    fn index_into_mut<'v>(&self, value: &'v mut PklValue) -> Option<&'v mut PklValue>;
+}
+
+impl ValueIndex for str {
+    fn index_into<'v>(&self, value: &'v PklValue) -> Option<&'v PklValue> {
+        match value {
+            PklValue::Map(members)
+            | PklValue::Mapping(members)
+            | PklValue::Object { members, .. } => members.get(self),
+            _ => None,
+        }
+    }
+
+    fn index_into_mut<'v>(&self, value: &'v mut PklValue) -> Option<&'v mut PklValue> {
+        match value {
+            PklValue::Map(members)
+            | PklValue::Mapping(members)
+            | PklValue::Object { members, .. } => members.get_mut(self),
+            _ => None,
+        }
+    }
+}
+
+impl ValueIndex for String {
+    fn index_into<'v>(&self, value: &'v PklValue) -> Option<&'v PklValue> {
+        self.as_str().index_into(value)
+    }
+
+    fn index_into_mut<'v>(&self, value: &'v mut PklValue) -> Option<&'v mut PklValue> {
+        self.as_str().index_into_mut(value)
+    }
+}
+
+impl ValueIndex for usize {
+    fn index_into<'v>(&self, value: &'v PklValue) -> Option<&'v PklValue> {
+        match value {
+            PklValue::List(items) | PklValue::Listing(items) | PklValue::Set(items) => {
+                items.get(*self)
+            }
+            PklValue::Pair(first, _) if *self == 0 => Some(first),
+            PklValue::Pair(_, second) if *self == 1 => Some(second),
+            PklValue::Entries(entries) | PklValue::MappingEntries(entries) => entries
+                .iter()
+                .find(|(key, _)| is_int(key, *self))
+                .map(|(_, value)| value),
+            _ => None,
+        }
+    }
+
+    fn index_into_mut<'v>(&self, value: &'v mut PklValue) -> Option<&'v mut PklValue> {
+        match value {
+            PklValue::List(items) | PklValue::Listing(items) | PklValue::Set(items) => {
+                items.get_mut(*self)
+            }
+            PklValue::Pair(first, _) if *self == 0 => Some(first),
+            PklValue::Pair(_, second) if *self == 1 => Some(second),
+            PklValue::Entries(entries) | PklValue::MappingEntries(entries) => entries
+                .iter_mut()
+                .find(|(key, _)| is_int(key, *self))
+                .map(|(_, value)| value),
+            _ => None,
+        }
+    }
+}
+
+impl<T: ValueIndex + ?Sized> ValueIndex for &T {
+    fn index_into<'v>(&self, value: &'v PklValue) -> Option<&'v PklValue> {
+        (**self).index_into(value)
+    }
+
+    fn index_into_mut<'v>(&self, value: &'v mut PklValue) -> Option<&'v mut PklValue> {
+        (**self).index_into_mut(value)
+    }
+}
+
+mod private {
+    pub trait Sealed {}
+    impl Sealed for str {}
+    impl Sealed for String {}
+    impl Sealed for usize {}
+    impl<T: Sealed + ?Sized> Sealed for &T {}
+}
+
+fn is_int(key: &PklValue, n: usize) -> bool {
+    matches!(key, PklValue::Int(Integer::Pos(k)) if usize::try_from(*k) == Ok(n))
+}
+
+/// Returns [`PklValue::Null`] if the member or element doesn't exist, like `serde_json::Value`
+impl<I: ValueIndex> Index<I> for PklValue {
+    type Output = PklValue;
+
+    fn index(&self, index: I) -> &PklValue {
+        index.index_into(self).unwrap_or(&NULL)
+    }
+}
+
+/// A segment of a path or pointer
+enum Segment {
+    /// A member name, from `.name`, `["name"]` or a pointer token
+    Key(String),
+    /// The contents of unquoted brackets, e.g. `0` in `[0]`, or a pointer token
+    Raw(String),
+}
+
+impl Segment {
+    fn get<'v>(&self, value: &'v PklValue) -> Option<&'v PklValue> {
+        match (self, value) {
+            (Segment::Key(key), _) => key.index_into(value),
+            (Segment::Raw(raw), PklValue::Entries(entries) | PklValue::MappingEntries(entries)) => {
+                entries
+                    .iter()
+                    .find(|(key, _)| matches_key(key, raw))
+                    .map(|(_, value)| value)
+            }
+            (
+                Segment::Raw(raw),
+                PklValue::Map(_) | PklValue::Mapping(_) | PklValue::Object { .. },
+            ) => raw.index_into(value),
+            (Segment::Raw(raw), _) => raw.parse::<usize>().ok()?.index_into(value),
+        }
+    }
+
+    fn get_mut<'v>(&self, value: &'v mut PklValue) -> Option<&'v mut PklValue> {
+        match (self, value) {
+            (Segment::Key(key), value) => key.index_into_mut(value),
+            (Segment::Raw(raw), PklValue::Entries(entries) | PklValue::MappingEntries(entries)) => {
+                entries
+                    .iter_mut()
+                    .find(|(key, _)| matches_key(key, raw))
+                    .map(|(_, value)| value)
+            }
+            (
+                Segment::Raw(raw),
+                value @ (PklValue::Map(_) | PklValue::Mapping(_) | PklValue::Object { .. }),
+            ) => raw.index_into_mut(value),
+            (Segment::Raw(raw), value) => raw.parse::<usize>().ok()?.index_into_mut(value),
+        }
+    }
+}
+
+/// Whether the key of a map entry is written as `raw`, e.g. `80`, `-1` or `5.min`
+fn matches_key(key: &PklValue, raw: &str) -> bool {
+    match key {
+        PklValue::String(s) => s == raw,
+        key => key.to_pkl_string() == raw,
+    }
+}
+
+/// Splits a path such as `database.replicas[0].host` or `headers["X-Id"]` into its segments
+fn parse_path(path: &str) -> Option<Vec<Segment>> {
+    let mut segments = vec![];
+    let mut chars = path.chars().peekable();
+    // a name can only be at the start of the path or after a `.`
+    let mut after_dot = true;
+
+    while let Some(c) = chars.next() {
+        match c {
+            '[' if chars.peek() == Some(&'"') => {
+                chars.next();
+                let mut key = String::new();
+                loop {
+                    match chars.next()? {
+                        '\\' => key.push(chars.next()?),
+                        '"' => break,
+                        c => key.push(c),
+                    }
+                }
+                if chars.next()? != ']' {
+                    return None;
+                }
+                segments.push(Segment::Key(key));
+            }
+            '[' => {
+                let mut raw = String::new();
+                loop {
+                    match chars.next()? {
+                        ']' => break,
+                        c => raw.push(c),
+                    }
+                }
+                if raw.is_empty() {
+                    return None;
+                }
+                segments.push(Segment::Raw(raw));
+            }
+            '.' if !after_dot => {
+                after_dot = true;
+                continue;
+            }
+            c if after_dot && c != '.' => {
+                let mut name = String::from(c);
+                while let Some(&c) = chars.peek() {
+                    if c == '.' || c == '[' {
+                        break;
+                    }
+                    name.push(c);
+                    chars.next();
+                }
+                segments.push(Segment::Key(name));
+            }
+            _ => return None,
+        }
+        after_dot = false;
+    }
+
+    if after_dot && !segments.is_empty() {
+        return None; // trailing `.`
+    }
+    Some(segments)
+}
+
+/// Splits a JSON pointer such as `/database/replicas/0` into its segments
+fn parse_pointer(pointer: &str) -> Option<Vec<Segment>> {
+    if pointer.is_empty() {
+        return Some(vec![]);
+    }
+    let tokens = pointer.strip_prefix('/')?;
+    Some(
+        tokens
+            .split('/')
+            .map(|token| Segment::Raw(token.replace("~1", "/").replace("~0", "~")))
+            .collect(),
+    )
+}
+
+impl PklValue {
+    /// Returns the member of a map or object, or the element of a list, set or pair, see [`ValueIndex`].
+    ///
+    /// Returns `None` if the value doesn't have the member or element.
+    pub fn get<I: ValueIndex>(&self, index: I) -> Option<&PklValue> {
+        index.index_into(self)
+    }
+
+    /// Mutable version of [`PklValue::get`]
+    pub fn get_mut<I: ValueIndex>(&mut self, index: I) -> Option<&mut PklValue> {
+        index.index_into_mut(self)
+    }
+
+    /// Looks up a value by a path in pkl syntax, e.g. `database.replicas[0].host`.
+    ///
+    /// Members with names that aren't identifiers are written in quotes, `headers["X-Request-Id"]`,
+    /// and entries of maps with non-string keys as pkl expressions, `ports[8080]` or `timeouts[5.min]`.
+    /// This is the format of [`DeserializeError::path_string`](crate::error::DeserializeError::path_string).
+    ///
+    /// Returns `None` if the path is malformed or doesn't exist.
+    pub fn get_path(&self, path: &str) -> Option<&PklValue> {
+        parse_path(path)?
+            .iter()
+            .try_fold(self, |value, segment| segment.get(value))
+    }
+
+    /// Looks up a value by a [JSON pointer](https://datatracker.ietf.org/doc/html/rfc6901),
+    /// e.g. `/database/replicas/0/host`. `~1` and `~0` stand for `/` and `~` in member names.
+    ///
+    /// An empty pointer refers to the value itself. Returns `None` if the pointer is malformed or doesn't exist.
+    pub fn pointer(&self, pointer: &str) -> Option<&PklValue> {
+        parse_pointer(pointer)?
+            .iter()
+            .try_fold(self, |value, segment| segment.get(value))
+    }
+
+    /// Mutable version of [`PklValue::pointer`]
+    pub fn pointer_mut(&mut self, pointer: &str) -> Option<&mut PklValue> {
+        parse_pointer(pointer)?
+            .iter()
+            .try_fold(self, |value, segment| segment.get_mut(value))
+    }
+
+    /// Takes the value out, leaving [`PklValue::Null`] in its place
+    pub fn take(&mut self) -> PklValue {
+        std::mem::replace(self, PklValue::Null)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::pkl_value;
+
+    fn config() -> PklValue {
+        let replica = |host: &str| PklValue::Object {
+            class_name: "config#Replica".into(),
+            module_uri: "file:///config.pkl".into(),
+            members: [("host".to_string(), pkl_value!(host))]
+                .into_iter()
+                .collect(),
+        };
+        pkl_value!({
+            "database": { "replicas": [replica("a"), replica("b")] },
+            "headers": { "X-Id.v2": "1" },
+            "ports": PklValue::Entries(vec![(pkl_value!(8080), pkl_value!("web"))]),
+            "a/b": "slash",
+            "0": "zero",
+        })
+    }
+
+    #[test]
+    fn get_path() {
+        let config = config();
+        assert_eq!(
+            config.get_path("database.replicas[1].host"),
+            Some(&pkl_value!("b"))
+        );
+        assert_eq!(
+            config.get_path(r#"headers["X-Id.v2"]"#),
+            Some(&pkl_value!("1"))
+        );
+        assert_eq!(config.get_path("ports[8080]"), Some(&pkl_value!("web")));
+        assert_eq!(config.get_path("database.replicas[2]"), None);
+        assert_eq!(config.get_path("database..replicas"), None);
+        assert_eq!(config.get_path("database."), None);
+        assert_eq!(config.get_path("database.replicas[0"), None);
+        assert_eq!(config.get_path(""), Some(&config));
+    }
+
+    #[test]
+    fn pointer() {
+        let mut config = config();
+        assert_eq!(
+            config.pointer("/database/replicas/0/host"),
+            Some(&pkl_value!("a"))
+        );
+        assert_eq!(config.pointer("/a~1b"), Some(&pkl_value!("slash")));
+        assert_eq!(config.pointer("/0"), Some(&pkl_value!("zero")));
+        assert_eq!(config.pointer("/ports/8080"), Some(&pkl_value!("web")));
+        assert_eq!(config.pointer("database"), None);
+        assert_eq!(config.pointer(""), Some(&config.clone()));
+
+        *config.pointer_mut("/database/replicas/0/host").unwrap() = pkl_value!("c");
+        assert_eq!(config["database"]["replicas"][0]["host"], pkl_value!("c"));
+    }
+
+    #[test]
+    fn index_and_take() {
+        let mut config = config();
+        assert_eq!(config["ports"][8080], pkl_value!("web"));
+        assert_eq!(config["missing"]["deeper"], PklValue::Null);
+        assert_eq!(
+            config
+                .get("database")
+                .and_then(|db| db.get("replicas"))
+                .map(|r| r.as_array().unwrap().len()),
+            Some(2)
+        );
+
+        let replicas = config
+            .get_mut("database")
+            .and_then(|database| database.get_mut("replicas"))
+            .unwrap()
+            .take();
+        assert_eq!(replicas.as_array().unwrap().len(), 2);
+        assert_eq!(config["database"]["replicas"], PklValue::Null);
+    }
+}
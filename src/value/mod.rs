@@ -1,8 +1,15 @@
+mod convert;
 pub mod datasize;
+pub mod duration;
+mod index;
+pub mod ser;
 
 #[allow(clippy::module_inception)] // exporting PklValue below
 pub mod value;
 
 pub use datasize::DataSize;
+pub use duration::{Duration, DurationUnit};
+pub use index::ValueIndex;
+pub use ser::{DataSizeFormat, DurationFormat, SerializeOptions};
 pub use value::IntSeq;
 pub use value::PklValue;
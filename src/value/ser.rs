@@ -0,0 +1,405 @@
+use std::fmt::Write as _;
+
+use serde::ser::{Serialize, SerializeMap, SerializeSeq, Serializer};
+
+use crate::{
+    internal::Integer,
+    pkl::ser::{Node, render_expr},
+    value::{DataSize, Duration, PklValue, value::MapImpl},
+};
+
+// Names of the newtype structs wrapping pkl specific values.
+// Serializers such as serde_json treat newtype structs as transparent,
+// but the pkl serializer uses these to write them back as pkl literals.
+pub(crate) const DURATION_TOKEN: &str = "$rpkl::private::Duration";
+pub(crate) const DATA_SIZE_TOKEN: &str = "$rpkl::private::DataSize";
+pub(crate) const REGEX_TOKEN: &str = "$rpkl::private::Regex";
+pub(crate) const PAIR_TOKEN: &str = "$rpkl::private::Pair";
+pub(crate) const INT_SEQ_TOKEN: &str = "$rpkl::private::IntSeq";
+pub(crate) const OBJECT_TOKEN: &str = "$rpkl::private::Object";
+pub(crate) const SET_TOKEN: &str = "$rpkl::private::Set";
+pub(crate) const LIST_TOKEN: &str = "$rpkl::private::List";
+pub(crate) const MAP_TOKEN: &str = "$rpkl::private::Map";
+
+/// How a `Duration` is written when serializing a [`PklValue`]
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub enum DurationFormat {
+    /// A pkl duration literal, e.g. `"5.min"`
+    #[default]
+    String,
+    /// An ISO-8601 duration, e.g. `"PT5M"` or `"-PT1.5S"`
+    Iso8601,
+    /// The total number of nanoseconds, negative for negative durations
+    Nanos,
+}
+
+/// How a `DataSize` is written when serializing a [`PklValue`]
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub enum DataSizeFormat {
+    /// A pkl data size literal, e.g. `"512.mb"`
+    #[default]
+    String,
+    /// The total number of bytes, as returned by [`DataSize::to_bytes`]
+    Bytes,
+}
+
+/// Options for serializing a [`PklValue`], see [`PklValue::serialize_with`].
+///
+/// The `Serialize` implementation of [`PklValue`] uses the default options.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct SerializeOptions {
+    pub duration: DurationFormat,
+    pub data_size: DataSizeFormat,
+}
+
+impl SerializeOptions {
+    pub fn duration(mut self, format: DurationFormat) -> Self {
+        self.duration = format;
+        self
+    }
+
+    pub fn data_size(mut self, format: DataSizeFormat) -> Self {
+        self.data_size = format;
+        self
+    }
+}
+
+/// A [`PklValue`] paired with the [`SerializeOptions`] to serialize it with.
+pub struct SerializeWith<'a> {
+    value: &'a PklValue,
+    options: SerializeOptions,
+}
+
+impl PklValue {
+    /// Serialize the value with the given options, e.g. to write durations as ISO-8601 strings.
+    ///
+    /// ```
+    /// use rpkl::value::{DurationFormat, SerializeOptions};
+    ///
+    /// let value = rpkl::Value::Duration(std::time::Duration::from_secs(90).into());
+    /// let options = SerializeOptions::default().duration(DurationFormat::Iso8601);
+    ///
+    /// assert_eq!(serde_json::to_string(&value.serialize_with(options)).unwrap(), r#""PT1M30S""#);
+    /// ```
+    pub fn serialize_with(&self, options: SerializeOptions) -> SerializeWith<'_> {
+        SerializeWith {
+            value: self,
+            options,
+        }
+    }
+}
+
+/// Maps and objects are written as maps, lists and sets as sequences and pairs as 2-tuples.
+/// Durations and data sizes are written as pkl literals (`"5.min"`, `"512.mb"`),
+/// use [`PklValue::serialize_with`] to choose a different format.
+impl Serialize for PklValue {
+    fn serialize<S>(&self, serializer: S) -> Result<S::Ok, S::Error>
+    where
+        S: Serializer,
+    {
+        self.serialize_with(SerializeOptions::default())
+            .serialize(serializer)
+    }
+}
+
+impl Serialize for SerializeWith<'_> {
+    fn serialize<S>(&self, serializer: S) -> Result<S::Ok, S::Error>
+    where
+        S: Serializer,
+    {
+        let options = self.options;
+        match self.value {
+            // rust maps and sequences are written as `Mapping` and `Listing` by default,
+            // `Map` and `List` are marked so the pkl serializer can tell them apart
+            PklValue::Map(map) => {
+                serializer.serialize_newtype_struct(MAP_TOKEN, &MapRepr { map, options })
+            }
+            PklValue::Mapping(map) => MapRepr { map, options }.serialize(serializer),
+            PklValue::Entries(entries) => {
+                serializer.serialize_newtype_struct(MAP_TOKEN, &EntriesRepr { entries, options })
+            }
+            PklValue::MappingEntries(entries) => {
+                EntriesRepr { entries, options }.serialize(serializer)
+            }
+            PklValue::Object { members, .. } => serializer.serialize_newtype_struct(
+                OBJECT_TOKEN,
+                &MapRepr {
+                    map: members,
+                    options,
+                },
+            ),
+            PklValue::List(items) => {
+                serializer.serialize_newtype_struct(LIST_TOKEN, &SeqRepr { items, options })
+            }
+            PklValue::Listing(items) => SeqRepr { items, options }.serialize(serializer),
+            PklValue::Set(items) => {
+                serializer.serialize_newtype_struct(SET_TOKEN, &SeqRepr { items, options })
+            }
+            PklValue::Regex(r) => serializer.serialize_newtype_struct(REGEX_TOKEN, r),
+            PklValue::String(s) => serializer.serialize_str(s),
+            PklValue::Int(Integer::Pos(i)) => serializer.serialize_u64(*i),
+            PklValue::Int(Integer::Neg(i)) => serializer.serialize_i64(*i),
+            PklValue::Int(Integer::Float(f)) => serializer.serialize_f64(*f),
+            PklValue::Boolean(b) => serializer.serialize_bool(*b),
+            PklValue::Duration(d) => serializer.serialize_newtype_struct(
+                DURATION_TOKEN,
+                &DurationRepr {
+                    duration: d,
+                    format: options.duration,
+                },
+            ),
+            PklValue::DataSize(ds) => serializer.serialize_newtype_struct(
+                DATA_SIZE_TOKEN,
+                &DataSizeRepr {
+                    data_size: ds,
+                    format: options.data_size,
+                },
+            ),
+            PklValue::Pair(a, b) => serializer.serialize_newtype_struct(
+                PAIR_TOKEN,
+                &(a.serialize_with(options), b.serialize_with(options)),
+            ),
+            PklValue::IntSeq(seq) => serializer.serialize_newtype_struct(INT_SEQ_TOKEN, seq),
+            PklValue::Bytes(b) => serializer.serialize_bytes(b),
+            PklValue::Null => serializer.serialize_none(),
+        }
+    }
+}
+
+struct MapRepr<'a> {
+    map: &'a MapImpl<String, PklValue>,
+    options: SerializeOptions,
+}
+
+impl Serialize for MapRepr<'_> {
+    fn serialize<S>(&self, serializer: S) -> Result<S::Ok, S::Error>
+    where
+        S: Serializer,
+    {
+        let mut s = serializer.serialize_map(Some(self.map.len()))?;
+        for (k, v) in self.map {
+            s.serialize_entry(k, &v.serialize_with(self.options))?;
+        }
+        s.end()
+    }
+}
+
+struct EntriesRepr<'a> {
+    entries: &'a [(PklValue, PklValue)],
+    options: SerializeOptions,
+}
+
+impl Serialize for EntriesRepr<'_> {
+    fn serialize<S>(&self, serializer: S) -> Result<S::Ok, S::Error>
+    where
+        S: Serializer,
+    {
+        let mut s = serializer.serialize_map(Some(self.entries.len()))?;
+        for (k, v) in self.entries {
+            s.serialize_entry(
+                &k.serialize_with(self.options),
+                &v.serialize_with(self.options),
+            )?;
+        }
+        s.end()
+    }
+}
+
+struct SeqRepr<'a> {
+    items: &'a [PklValue],
+    options: SerializeOptions,
+}
+
+impl Serialize for SeqRepr<'_> {
+    fn serialize<S>(&self, serializer: S) -> Result<S::Ok, S::Error>
+    where
+        S: Serializer,
+    {
+        let mut s = serializer.serialize_seq(Some(self.items.len()))?;
+        for item in self.items {
+            s.serialize_element(&item.serialize_with(self.options))?;
+        }
+        s.end()
+    }
+}
+
+struct DurationRepr<'a> {
+    duration: &'a Duration,
+    format: DurationFormat,
+}
+
+impl Serialize for DurationRepr<'_> {
+    fn serialize<S>(&self, serializer: S) -> Result<S::Ok, S::Error>
+    where
+        S: Serializer,
+    {
+        match (self.format, self.duration.as_nanos()) {
+            (DurationFormat::Iso8601, Some(nanos)) => serializer.serialize_str(&iso8601(nanos)),
+            (DurationFormat::Nanos, Some(nanos)) => match i64::try_from(nanos) {
+                Ok(nanos) => serializer.serialize_i64(nanos),
+                Err(_) => serializer.serialize_i128(nanos),
+            },
+            // `NaN` and infinite durations only have a pkl representation
+            (DurationFormat::String, _) | (_, None) => {
+                serializer.serialize_str(&render_expr(&Node::Duration(*self.duration)))
+            }
+        }
+    }
+}
+
+struct DataSizeRepr<'a> {
+    data_size: &'a DataSize,
+    format: DataSizeFormat,
+}
+
+impl Serialize for DataSizeRepr<'_> {
+    fn serialize<S>(&self, serializer: S) -> Result<S::Ok, S::Error>
+    where
+        S: Serializer,
+    {
+        match self.format {
+            DataSizeFormat::String => {
+                serializer.serialize_str(&render_expr(&Node::DataSize(*self.data_size)))
+            }
+            DataSizeFormat::Bytes => {
+                let bytes = self.data_size.to_bytes();
+                match u64::try_from(bytes) {
+                    Ok(bytes) => serializer.serialize_u64(bytes),
+                    Err(_) => serializer.serialize_u128(bytes),
+                }
+            }
+        }
+    }
+}
+
+/// Formats a number of nanoseconds as `PnDTnHnMnS`, omitting zero components, e.g. `PT1M30S` or `P1DT0.5S`.
+/// Negative durations are prefixed with `-`, e.g. `-PT5M`
+fn iso8601(nanos: i128) -> String {
+    let sign = if nanos < 0 { "-" } else { "" };
+    let total = nanos.unsigned_abs();
+    let secs = total / 1_000_000_000;
+    let nanos = total % 1_000_000_000;
+
+    let days = secs / 86_400;
+    let hours = secs % 86_400 / 3_600;
+    let minutes = secs % 3_600 / 60;
+    let seconds = secs % 60;
+
+    let mut out = format!("{sign}P");
+    if days > 0 {
+        let _ = write!(out, "{days}D");
+    }
+    if hours == 0 && minutes == 0 && seconds == 0 && nanos == 0 {
+        if days == 0 {
+            out.push_str("T0S");
+        }
+        return out;
+    }
+
+    out.push('T');
+    if hours > 0 {
+        let _ = write!(out, "{hours}H");
+    }
+    if minutes > 0 {
+        let _ = write!(out, "{minutes}M");
+    }
+    if seconds > 0 || nanos > 0 {
+        let _ = write!(out, "{seconds}");
+        if nanos > 0 {
+            let fraction = format!("{nanos:09}");
+            let _ = write!(out, ".{}", fraction.trim_end_matches('0'));
+        }
+        out.push('S');
+    }
+    out
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::value::{DurationUnit, datasize::DataSizeUnit};
+    use std::time::Duration as StdDuration;
+
+    #[test]
+    fn serialize_json() {
+        let value: PklValue =
+            serde_json::from_str(r#"{"name": "app", "ports": [80, -1, 0.5]}"#).unwrap();
+        let json = serde_json::to_value(&value).unwrap();
+
+        assert_eq!(
+            json,
+            serde_json::json!({"name": "app", "ports": [80, -1, 0.5]})
+        );
+    }
+
+    #[test]
+    fn serialize_pkl_types() {
+        let value = PklValue::List(vec![
+            PklValue::Duration(StdDuration::from_secs(300).into()),
+            PklValue::DataSize(DataSize::new(1.5, DataSizeUnit::Kilobytes)),
+            PklValue::Pair(
+                Box::new(PklValue::String("a".into())),
+                Box::new(PklValue::Null),
+            ),
+            PklValue::Regex("[a-z]+".into()),
+        ]);
+
+        assert_eq!(
+            serde_json::to_value(&value).unwrap(),
+            serde_json::json!(["5.min", "1.5.kb", ["a", null], "[a-z]+"])
+        );
+
+        let options = SerializeOptions::default()
+            .duration(DurationFormat::Nanos)
+            .data_size(DataSizeFormat::Bytes);
+        assert_eq!(
+            serde_json::to_value(value.serialize_with(options)).unwrap(),
+            serde_json::json!([300_000_000_000u64, 1500, ["a", null], "[a-z]+"])
+        );
+    }
+
+    #[test]
+    fn serialize_data_size_bytes_like_to_bytes() {
+        let options = SerializeOptions::default().data_size(DataSizeFormat::Bytes);
+        for size in [
+            DataSize::new(0.0015, DataSizeUnit::Kilobytes),
+            DataSize::new(-1.0, DataSizeUnit::Megabytes),
+            DataSize::new(2.5, DataSizeUnit::Bytes),
+        ] {
+            assert_eq!(
+                serde_json::to_value(PklValue::DataSize(size).serialize_with(options)).unwrap(),
+                serde_json::json!(size.to_bytes() as u64)
+            );
+        }
+    }
+
+    #[test]
+    fn iso8601_durations() {
+        let cases = [
+            (0, "PT0S"),
+            (90_000_000_000, "PT1M30S"),
+            (500_000_000, "PT0.5S"),
+            (86_400_000_000_000, "P1D"),
+            (90_000_000_000_000, "P1DT1H"),
+            (1, "PT0.000000001S"),
+            (-300_000_000_000, "-PT5M"),
+        ];
+
+        for (nanos, expected) in cases {
+            assert_eq!(iso8601(nanos), expected);
+        }
+    }
+
+    #[test]
+    fn serialize_negative_duration() {
+        let value = PklValue::Duration(Duration::new(-1.5, DurationUnit::Seconds));
+
+        assert_eq!(serde_json::to_value(&value).unwrap(), "-1.5.s");
+
+        let options = SerializeOptions::default().duration(DurationFormat::Nanos);
+        assert_eq!(
+            serde_json::to_value(value.serialize_with(options)).unwrap(),
+            serde_json::json!(-1_500_000_000i64)
+        );
+    }
+}
@@ -13,16 +13,41 @@ pub type MapImpl<K, V> = IndexMap<K, V>;
 pub type MapImpl<K, V> = HashMap<K, V>;
 
 /// Represents a `.pkl` value
-#[derive(Debug, Clone, Serialize, PartialEq)]
+///
+/// Implements `Serialize` so it can be exported with any serde format, see [`PklValue::serialize_with`].
+#[derive(Debug, Clone, PartialEq)]
 pub enum PklValue {
+    /// A `Map` with string keys
     Map(MapImpl<String, PklValue>),
+    /// A `Map` with keys of any other type, e.g. `Map<Int, String>`.
+    ///
+    /// Entries are kept in the order pkl sent them.
+    Entries(Vec<(PklValue, PklValue)>),
+    /// A `Mapping` with string keys
+    Mapping(MapImpl<String, PklValue>),
+    /// A `Mapping` with keys of any other type, e.g. `Mapping<Int, String>`.
+    ///
+    /// Entries are kept in the order pkl sent them.
+    MappingEntries(Vec<(PklValue, PklValue)>),
+    /// An object, e.g. an instance of a class or a `Dynamic`
+    Object {
+        /// Fully qualified name of the class, e.g. `config#Database` or `pkl.base#Dynamic`
+        class_name: String,
+        /// URI of the module the class is defined in
+        module_uri: String,
+        members: MapImpl<String, PklValue>,
+    },
+    /// A `List`
     List(Vec<PklValue>),
+    /// A `Listing`
+    Listing(Vec<PklValue>),
+    Set(Vec<PklValue>),
     /// Represents a regex string
     Regex(String),
     String(String),
     Int(Integer),
     Boolean(bool),
-    Duration(std::time::Duration),
+    Duration(crate::value::Duration),
 
     Pair(Box<PklValue>, Box<PklValue>), // needs indirection
 
@@ -33,16 +58,79 @@ pub enum PklValue {
 }
 
 impl PklValue {
+    /// The name of the pkl type this value was decoded from, e.g. `String` or `Duration`
+    pub fn type_name(&self) -> &'static str {
+        match self {
+            PklValue::Map(_) | PklValue::Entries(_) => "Map",
+            PklValue::Mapping(_) | PklValue::MappingEntries(_) => "Mapping",
+            PklValue::Object { .. } => "Object",
+            PklValue::List(_) => "List",
+            PklValue::Listing(_) => "Listing",
+            PklValue::Set(_) => "Set",
+            PklValue::Regex(_) => "Regex",
+            PklValue::String(_) => "String",
+            PklValue::Int(Integer::Float(_)) => "Float",
+            PklValue::Int(_) => "Int",
+            PklValue::Boolean(_) => "Boolean",
+            PklValue::Duration(_) => "Duration",
+            PklValue::Pair(_, _) => "Pair",
+            PklValue::IntSeq(_) => "IntSeq",
+            PklValue::DataSize(_) => "DataSize",
+            PklValue::Bytes(_) => "Bytes",
+            PklValue::Null => "Null",
+        }
+    }
+
+    /// Renders the value as a pkl expression, e.g. `new { port = 8080 }`, `5.min` or `"text"`
+    pub fn to_pkl_string(&self) -> String {
+        crate::pkl::ser::render_expr(&(self.into()))
+    }
+
+    /// Returns the entries of a map or mapping, or the members of an object
     pub fn as_map(&self) -> Option<&MapImpl<String, PklValue>> {
         match self {
-            PklValue::Map(m) => Some(m),
+            PklValue::Map(m) | PklValue::Mapping(m) | PklValue::Object { members: m, .. } => {
+                Some(m)
+            }
+            _ => None,
+        }
+    }
+
+    /// Returns the entries of a map or mapping with non-string keys
+    pub fn as_entries(&self) -> Option<&[(PklValue, PklValue)]> {
+        match self {
+            PklValue::Entries(entries) | PklValue::MappingEntries(entries) => Some(entries),
+            _ => None,
+        }
+    }
+
+    /// The fully qualified class name of an object, e.g. `config#Database`
+    pub fn class_name(&self) -> Option<&str> {
+        match self {
+            PklValue::Object { class_name, .. } => Some(class_name),
             _ => None,
         }
     }
 
+    /// The URI of the module that defines the class of an object
+    pub fn module_uri(&self) -> Option<&str> {
+        match self {
+            PklValue::Object { module_uri, .. } => Some(module_uri),
+            _ => None,
+        }
+    }
+
+    /// Returns the elements of a list, listing or set
     pub fn as_array(&self) -> Option<&Vec<PklValue>> {
         match self {
-            PklValue::List(l) => Some(l),
+            PklValue::List(l) | PklValue::Listing(l) | PklValue::Set(l) => Some(l),
+            _ => None,
+        }
+    }
+
+    pub fn as_set(&self) -> Option<&Vec<PklValue>> {
+        match self {
+            PklValue::Set(s) => Some(s),
             _ => None,
         }
     }
@@ -54,6 +142,70 @@ impl PklValue {
         }
     }
 
+    /// Returns an `Int` that fits in an `i64`
+    pub fn as_i64(&self) -> Option<i64> {
+        match self {
+            PklValue::Int(Integer::Pos(n)) => i64::try_from(*n).ok(),
+            PklValue::Int(Integer::Neg(n)) => Some(*n),
+            _ => None,
+        }
+    }
+
+    /// Returns an `Int` that fits in a `u64`
+    pub fn as_u64(&self) -> Option<u64> {
+        match self {
+            PklValue::Int(Integer::Pos(n)) => Some(*n),
+            PklValue::Int(Integer::Neg(n)) => u64::try_from(*n).ok(),
+            _ => None,
+        }
+    }
+
+    /// Returns a `Float`, or an `Int` converted to `f64`
+    pub fn as_f64(&self) -> Option<f64> {
+        match self {
+            PklValue::Int(Integer::Pos(n)) => Some(*n as f64),
+            PklValue::Int(Integer::Neg(n)) => Some(*n as f64),
+            PklValue::Int(Integer::Float(f)) => Some(*f),
+            _ => None,
+        }
+    }
+
+    pub fn as_duration(&self) -> Option<&crate::value::Duration> {
+        match self {
+            PklValue::Duration(d) => Some(d),
+            _ => None,
+        }
+    }
+
+    pub fn as_data_size(&self) -> Option<&DataSize> {
+        match self {
+            PklValue::DataSize(ds) => Some(ds),
+            _ => None,
+        }
+    }
+
+    pub fn as_pair(&self) -> Option<(&PklValue, &PklValue)> {
+        match self {
+            PklValue::Pair(first, second) => Some((first, second)),
+            _ => None,
+        }
+    }
+
+    /// Returns the pattern of a `Regex`
+    pub fn as_regex(&self) -> Option<&str> {
+        match self {
+            PklValue::Regex(pattern) => Some(pattern),
+            _ => None,
+        }
+    }
+
+    pub fn as_int_seq(&self) -> Option<&IntSeq> {
+        match self {
+            PklValue::IntSeq(seq) => Some(seq),
+            _ => None,
+        }
+    }
+
     pub fn as_bool(&self) -> Option<&bool> {
         match self {
             PklValue::Boolean(b) => Some(b),
@@ -99,17 +251,45 @@ impl PklValue {
         matches!(self, PklValue::Boolean(_))
     }
 
+    /// Returns true for maps, mappings and objects, see [`PklValue::as_map`]
     pub fn is_map(&self) -> bool {
-        matches!(self, PklValue::Map(_))
+        matches!(
+            self,
+            PklValue::Map(_) | PklValue::Mapping(_) | PklValue::Object { .. }
+        )
     }
 
+    pub fn is_object(&self) -> bool {
+        matches!(self, PklValue::Object { .. })
+    }
+
+    /// Returns true for lists, listings and sets, see [`PklValue::as_array`]
     pub fn is_array(&self) -> bool {
-        matches!(self, PklValue::List(_))
+        matches!(
+            self,
+            PklValue::List(_) | PklValue::Listing(_) | PklValue::Set(_)
+        )
+    }
+
+    pub fn is_set(&self) -> bool {
+        matches!(self, PklValue::Set(_))
     }
 
     pub fn is_bytes(&self) -> bool {
         matches!(self, PklValue::Bytes(_))
     }
+
+    pub fn is_null(&self) -> bool {
+        matches!(self, PklValue::Null)
+    }
+
+    pub fn is_duration(&self) -> bool {
+        matches!(self, PklValue::Duration(_))
+    }
+
+    pub fn is_data_size(&self) -> bool {
+        matches!(self, PklValue::DataSize(_))
+    }
 }
 
 /// 64-bit signed integer range <https://pkl-lang.org/package-docs/pkl/0.26.1/base/IntSeq>
@@ -0,0 +1,664 @@
+//! Hot-reloading of pkl configs.
+//!
+//! A [`ConfigWatcher`] evaluates a module into `T`, watches the files it imports and the files read through
+//! its client readers, and re-evaluates it when any of them change.
+//!
+//! # Example
+//!
+//! ```no_run
+//! use serde::Deserialize;
+//! use rpkl::watch::{ConfigWatcher, WatchEvent};
+//!
+//! #[derive(Deserialize)]
+//! struct Config {
+//!     port: u16,
+//! }
+//!
+//! # fn main() -> Result<(), rpkl::Error> {
+//! let watcher = ConfigWatcher::<Config>::new("config.pkl")?;
+//! println!("listening on {}", watcher.get().port);
+//!
+//! for event in watcher.subscribe() {
+//!     match event {
+//!         WatchEvent::Reloaded(config) => println!("listening on {}", config.port),
+//!         WatchEvent::Failed(e) => eprintln!("keeping the previous config: {e}"),
+//!     }
+//! }
+//! #    Ok(())
+//! # }
+//! ```
+
+use std::{
+    cell::RefCell,
+    collections::HashSet,
+    path::{Path, PathBuf},
+    rc::Rc,
+    sync::{
+        Arc, Mutex, RwLock,
+        mpsc::{self, Receiver, RecvTimeoutError, Sender},
+    },
+    thread::JoinHandle,
+    time::Duration,
+};
+
+use notify::{
+    Event, EventKind, RecommendedWatcher, RecursiveMode, Watcher as _,
+    event::{MetadataKind, ModifyKind},
+};
+use serde::{Deserialize, de::DeserializeOwned};
+
+use crate::{
+    EvaluatorOptions,
+    api::{
+        EvaluatorManager, PklVersion,
+        evaluator::{Evaluator, module_uri},
+        reader::{PathElements, PklModuleReader, PklResourceReader},
+    },
+    error::{Error, Result},
+    utils::macros::_warn,
+};
+
+/// Options for a [`ConfigWatcher`]
+pub struct WatchOptions {
+    /// Creates the options of the evaluator used for each evaluation of the module.
+    /// A new evaluator is created for every reload, so that the modules it imports aren't cached.
+    pub evaluator_options: Option<Box<dyn Fn() -> EvaluatorOptions + Send>>,
+
+    /// How long the watched files have to be unchanged before the module is re-evaluated
+    pub debounce: Duration,
+
+    /// Additional files to watch, e.g. files read by an external reader
+    pub paths: Vec<PathBuf>,
+}
+
+impl Default for WatchOptions {
+    fn default() -> Self {
+        WatchOptions {
+            evaluator_options: None,
+            debounce: Duration::from_millis(200),
+            paths: vec![],
+        }
+    }
+}
+
+impl WatchOptions {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Sets the function creating the evaluator options.
+    ///
+    /// It's called on the watcher's thread before every evaluation, so the options can hold readers that aren't `Send`.
+    pub fn evaluator_options(
+        mut self,
+        options: impl Fn() -> EvaluatorOptions + Send + 'static,
+    ) -> Self {
+        self.evaluator_options = Some(Box::new(options));
+        self
+    }
+
+    /// Sets how long the watched files have to be unchanged before the module is re-evaluated, 200ms by default
+    pub fn debounce(mut self, debounce: Duration) -> Self {
+        self.debounce = debounce;
+        self
+    }
+
+    /// Watches an additional file
+    pub fn watch_path(mut self, path: impl Into<PathBuf>) -> Self {
+        self.paths.push(path.into());
+        self
+    }
+}
+
+/// The outcome of re-evaluating a watched module
+#[derive(Debug)]
+pub enum WatchEvent<T> {
+    /// The module was re-evaluated, [`ConfigWatcher::get`] now returns this value
+    Reloaded(Arc<T>),
+    /// The module failed to evaluate or deserialize, [`ConfigWatcher::get`] keeps returning the last good value
+    Failed(Arc<Error>),
+}
+
+impl<T> Clone for WatchEvent<T> {
+    fn clone(&self) -> Self {
+        match self {
+            WatchEvent::Reloaded(value) => WatchEvent::Reloaded(value.clone()),
+            WatchEvent::Failed(e) => WatchEvent::Failed(e.clone()),
+        }
+    }
+}
+
+enum Signal {
+    Changed,
+    Stop,
+}
+
+/// State shared between a [`ConfigWatcher`] and its thread
+struct Shared<T> {
+    current: RwLock<Arc<T>>,
+    subscribers: Mutex<Vec<Sender<WatchEvent<T>>>>,
+}
+
+/// Evaluates a pkl module into `T` and re-evaluates it whenever the files it depends on change.
+///
+/// The module, the modules it imports and any file read through a client reader are watched.
+/// Imports are found with `pkl:analyze`, which requires pkl 0.27 or newer; on older versions only the module itself
+/// and the files read through client readers are watched. Files read with `read("file:...")` aren't tracked,
+/// add them with [`WatchOptions::watch_path`].
+///
+/// Evaluation runs on a dedicated thread, which is stopped when the watcher is dropped.
+pub struct ConfigWatcher<T> {
+    shared: Arc<Shared<T>>,
+    files: Arc<Mutex<HashSet<PathBuf>>>,
+    signals: Sender<Signal>,
+    thread: Option<JoinHandle<()>>,
+}
+
+impl<T> ConfigWatcher<T>
+where
+    T: DeserializeOwned + Send + Sync + 'static,
+{
+    /// Evaluates the module at `path` and starts watching it with the default options
+    /// # Errors
+    /// - Returns an error if the pkl process fails to start or the first evaluation fails
+    pub fn new(path: impl AsRef<Path>) -> Result<Self> {
+        Self::new_from_options(path, WatchOptions::default())
+    }
+
+    /// Evaluates the module at `path` and starts watching it
+    /// # Errors
+    /// - Returns an error if the pkl process fails to start or the first evaluation fails
+    pub fn new_from_options(path: impl AsRef<Path>, options: WatchOptions) -> Result<Self> {
+        let module = std::fs::canonicalize(path).map_err(Error::Io)?;
+        let (signals, signal_rx) = mpsc::channel();
+        let (ready, ready_rx) = mpsc::sync_channel(1);
+        let files = Arc::new(Mutex::new(HashSet::new()));
+
+        let thread = std::thread::Builder::new()
+            .name("rpkl-watch".into())
+            .spawn({
+                let signals = signals.clone();
+                let files = files.clone();
+                move || run(module, options, files, signals, &signal_rx, &ready)
+            })
+            .map_err(Error::Io)?;
+
+        match ready_rx.recv() {
+            Ok(Ok(shared)) => Ok(ConfigWatcher {
+                shared,
+                files,
+                signals,
+                thread: Some(thread),
+            }),
+            Ok(Err(e)) => {
+                let _ = thread.join();
+                Err(e)
+            }
+            Err(_) => Err(Error::Message(
+                "the watcher thread exited unexpectedly".into(),
+            )),
+        }
+    }
+}
+
+impl<T> ConfigWatcher<T> {
+    /// The last value the module evaluated to successfully
+    pub fn get(&self) -> Arc<T> {
+        self.shared.current.read().unwrap().clone()
+    }
+
+    /// Receives an event each time the module is re-evaluated
+    pub fn subscribe(&self) -> Receiver<WatchEvent<T>> {
+        let (sender, receiver) = mpsc::channel();
+        self.shared.subscribers.lock().unwrap().push(sender);
+        receiver
+    }
+
+    /// Re-evaluates the module, as if one of its files changed
+    pub fn reload(&self) {
+        let _ = self.signals.send(Signal::Changed);
+    }
+
+    /// The files currently being watched
+    pub fn watched_files(&self) -> Vec<PathBuf> {
+        let mut files: Vec<_> = self.files.lock().unwrap().iter().cloned().collect();
+        files.sort();
+        files
+    }
+}
+
+impl<T> Drop for ConfigWatcher<T> {
+    fn drop(&mut self) {
+        let _ = self.signals.send(Signal::Stop);
+        if let Some(thread) = self.thread.take() {
+            let _ = thread.join();
+        }
+    }
+}
+
+/// Body of the watcher thread
+fn run<T>(
+    module: PathBuf,
+    options: WatchOptions,
+    files: Arc<Mutex<HashSet<PathBuf>>>,
+    signals: Sender<Signal>,
+    signal_rx: &Receiver<Signal>,
+    ready: &mpsc::SyncSender<Result<Arc<Shared<T>>>>,
+) where
+    T: DeserializeOwned + Send + Sync + 'static,
+{
+    let WatchOptions {
+        evaluator_options,
+        debounce,
+        paths,
+    } = options;
+    let make_options = || {
+        evaluator_options
+            .as_ref()
+            .map_or_else(EvaluatorOptions::default, |f| f())
+    };
+
+    let first = make_options();
+    let initial = Reloader::new(module, paths, first.pkl_command.clone(), files, signals)
+        .and_then(|mut reloader| Ok((reloader.evaluate::<T>(first)?, reloader)));
+    let (value, mut reloader) = match initial {
+        Ok(initial) => initial,
+        Err(e) => {
+            let _ = ready.send(Err(e));
+            return;
+        }
+    };
+
+    let shared = Arc::new(Shared {
+        current: RwLock::new(Arc::new(value)),
+        subscribers: Mutex::new(vec![]),
+    });
+    if ready.send(Ok(shared.clone())).is_err() {
+        return;
+    }
+
+    while let Ok(Signal::Changed) = signal_rx.recv() {
+        if !settle(signal_rx, debounce) {
+            return;
+        }
+
+        let event = match reloader.evaluate::<T>(make_options()) {
+            Ok(value) => {
+                let value = Arc::new(value);
+                *shared.current.write().unwrap() = value.clone();
+                WatchEvent::Reloaded(value)
+            }
+            Err(e) => {
+                _warn!("failed to reload {}: {e}", reloader.module.display());
+                WatchEvent::Failed(Arc::new(e))
+            }
+        };
+        shared
+            .subscribers
+            .lock()
+            .unwrap()
+            .retain(|subscriber| subscriber.send(event.clone()).is_ok());
+    }
+}
+
+/// Waits until no change has been signalled for `debounce`.
+/// Returns `false` if the watcher was stopped in the meantime.
+fn settle(signals: &Receiver<Signal>, debounce: Duration) -> bool {
+    loop {
+        match signals.recv_timeout(debounce) {
+            Ok(Signal::Changed) => {}
+            Ok(Signal::Stop) | Err(RecvTimeoutError::Disconnected) => return false,
+            Err(RecvTimeoutError::Timeout) => return true,
+        }
+    }
+}
+
+/// Evaluates the module and keeps the file watcher in sync with the files it depends on
+struct Reloader {
+    module: PathBuf,
+    module_uri: String,
+    manager: EvaluatorManager,
+    analyze_imports: bool,
+    paths: Vec<PathBuf>,
+    files: Arc<Mutex<HashSet<PathBuf>>>,
+    dirs: HashSet<PathBuf>,
+    watcher: RecommendedWatcher,
+}
+
+impl Reloader {
+    fn new(
+        module: PathBuf,
+        paths: Vec<PathBuf>,
+        command: Option<crate::api::PklCommand>,
+        files: Arc<Mutex<HashSet<PathBuf>>>,
+        signals: Sender<Signal>,
+    ) -> Result<Self> {
+        let module_uri = module_uri(&module)?;
+        let manager = EvaluatorManager::with_command(command.unwrap_or_default())?;
+
+        let analyze_imports = manager
+            .pkl_version()?
+            .is_none_or(|version| version >= PklVersion::IMPORT_GRAPH);
+        if !analyze_imports {
+            _warn!(
+                "listing imports requires pkl {}, only {} itself is watched",
+                PklVersion::IMPORT_GRAPH,
+                module.display()
+            );
+        }
+
+        let watcher = notify::recommended_watcher({
+            let files = files.clone();
+            move |event: notify::Result<Event>| match event {
+                Ok(event) if is_change(&event, &files.lock().unwrap()) => {
+                    let _ = signals.send(Signal::Changed);
+                }
+                Ok(_) => {}
+                Err(_e) => {
+                    _warn!("file watcher error: {_e}");
+                }
+            }
+        })?;
+
+        Ok(Reloader {
+            module,
+            module_uri,
+            manager,
+            analyze_imports,
+            paths: paths
+                .iter()
+                .map(|path| canonical_path(path))
+                .collect::<std::io::Result<_>>()?,
+            files,
+            dirs: HashSet::new(),
+            watcher,
+        })
+    }
+
+    /// Evaluates the module on a new evaluator, then watches the files it used
+    fn evaluate<T: DeserializeOwned>(&mut self, options: EvaluatorOptions) -> Result<T> {
+        let reads = Rc::new(RefCell::new(vec![]));
+        let mut evaluator = self.manager.new_evaluator(track_reads(options, &reads))?;
+        let result = evaluator
+            .evaluate_uri(self.module_uri.as_str())
+            .and_then(crate::deserialize_module);
+
+        let mut files: HashSet<PathBuf> = self.paths.iter().cloned().collect();
+        files.insert(self.module.clone());
+        match self.imports(&mut evaluator) {
+            Ok(modules) => files.extend(modules.iter().filter_map(|uri| uri_to_path(uri))),
+            Err(_e) => {
+                // e.g. a syntax error in an import, keep watching everything that was watched before
+                _warn!("failed to list the imports of {}: {_e}", self.module_uri);
+                files.extend(self.files.lock().unwrap().iter().cloned());
+            }
+        }
+        files.extend(reads.borrow().iter().filter_map(|uri| uri_to_path(uri)));
+        self.watch(files);
+
+        result
+    }
+
+    /// URIs of the module and all the modules it imports, directly or transitively
+    fn imports(&self, evaluator: &mut Evaluator) -> Result<Vec<String>> {
+        #[derive(Deserialize)]
+        struct ImportGraph {
+            modules: Vec<String>,
+        }
+
+        if !self.analyze_imports {
+            return Ok(vec![]);
+        }
+        let source = format!(
+            "import \"pkl:analyze\"\n\nmodules = analyze.importGraph(Set(\"{}\")).imports.keys.toList()\n",
+            self.module_uri.replace('\\', "\\\\").replace('"', "\\\"")
+        );
+        let pkl_mod = evaluator.evaluate_text(source, "repl:watch")?;
+        crate::deserialize_module::<ImportGraph>(pkl_mod).map(|graph| graph.modules)
+    }
+
+    /// Watches the directories containing `files`, so files replaced by editors keep being watched
+    fn watch(&mut self, files: HashSet<PathBuf>) {
+        let dirs: HashSet<PathBuf> = files
+            .iter()
+            .filter_map(|file| file.parent().map(Path::to_path_buf))
+            .collect();
+
+        for dir in self.dirs.difference(&dirs) {
+            let _ = self.watcher.unwatch(dir);
+        }
+        for dir in dirs.difference(&self.dirs) {
+            if let Err(_e) = self.watcher.watch(dir, RecursiveMode::NonRecursive) {
+                _warn!("failed to watch {}: {_e}", dir.display());
+            }
+        }
+
+        self.dirs = dirs;
+        *self.files.lock().unwrap() = files;
+    }
+}
+
+/// The path notify reports for `path`, with symlinks resolved.
+/// A file that doesn't exist yet is resolved through its directory, so it's noticed once it's created.
+fn canonical_path(path: &Path) -> std::io::Result<PathBuf> {
+    if let Ok(path) = std::fs::canonicalize(path) {
+        return Ok(path);
+    }
+    let path = std::path::absolute(path)?;
+    match (path.parent(), path.file_name()) {
+        (Some(dir), Some(name)) => Ok(std::fs::canonicalize(dir)?.join(name)),
+        _ => Ok(path),
+    }
+}
+
+/// Whether `event` changed the contents of one of the watched `files`
+fn is_change(event: &Event, files: &HashSet<PathBuf>) -> bool {
+    let changed = match event.kind {
+        EventKind::Modify(ModifyKind::Metadata(MetadataKind::AccessTime)) => false,
+        EventKind::Create(_) | EventKind::Modify(_) | EventKind::Remove(_) => true,
+        _ => false,
+    };
+    changed && event.paths.iter().any(|path| files.contains(path))
+}
+
+/// The file a module or resource URI refers to.
+///
+/// `file:` URIs always map to a path. Other schemes, e.g. those of client readers, only do if their path part
+/// is an absolute path to an existing file, like `config:/etc/app/secrets.json`.
+fn uri_to_path(uri: &str) -> Option<PathBuf> {
+    let (scheme, rest) = uri.split_once(':')?;
+    let rest = rest.split(['?', '#']).next()?;
+    let path = match rest.strip_prefix("//") {
+        Some(rest) => rest,
+        None => rest,
+    };
+    if !path.starts_with('/') {
+        return None;
+    }
+    let path = percent_decode(path);
+    // `/C:/dir/config.pkl` on windows
+    #[cfg(target_os = "windows")]
+    let path = match path.strip_prefix('/') {
+        Some(rest) if rest.get(1..2) == Some(":") => rest.to_string(),
+        _ => path,
+    };
+
+    let path = PathBuf::from(path);
+    (scheme == "file" || path.is_file()).then_some(path)
+}
+
+fn percent_decode(s: &str) -> String {
+    let bytes = s.as_bytes();
+    let mut out = Vec::with_capacity(bytes.len());
+    let mut i = 0;
+    while i < bytes.len() {
+        if bytes[i] == b'%'
+            && let Some(byte) = s
+                .get(i + 1..i + 3)
+                .and_then(|hex| u8::from_str_radix(hex, 16).ok())
+        {
+            out.push(byte);
+            i += 3;
+        } else {
+            out.push(bytes[i]);
+            i += 1;
+        }
+    }
+    String::from_utf8_lossy(&out).into_owned()
+}
+
+/// Wraps the client readers of `options` to record the URIs they're asked to read
+#[allow(clippy::arc_with_non_send_sync)] // the readers are only used on the watcher thread
+fn track_reads(
+    mut options: EvaluatorOptions,
+    reads: &Rc<RefCell<Vec<String>>>,
+) -> EvaluatorOptions {
+    if let Some(readers) = options.client_module_readers.as_mut() {
+        for reader in readers.iter_mut() {
+            *reader = Arc::new(Tracked {
+                inner: reader.clone(),
+                reads: reads.clone(),
+            });
+        }
+    }
+    if let Some(readers) = options.client_resource_readers.as_mut() {
+        for reader in readers.iter_mut() {
+            *reader = Arc::new(Tracked {
+                inner: reader.clone(),
+                reads: reads.clone(),
+            });
+        }
+    }
+    options
+}
+
+/// A client reader recording the URIs it reads
+struct Tracked<R: ?Sized> {
+    inner: Arc<R>,
+    reads: Rc<RefCell<Vec<String>>>,
+}
+
+impl PklModuleReader for Tracked<dyn PklModuleReader> {
+    fn scheme(&self) -> &str {
+        self.inner.scheme()
+    }
+
+    fn has_hierarchical_uris(&self) -> bool {
+        self.inner.has_hierarchical_uris()
+    }
+
+    fn is_globbable(&self) -> bool {
+        self.inner.is_globbable()
+    }
+
+    fn is_local(&self) -> bool {
+        self.inner.is_local()
+    }
+
+    fn read(&self, uri: &str) -> std::result::Result<String, Box<dyn std::error::Error>> {
+        self.reads.borrow_mut().push(uri.to_string());
+        self.inner.read(uri)
+    }
+
+    fn list(
+        &self,
+        uri: &str,
+    ) -> std::result::Result<Vec<PathElements>, Box<dyn std::error::Error>> {
+        self.inner.list(uri)
+    }
+}
+
+impl PklResourceReader for Tracked<dyn PklResourceReader> {
+    fn scheme(&self) -> &str {
+        self.inner.scheme()
+    }
+
+    fn has_hierarchical_uris(&self) -> bool {
+        self.inner.has_hierarchical_uris()
+    }
+
+    fn is_globbable(&self) -> bool {
+        self.inner.is_globbable()
+    }
+
+    fn read(&self, uri: &str) -> std::result::Result<Vec<u8>, Box<dyn std::error::Error>> {
+        self.reads.borrow_mut().push(uri.to_string());
+        self.inner.read(uri)
+    }
+
+    fn list(
+        &self,
+        uri: &str,
+    ) -> std::result::Result<Vec<PathElements>, Box<dyn std::error::Error>> {
+        self.inner.list(uri)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use notify::event::{AccessKind, CreateKind, DataChange};
+
+    #[test]
+    fn file_uris() {
+        assert_eq!(
+            uri_to_path("file:///etc/app/config.pkl"),
+            Some(PathBuf::from("/etc/app/config.pkl"))
+        );
+        assert_eq!(
+            uri_to_path("file:///etc/my%20app/config.pkl"),
+            Some(PathBuf::from("/etc/my app/config.pkl"))
+        );
+        assert_eq!(uri_to_path("pkl:base"), None);
+        assert_eq!(uri_to_path("https://example.com/config.pkl"), None);
+
+        let manifest = concat!(env!("CARGO_MANIFEST_DIR"), "/Cargo.toml");
+        assert_eq!(
+            uri_to_path(&format!("custom:{manifest}")),
+            Some(PathBuf::from(manifest))
+        );
+        assert_eq!(uri_to_path("custom:/does/not/exist.pkl"), None);
+    }
+
+    #[test]
+    fn only_content_changes_of_watched_files() {
+        let files = HashSet::from([PathBuf::from("/app/config.pkl")]);
+        let event = |kind, path: &str| Event::new(kind).add_path(PathBuf::from(path));
+
+        assert!(is_change(
+            &event(
+                EventKind::Modify(ModifyKind::Data(DataChange::Content)),
+                "/app/config.pkl"
+            ),
+            &files
+        ));
+        assert!(is_change(
+            &event(EventKind::Create(CreateKind::File), "/app/config.pkl"),
+            &files
+        ));
+        assert!(!is_change(
+            &event(EventKind::Access(AccessKind::Read), "/app/config.pkl"),
+            &files
+        ));
+        assert!(!is_change(
+            &event(
+                EventKind::Modify(ModifyKind::Data(DataChange::Content)),
+                "/app/other.txt"
+            ),
+            &files
+        ));
+    }
+
+    #[test]
+    fn settle_waits_for_quiet() {
+        let (signals, receiver) = mpsc::channel();
+        signals.send(Signal::Changed).unwrap();
+        signals.send(Signal::Changed).unwrap();
+        assert!(settle(&receiver, Duration::from_millis(10)));
+        assert!(receiver.try_recv().is_err());
+
+        signals.send(Signal::Changed).unwrap();
+        signals.send(Signal::Stop).unwrap();
+        assert!(!settle(&receiver, Duration::from_millis(10)));
+    }
+}
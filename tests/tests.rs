@@ -3,11 +3,12 @@ mod utils;
 #[cfg(test)]
 mod tests {
 
+    use std::collections::HashMap;
     use std::path::PathBuf;
 
     use rpkl::api::evaluator::EvaluatorOptions;
     use rpkl::{HttpOptions, HttpProxy};
-    use serde::Deserialize;
+    use serde::{Deserialize, Serialize};
 
     #[test]
     fn optional_values() {
@@ -59,6 +60,486 @@ mod tests {
         assert_eq!(config.name, "zjxy");
     }
 
+    #[test]
+    fn restricted_resources() {
+        let path = PathBuf::from(env!("CARGO_MANIFEST_DIR"))
+            .join("tests")
+            .join("pkl")
+            .join("allowed-resources.pkl");
+
+        // `env:` isn't allowed, so reading `env:PATH` fails
+        let options = EvaluatorOptions::default()
+            .properties([("name", "zjxy")])
+            .allowed_modules(["pkl:", "file:"])
+            .allowed_resources(["prop:"]);
+
+        let mut evaluator = rpkl::api::Evaluator::new_from_options(options).unwrap();
+        let err = evaluator.evaluate_module(path).unwrap_err();
+        assert!(matches!(err, rpkl::Error::PklServerError(_)));
+    }
+
+    #[test]
+    fn env_vars() {
+        use rpkl::pkl::IntoPklMap;
+
+        let options = EvaluatorOptions::default()
+            .inherit_env(false)
+            .env_var("APP_ENV", "test");
+        let mut evaluator = rpkl::api::Evaluator::new_from_options(options).unwrap();
+        let env = evaluator
+            .evaluate_text(
+                r#"
+                app_env = read("env:APP_ENV")
+                path = read?("env:PATH")
+                "#,
+                "repl:text",
+            )
+            .unwrap()
+            .into_pkl_map();
+
+        assert_eq!(env["app_env"].as_str(), Some("test"));
+        assert_eq!(env["path"], rpkl::Value::Null);
+    }
+
+    #[test]
+    fn from_str() {
+        #[derive(Debug, Deserialize)]
+        struct Config {
+            ip: String,
+            port: u16,
+        }
+
+        let config = rpkl::from_str::<Config>(
+            r#"
+            ip = "127.0.0.1"
+            port = 8000 + 80
+            "#,
+        )
+        .unwrap();
+
+        assert_eq!(config.ip, "127.0.0.1");
+        assert_eq!(config.port, 8080);
+    }
+
+    #[test]
+    fn to_string_round_trip() {
+        #[derive(Debug, PartialEq, Serialize, Deserialize)]
+        struct Config {
+            ip: String,
+            timeout: std::time::Duration,
+            tags: Vec<String>,
+            limits: HashMap<String, i64>,
+            database: Database,
+        }
+
+        #[derive(Debug, PartialEq, Serialize, Deserialize)]
+        struct Database {
+            username: String,
+            #[serde(rename = "class")]
+            class_name: Option<String>,
+        }
+
+        let config = Config {
+            ip: "127.0.0.1".into(),
+            timeout: std::time::Duration::from_millis(1500),
+            tags: vec!["web".into(), "quote\"d".into()],
+            limits: HashMap::from([("*".into(), -1)]),
+            database: Database {
+                username: "admin".into(),
+                class_name: None,
+            },
+        };
+
+        let source = rpkl::to_string(&config).unwrap();
+        let decoded = rpkl::from_str::<Config>(&source).unwrap();
+
+        assert_eq!(decoded, config);
+    }
+
+    #[test]
+    fn enums_from_class_names() {
+        #[derive(Debug, PartialEq, Deserialize)]
+        #[allow(clippy::enum_variant_names)]
+        enum Backend {
+            S3Backend { bucket: String },
+            GcsBackend { project: String },
+        }
+
+        #[derive(Debug, Deserialize)]
+        struct Config {
+            backends: Vec<Backend>,
+        }
+
+        let config = rpkl::from_str::<Config>(
+            r#"
+            abstract class Backend {}
+            class S3Backend extends Backend { bucket: String }
+            class GcsBackend extends Backend { project: String }
+
+            backends: Listing<Backend> = new {
+                new S3Backend { bucket = "logs" }
+                new GcsBackend { project = "prod" }
+            }
+            "#,
+        )
+        .unwrap();
+
+        assert_eq!(
+            config.backends,
+            vec![
+                Backend::S3Backend {
+                    bucket: "logs".into()
+                },
+                Backend::GcsBackend {
+                    project: "prod".into()
+                }
+            ]
+        );
+    }
+
+    #[test]
+    fn mappings_with_int_keys() {
+        #[derive(Debug, Deserialize)]
+        struct Config {
+            ports: std::collections::BTreeMap<u16, String>,
+            priorities: HashMap<i64, Vec<String>>,
+        }
+
+        let config = rpkl::from_str::<Config>(
+            r#"
+            ports: Mapping<Int, String> = new {
+                [80] = "http"
+                [443] = "https"
+            }
+            priorities: Map<Int, List<String>> = Map(-1, List("low"), 10, List("high"))
+            "#,
+        )
+        .unwrap();
+
+        assert_eq!(config.ports[&80], "http");
+        assert_eq!(config.ports[&443], "https");
+        assert_eq!(config.priorities[&-1], vec!["low".to_string()]);
+    }
+
+    // builds a `file://` uri from the path directly, which isn't valid on windows
+    #[cfg(not(target_os = "windows"))]
+    #[test]
+    fn evaluate_text_with_relative_import() {
+        let base = PathBuf::from(env!("CARGO_MANIFEST_DIR"))
+            .join("tests")
+            .join("pkl")
+            .join("text.pkl");
+        let uri = format!("file://{}", base.display());
+
+        let mut evaluator = rpkl::api::Evaluator::new().unwrap();
+        let pkl_mod = evaluator
+            .evaluate_text(
+                r#"
+                import "database.pkl"
+                username = database.database.username
+                "#,
+                uri,
+            )
+            .unwrap();
+
+        assert_eq!(pkl_mod.module_name(), "text");
+    }
+
+    #[test]
+    fn evaluate_expression() {
+        #[derive(Debug, Deserialize)]
+        struct Database {
+            username: String,
+            password: String,
+        }
+
+        let path = PathBuf::from(env!("CARGO_MANIFEST_DIR"))
+            .join("tests")
+            .join("pkl")
+            .join("database.pkl");
+
+        let database = rpkl::from_expression::<Database>(&path, "database").unwrap();
+        assert_eq!(database.username, "admin");
+        assert_eq!(database.password, "secret");
+
+        let mut evaluator = rpkl::api::Evaluator::new().unwrap();
+        let port = evaluator.evaluate_expression(&path, "port + 1").unwrap();
+        assert!(port.is_u64());
+
+        let rendered = evaluator.evaluate_expression(&path, "output.text").unwrap();
+        assert!(rendered.as_str().unwrap().contains("pet_name = \"Doggo\""));
+    }
+
+    #[test]
+    fn evaluator_manager() {
+        let manager = rpkl::api::EvaluatorManager::new().unwrap();
+        let mut staging = manager
+            .new_evaluator(EvaluatorOptions::default().property("env", "staging"))
+            .unwrap();
+        let mut prod = manager
+            .new_evaluator(EvaluatorOptions::default().property("env", "prod"))
+            .unwrap();
+        assert_ne!(staging.id(), prod.id());
+
+        use rpkl::pkl::IntoPklMap;
+
+        let source = r#"env = read("prop:env")"#;
+        let staging_env = staging
+            .evaluate_text(source, "repl:text")
+            .unwrap()
+            .into_pkl_map();
+        let prod_env = prod
+            .evaluate_text(source, "repl:text")
+            .unwrap()
+            .into_pkl_map();
+
+        assert_eq!(staging_env["env"].as_str(), Some("staging"));
+        assert_eq!(prod_env["env"].as_str(), Some("prod"));
+
+        // the server keeps running for the remaining evaluators
+        drop(staging);
+        assert!(prod.evaluate_text(source, "repl:text").is_ok());
+    }
+
+    #[test]
+    fn project_dir() {
+        #[derive(Deserialize)]
+        struct Config {
+            greeting: String,
+            env: String,
+            region: String,
+        }
+
+        let project = PathBuf::from(env!("CARGO_MANIFEST_DIR"))
+            .join("tests")
+            .join("pkl")
+            .join("project")
+            .join("app");
+        let options = EvaluatorOptions::default()
+            .project_dir(&project)
+            .property("env", "prod");
+        let config: Config =
+            rpkl::from_config_with_options(project.join("config.pkl"), options).unwrap();
+
+        assert_eq!(config.greeting, "hello");
+        // properties set on the evaluator override the project's `externalProperties`
+        assert_eq!(config.env, "prod");
+        assert_eq!(config.region, "eu");
+    }
+
+    #[cfg(feature = "async")]
+    #[tokio::test]
+    async fn async_evaluator() {
+        let manager = rpkl::api::AsyncEvaluatorManager::new().unwrap();
+        let evaluator = manager
+            .new_evaluator(EvaluatorOptions::default().property("env", "staging"))
+            .await
+            .unwrap();
+
+        let (env, joined) = tokio::join!(
+            evaluator.evaluate_expression_uri("repl:text", r#"read("prop:env")"#),
+            evaluator.evaluate_expression_uri("repl:text", r#""a" + "b""#),
+        );
+        assert_eq!(env.unwrap().as_str(), Some("staging"));
+        assert_eq!(joined.unwrap().as_str(), Some("ab"));
+    }
+
+    #[cfg(feature = "watch")]
+    #[test]
+    fn watch_reloads() {
+        use rpkl::watch::{ConfigWatcher, WatchEvent, WatchOptions};
+        use std::time::Duration;
+
+        #[derive(Deserialize)]
+        struct Config {
+            port: u16,
+        }
+
+        let dir = PathBuf::from(env!("CARGO_TARGET_TMPDIR")).join("watch_reloads");
+        std::fs::create_dir_all(&dir).unwrap();
+        std::fs::write(dir.join("base.pkl"), "port = 8080\n").unwrap();
+        std::fs::write(
+            dir.join("config.pkl"),
+            "import \"base.pkl\"\n\nport = base.port\n",
+        )
+        .unwrap();
+
+        let options = WatchOptions::new().debounce(Duration::from_millis(50));
+        let watcher =
+            ConfigWatcher::<Config>::new_from_options(dir.join("config.pkl"), options).unwrap();
+        let events = watcher.subscribe();
+        assert_eq!(watcher.get().port, 8080);
+        assert!(
+            watcher
+                .watched_files()
+                .contains(&dir.join("base.pkl").canonicalize().unwrap())
+        );
+
+        std::fs::write(dir.join("base.pkl"), "port = 9090\n").unwrap();
+        let event = events.recv_timeout(Duration::from_secs(30)).unwrap();
+        assert!(matches!(event, WatchEvent::Reloaded(config) if config.port == 9090));
+
+        std::fs::write(dir.join("base.pkl"), "port = \"not a port\"\n").unwrap();
+        let event = events.recv_timeout(Duration::from_secs(30)).unwrap();
+        assert!(matches!(event, WatchEvent::Failed(_)));
+        assert_eq!(watcher.get().port, 9090);
+    }
+
+    #[test]
+    fn layered_config() {
+        #[derive(Deserialize)]
+        struct Config {
+            host: String,
+            port: u16,
+            tags: Vec<String>,
+            database: Database,
+        }
+
+        #[derive(Deserialize)]
+        struct Database {
+            host: String,
+            port: u16,
+        }
+
+        let dir = PathBuf::from(env!("CARGO_MANIFEST_DIR"))
+            .join("tests")
+            .join("pkl")
+            .join("layered");
+        let layered = rpkl::ConfigBuilder::new()
+            .module(dir.join("base.pkl"))
+            .module(dir.join("prod.pkl"))
+            .text("cli", "port = 9090")
+            .overrides(["database.port=6543"])
+            .list_merge_at("tags", rpkl::ListMerge::Append)
+            .evaluate()
+            .unwrap();
+
+        let config: Config = layered.deserialize().unwrap();
+        assert_eq!(config.host, "app.example.com");
+        assert_eq!(config.port, 9090);
+        assert_eq!(config.tags, ["base", "prod"]);
+        assert_eq!(config.database.host, "db.example.com");
+        assert_eq!(config.database.port, 6543);
+
+        assert!(layered.origin("host").unwrap().ends_with("prod.pkl"));
+        assert_eq!(layered.origin("port"), Some("cli"));
+        assert_eq!(
+            layered.origin("database.port"),
+            Some("override database.port")
+        );
+    }
+
+    #[test]
+    fn evaluate_with_overrides() {
+        use rpkl::pkl::IntoPklMap;
+
+        let path = PathBuf::from(env!("CARGO_MANIFEST_DIR"))
+            .join("tests")
+            .join("pkl")
+            .join("overrides.pkl");
+        let mut evaluator = rpkl::api::Evaluator::new().unwrap();
+
+        let port = rpkl::config::parse_override("database.port=5433").unwrap();
+        let config = evaluator
+            .evaluate_module_with_overrides(&path, [port])
+            .unwrap()
+            .into_pkl_map();
+        let database = config["database"].as_map().unwrap();
+        assert_eq!(database["host"].as_str(), Some("localhost"));
+        assert_eq!(database["url"].as_str(), Some("postgres://localhost:5433"));
+
+        let port = rpkl::config::parse_override("database.port=70000").unwrap();
+        let err = evaluator
+            .evaluate_module_with_overrides(&path, [port])
+            .unwrap_err();
+        assert!(matches!(err, rpkl::Error::PklServerError(_)));
+
+        // indexes aren't written as member names
+        let err = evaluator
+            .evaluate_module_with_overrides(&path, [("database[0].port", rpkl::pkl_value!(1))])
+            .unwrap_err();
+        assert!(matches!(err, rpkl::Error::Message(_)));
+    }
+
+    #[test]
+    fn evaluate_with_typed_overrides() {
+        use rpkl::pkl::IntoPklMap;
+
+        let path = PathBuf::from(env!("CARGO_MANIFEST_DIR"))
+            .join("tests")
+            .join("pkl")
+            .join("overrides.pkl");
+        let mut evaluator = rpkl::api::Evaluator::new().unwrap();
+
+        let overrides = [
+            rpkl::config::parse_override("ratio=1").unwrap(),
+            rpkl::config::parse_override("timeout=5.s").unwrap(),
+            ("tags".to_string(), rpkl::pkl_value!(["a", "b"])),
+        ];
+        let config = evaluator
+            .evaluate_module_with_overrides(&path, overrides)
+            .unwrap()
+            .into_pkl_map();
+        assert_eq!(config["ratio"].as_f64(), Some(1.0));
+        assert_eq!(
+            config["timeout"].as_duration().map(|d| d.as_secs_f64()),
+            Some(5.0)
+        );
+        assert_eq!(config["tags"], rpkl::pkl_value!(["a", "b"]));
+
+        // members of a map value are written with their declared types too
+        let database = (
+            "database".to_string(),
+            rpkl::pkl_value!({ "timeout": "5.s" }),
+        );
+        let config = evaluator
+            .evaluate_module_with_overrides(&path, [database])
+            .unwrap()
+            .into_pkl_map();
+        let timeout = config["database"].as_map().unwrap()["timeout"].as_duration();
+        assert_eq!(timeout.map(|d| d.as_secs_f64()), Some(5.0));
+    }
+
+    #[test]
+    fn log_messages() {
+        use rpkl::api::logger::{LogLevel, LogMessage};
+        use std::sync::{Arc, Mutex};
+
+        let logged = Arc::new(Mutex::new(Vec::<LogMessage>::new()));
+        let sink = logged.clone();
+        let options = EvaluatorOptions::default()
+            .logger(move |msg: &LogMessage| sink.lock().unwrap().push(msg.clone()));
+
+        let value = rpkl::from_str_with_options::<std::collections::HashMap<String, i64>>(
+            "value = trace(1 + 1)",
+            options,
+        )
+        .unwrap();
+
+        assert_eq!(value.get("value"), Some(&2));
+
+        let logged = logged.lock().unwrap();
+        assert_eq!(logged.len(), 1);
+        assert_eq!(logged[0].level, LogLevel::Trace);
+        assert!(logged[0].message.contains("1 + 1"));
+    }
+
+    #[test]
+    fn evaluation_error() {
+        let err = rpkl::from_str::<std::collections::HashMap<String, String>>("name = usrname")
+            .unwrap_err();
+
+        let rpkl::Error::PklServerError(err) = err else {
+            panic!("expected PklServerError, got {err:?}");
+        };
+
+        assert!(err.message().contains("usrname"));
+        let location = err.location().expect("error should have a location");
+        assert_eq!(location.uri, "repl:text");
+        assert_eq!(location.line, Some(1));
+        assert_eq!(location.column, Some(8));
+    }
+
     // #[test]
     // TODO: figure out a better way to run this test
     #[allow(dead_code)]
@@ -268,8 +749,8 @@ mod non_primitive_values {
             duration_h: std::time::Duration,
             duration_d: std::time::Duration,
 
-            // TODO: support negative durations
-            // negative_durzation: std::time::Duration,
+            negative_duration: rpkl::value::Duration,
+            long_duration: rpkl::value::Duration,
             floating_duration: std::time::Duration,
             floating_duration_ms: std::time::Duration,
         }
@@ -282,6 +763,13 @@ mod non_primitive_values {
             config.floating_duration_ms,
             std::time::Duration::from_nanos(2_500_000) // 2.5ms = 2,500,000 ns
         );
+        assert_eq!(config.negative_duration.value(), -5.0);
+        assert_eq!(config.negative_duration.as_nanos(), Some(-300_000_000_000));
+        // the unit is kept as written
+        assert_eq!(
+            config.long_duration.unit(),
+            rpkl::value::DurationUnit::Minutes
+        );
 
         println!("config: {:#?}", config);
 